@@ -1,4 +1,4 @@
-import init, { Swarmalator } from "wasm-swarmalators";
+import init, { Colormap, Swarmalator } from "wasm-swarmalators";
 import { memory } from "wasm-swarmalators/wasm-swamalators_bg";
 // import { memory } from "my-create/my_crate_bg";
 // Don't worry if vscode told you can't find my-crate
@@ -52,67 +52,7 @@ function mapRange(num, inMin, inMax, outMin, outMax) {
   return ((num - inMin) * (outMax - outMin)) / (inMax - inMin) + outMin;
 }
 
-function radiansToHSV(angleInRadians) {
-  // Convert radians to degrees
-  let degrees = angleInRadians * (180 / Math.PI);
-
-  // Normalize degrees to [0, 360)
-  let hue = ((degrees % 360) + 360) % 360;
-
-  // Set saturation and value to maximum
-  let saturation = 1;
-  let value = 1;
-
-  return { hue, saturation, value };
-}
-
-function HSVtoRGB(h, s, v) {
-  let c = v * s;
-  let x = c * (1 - Math.abs(((h / 60) % 2) - 1));
-  let m = v - c;
-  let r, g, b;
-
-  if (0 <= h && h < 60) {
-    r = c;
-    g = x;
-    b = 0;
-  } else if (60 <= h && h < 120) {
-    r = x;
-    g = c;
-    b = 0;
-  } else if (120 <= h && h < 180) {
-    r = 0;
-    g = c;
-    b = x;
-  } else if (180 <= h && h < 240) {
-    r = 0;
-    g = x;
-    b = c;
-  } else if (240 <= h && h < 300) {
-    r = x;
-    g = 0;
-    b = c;
-  } else {
-    r = c;
-    g = 0;
-    b = x;
-  }
-
-  r = Math.round((r + m) * 255);
-  g = Math.round((g + m) * 255);
-  b = Math.round((b + m) * 255);
-
-  return { r, g, b };
-}
-
-function HSVtoCanvasFillStyle(angleInRadians) {
-  let hsv = radiansToHSV(angleInRadians);
-  let rgb = HSVtoRGB(hsv.hue, hsv.saturation, hsv.value);
-
-  return `rgb(${rgb.r}, ${rgb.g}, ${rgb.b})`;
-}
-
-init().then(({ memory }) => {
+init().then(() => {
   const agents = 200;
 
   // Create random positions
@@ -185,6 +125,31 @@ init().then(({ memory }) => {
 
   ctx.scale(scale, scale);
 
+  // Click to spawn an agent, right-click to delete one
+  const toWorld = (event: MouseEvent) => {
+    const rect = canvas.getBoundingClientRect();
+    const px = event.clientX - rect.left;
+    const py = event.clientY - rect.top;
+    return [
+      mapRange(px, 0.1 * size, 0.9 * size, -3, 3),
+      mapRange(py, 0.9 * size, 0.1 * size, -3, 3),
+    ];
+  };
+
+  canvas.addEventListener("click", (event) => {
+    const [x, y] = toWorld(event);
+    swarmalator.add_agent(x, y, Math.random() * 2 * Math.PI, 0);
+  });
+
+  canvas.addEventListener("contextmenu", (event) => {
+    event.preventDefault();
+    const [x, y] = toWorld(event);
+    const nearby = swarmalator.agents_within(x, y, 0.2);
+    if (nearby.length > 0) {
+      swarmalator.remove_agent(nearby[0]);
+    }
+  });
+
   let count = 0;
 
   function updateAndDraw() {
@@ -193,31 +158,20 @@ init().then(({ memory }) => {
 
     count += 1;
 
-    const positionsPtr = swarmalator.positions();
-    const positions = new Float64Array(memory.buffer, positionsPtr, agents * 2);
-
-    const velocitiesPtr = swarmalator.velocities();
-    const velocities = new Float64Array(
-      memory.buffer,
-      velocitiesPtr,
-      agents * 2
-    );
-
-    const phasesPtr = swarmalator.phases();
-    const phases = new Float64Array(memory.buffer, phasesPtr, agents);
+    const positions = swarmalator.positions_view();
+    const velocities = swarmalator.velocities_view();
+    const colors = swarmalator.colors(Colormap.Hsv);
 
     ctx.clearRect(0, 0, canvas.width, canvas.height);
 
-    for (let i = 0; i < agents; i++) {
+    for (let i = 0; i < swarmalator.agents(); i++) {
       const x = mapRange(positions[i * 2], -3, 3, 0.1 * size, 0.9 * size);
       const y = mapRange(positions[i * 2 + 1], -3, 3, 0.9 * size, 0.1 * size);
 
       ctx.beginPath();
       ctx.arc(x, y, 5, 0, 2 * Math.PI);
 
-      const phase = phases[i];
-
-      ctx.fillStyle = HSVtoCanvasFillStyle(phase);
+      ctx.fillStyle = `rgb(${colors[i * 4]}, ${colors[i * 4 + 1]}, ${colors[i * 4 + 2]})`;
       ctx.fill();
     }
 
@@ -0,0 +1,29 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use wasm_swarmalators::{IntegratorKind, Swarmalator};
+
+/// Times a single `update` for a range of swarm sizes and the main integration paths.
+fn step(c: &mut Criterion) {
+    let mut group = c.benchmark_group("update");
+    for agents in [100, 500, 1000] {
+        group.bench_with_input(BenchmarkId::new("all_to_all", agents), &agents, |b, &n| {
+            let mut system = Swarmalator::random(n, 0, 1.0, 0.5, 0.1).unwrap();
+            b.iter(|| system.update(0.01).unwrap());
+        });
+
+        group.bench_with_input(BenchmarkId::new("cutoff", agents), &agents, |b, &n| {
+            let mut system = Swarmalator::random(n, 0, 1.0, 0.5, 0.1).unwrap();
+            system.set_cutoff(Some(0.3)).unwrap();
+            b.iter(|| system.update(0.01).unwrap());
+        });
+
+        group.bench_with_input(BenchmarkId::new("rk4", agents), &agents, |b, &n| {
+            let mut system = Swarmalator::random(n, 0, 1.0, 0.5, 0.1).unwrap();
+            system.set_integrator(IntegratorKind::Rk4);
+            b.iter(|| system.update(0.01).unwrap());
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, step);
+criterion_main!(benches);
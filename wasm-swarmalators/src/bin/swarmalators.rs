@@ -0,0 +1,255 @@
+//! Headless native runner for the same simulation core the WASM build exposes.
+//!
+//! Build with `cargo run --release --features cli --bin swarmalators -- --help`.
+
+#![allow(non_snake_case)]
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::process;
+
+use wasm_swarmalators::{Preset, RecordingFormat, Swarmalator};
+
+const USAGE: &str = "\
+Usage: swarmalators [OPTIONS]
+
+Integrates a swarmalator system natively and writes its trajectory, or the final
+order parameters of a parameter sweep, to a file or stdout.
+
+Options:
+  --agents <N>        Number of agents [default: 500]
+  --seed <SEED>       Seed for the initial state [default: 0]
+  --dim <2|3>         Number of spatial dimensions [default: 2]
+  --K <K>             Phase coupling [default: 1]
+  --J <J>             Spatial-phase coupling [default: 0.5]
+  --spread <W>        Half-width of the natural frequencies [default: 0]
+  --preset <NAME>     Start from a canonical state instead: static_sync, static_async,
+                      static_phase_wave, splintered_phase_wave or active_phase_wave
+  --config <PATH>     Take every parameter, and the number of agents and dimensions,
+                      from a configuration saved by `to_config_json` instead
+  --dt <DT>           Time step [default: 0.1]
+  --steps <N>         Number of steps [default: 1000]
+  --every <N>         Record a frame every N steps [default: 10]
+  --format <FORMAT>   Trajectory format: csv, json or binary [default: csv]
+  --sweep <P=A:B:N>   Sweep K or J over N values from A to B, writing one CSV row of
+                      final order parameters per value instead of a trajectory
+  --out <PATH>        Output file [default: stdout]
+  --help              Print this message
+";
+
+fn main() {
+    let options = parse_options().unwrap_or_else(|message| fail(&message));
+
+    let output = match options.get("sweep") {
+        Some(sweep) => run_sweep(&options, sweep),
+        None => run_trajectory(&options),
+    }
+    .unwrap_or_else(|message| fail(&message));
+
+    let written = match options.get("out") {
+        Some(path) => fs::write(path, output),
+        None => io::stdout().write_all(&output),
+    };
+    if let Err(e) = written {
+        fail(&format!("Couldn't write output: {}", e));
+    }
+}
+
+/// Integrates one system and returns its recorded trajectory.
+fn run_trajectory(options: &HashMap<String, String>) -> Result<Vec<u8>, String> {
+    let steps: usize = parse(options, "steps", 1000)?;
+    let every: usize = parse(options, "every", 10)?;
+    if every == 0 {
+        return Err("--every must be positive".to_string());
+    }
+    let format = match options.get("format").map(String::as_str) {
+        None | Some("csv") => RecordingFormat::Csv,
+        Some("json") => RecordingFormat::Json,
+        Some("binary") => RecordingFormat::Binary,
+        Some(other) => return Err(format!("Unknown format '{}'", other)),
+    };
+
+    let mut system = build(options, None)?;
+    system
+        .start_recording(every, Some(steps / every + 1))
+        .map_err(|e| format!("Couldn't start recording: {}", e))?;
+    system
+        .step_many(steps, parse_finite(options, "dt", 0.1)?)
+        .map_err(|e| format!("Integration failed: {}", e))?;
+
+    let d = system.diagnostics();
+    eprintln!(
+        "t = {}: R = {:.4}, S = {:.4}, mean radius = {:.4}, mean speed = {:.4}",
+        system.time(),
+        d.phase_coherence,
+        d.phase_space_correlation,
+        d.mean_radius,
+        d.mean_speed
+    );
+
+    Ok(system.export_recording(format))
+}
+
+/// Integrates one system per swept value and returns a CSV of the final order
+/// parameters.
+fn run_sweep(options: &HashMap<String, String>, sweep: &str) -> Result<Vec<u8>, String> {
+    let invalid = || format!("Invalid sweep '{}', expected K=A:B:N or J=A:B:N", sweep);
+    let (parameter, range) = sweep.split_once('=').ok_or_else(invalid)?;
+    if parameter != "K" && parameter != "J" {
+        return Err(invalid());
+    }
+    let bounds: Vec<&str> = range.split(':').collect();
+    let [start, end, count] = bounds[..] else {
+        return Err(invalid());
+    };
+    let (start, end): (f64, f64) = (
+        start.parse().map_err(|_| invalid())?,
+        end.parse().map_err(|_| invalid())?,
+    );
+    let count: usize = count.parse().map_err(|_| invalid())?;
+    if !start.is_finite() || !end.is_finite() || count == 0 {
+        return Err(invalid());
+    }
+
+    let steps: usize = parse(options, "steps", 1000)?;
+    let dt = parse_finite(options, "dt", 0.1)?;
+
+    let mut csv = String::from("K,J,phase_coherence,s_plus,s_minus,mean_radius,mean_speed\n");
+    for n in 0..count {
+        let value = if count == 1 {
+            start
+        } else {
+            start + (end - start) * n as f64 / (count - 1) as f64
+        };
+
+        let mut system = build(options, Some((parameter, value)))?;
+        system
+            .step_many(steps, dt)
+            .map_err(|e| format!("Integration failed: {}", e))?;
+
+        let config = system.config();
+        let d = system.diagnostics();
+        csv.push_str(&format!(
+            "{},{},{},{},{},{},{}\n",
+            config[3],
+            config[4],
+            d.phase_coherence,
+            d.s_plus,
+            d.s_minus,
+            d.mean_radius,
+            d.mean_speed
+        ));
+        eprintln!("{} = {}: R = {:.4}", parameter, value, d.phase_coherence);
+    }
+
+    Ok(csv.into_bytes())
+}
+
+/// Creates the system described by the options, with `K` or `J` overridden by a sweep.
+fn build(
+    options: &HashMap<String, String>,
+    sweep: Option<(&str, f64)>,
+) -> Result<Swarmalator, String> {
+    let agents: usize = parse(options, "agents", 500)?;
+    let seed: u64 = parse(options, "seed", 0)?;
+    let dim: usize = parse(options, "dim", 2)?;
+    let mut K = parse_finite(options, "K", 1.0)?;
+    let mut J = parse_finite(options, "J", 0.5)?;
+    let spread = parse_finite(options, "spread", 0.0)?;
+
+    let preset = match options.get("preset").map(String::as_str) {
+        None => None,
+        Some("static_sync") => Some(Preset::StaticSync),
+        Some("static_async") => Some(Preset::StaticAsync),
+        Some("static_phase_wave") => Some(Preset::StaticPhaseWave),
+        Some("splintered_phase_wave") => Some(Preset::SplinteredPhaseWave),
+        Some("active_phase_wave") => Some(Preset::ActivePhaseWave),
+        Some(other) => return Err(format!("Unknown preset '{}'", other)),
+    };
+    if let Some(preset) = preset {
+        (K, J) = preset.couplings();
+    }
+    match sweep {
+        Some(("K", value)) => K = value,
+        Some(("J", value)) => J = value,
+        _ => {}
+    }
+
+    if let Some(path) = options.get("config") {
+        if preset.is_some() {
+            return Err("--config and --preset can't be combined".to_string());
+        }
+        let json = fs::read_to_string(path)
+            .map_err(|e| format!("Couldn't read configuration '{}': {}", path, e))?;
+        let mut system = Swarmalator::from_config(&json, seed, spread)?;
+
+        match sweep {
+            Some(("K", value)) => system.set_K(value),
+            Some(("J", value)) => system.set_J(value),
+            _ => Ok(()),
+        }
+        .map_err(|e| e.to_string())?;
+
+        return Ok(system);
+    }
+
+    let system = match dim {
+        2 => Swarmalator::random(agents, seed, K, J, spread),
+        3 => Swarmalator::random_3d(agents, seed, K, J, spread),
+        _ => return Err("--dim must be 2 or 3".to_string()),
+    };
+
+    system.map_err(|e| e.to_string())
+}
+
+/// Reads `--key value` pairs from the command line.
+fn parse_options() -> Result<HashMap<String, String>, String> {
+    let mut options = HashMap::new();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--help" || arg == "-h" {
+            print!("{}", USAGE);
+            process::exit(0);
+        }
+
+        let key = arg
+            .strip_prefix("--")
+            .ok_or_else(|| format!("Unexpected argument '{}'", arg))?;
+        let value = args
+            .next()
+            .ok_or_else(|| format!("Missing value for --{}", key))?;
+        options.insert(key.to_string(), value);
+    }
+
+    Ok(options)
+}
+
+/// Parses option `key`, or returns `default` if it wasn't given.
+fn parse<T: std::str::FromStr>(
+    options: &HashMap<String, String>,
+    key: &str,
+    default: T,
+) -> Result<T, String> {
+    match options.get(key) {
+        Some(value) => value
+            .parse()
+            .map_err(|_| format!("Invalid value '{}' for --{}", value, key)),
+        None => Ok(default),
+    }
+}
+
+/// Parses option `key` as a finite number, or returns `default` if it wasn't given.
+fn parse_finite(options: &HashMap<String, String>, key: &str, default: f64) -> Result<f64, String> {
+    let value = parse(options, key, default)?;
+    if !value.is_finite() {
+        return Err(format!("--{} must be finite", key));
+    }
+
+    Ok(value)
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("error: {}\n\n{}", message, USAGE);
+    process::exit(2);
+}
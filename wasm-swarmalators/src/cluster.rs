@@ -0,0 +1,104 @@
+/// Labels agents with DBSCAN.
+///
+/// `neighbours(i, out)` must push into `out` every agent within the
+/// neighbourhood radius of agent `i` (including `i` itself). Returns the
+/// cluster index of every agent, or `None` for noise.
+pub fn dbscan<F: FnMut(usize, &mut Vec<usize>)>(
+    agents: usize,
+    min_pts: usize,
+    mut neighbours: F,
+) -> Vec<Option<usize>> {
+    let mut labels: Vec<Option<usize>> = vec![None; agents];
+    let mut visited = vec![false; agents];
+    let mut clusters = 0;
+
+    let mut found = Vec::new();
+    let mut queue = Vec::new();
+
+    for i in 0..agents {
+        if visited[i] {
+            continue;
+        }
+        visited[i] = true;
+
+        found.clear();
+        neighbours(i, &mut found);
+        if found.len() < min_pts {
+            continue;
+        }
+
+        // i is a core point so grow a new cluster from it
+        let cluster = clusters;
+        clusters += 1;
+        labels[i] = Some(cluster);
+        queue.clear();
+        queue.extend_from_slice(&found);
+
+        while let Some(j) = queue.pop() {
+            if labels[j].is_none() {
+                labels[j] = Some(cluster);
+            }
+            if visited[j] {
+                continue;
+            }
+            visited[j] = true;
+
+            found.clear();
+            neighbours(j, &mut found);
+            if found.len() >= min_pts {
+                queue.extend_from_slice(&found);
+            }
+        }
+    }
+
+    labels
+}
+
+/// Disjoint-set forest with path halving and union by size.
+pub struct UnionFind {
+    parents: Vec<usize>,
+    sizes: Vec<usize>,
+}
+
+impl UnionFind {
+    /// Creates `n` singleton sets.
+    pub fn new(n: usize) -> UnionFind {
+        UnionFind {
+            parents: (0..n).collect(),
+            sizes: vec![1; n],
+        }
+    }
+
+    /// Returns the representative of the set containing `i`.
+    pub fn find(&mut self, mut i: usize) -> usize {
+        while self.parents[i] != i {
+            self.parents[i] = self.parents[self.parents[i]];
+            i = self.parents[i];
+        }
+        i
+    }
+
+    /// Merges the sets containing `a` and `b`.
+    pub fn union(&mut self, a: usize, b: usize) {
+        let (mut a, mut b) = (self.find(a), self.find(b));
+        if a == b {
+            return;
+        }
+        if self.sizes[a] < self.sizes[b] {
+            std::mem::swap(&mut a, &mut b);
+        }
+        self.parents[b] = a;
+        self.sizes[a] += self.sizes[b];
+    }
+
+    /// Returns the size of the largest set, or 0 if there are no elements.
+    pub fn largest(&mut self) -> usize {
+        (0..self.parents.len())
+            .map(|i| {
+                let root = self.find(i);
+                self.sizes[root]
+            })
+            .max()
+            .unwrap_or(0)
+    }
+}
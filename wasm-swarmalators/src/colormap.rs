@@ -0,0 +1,101 @@
+use std::f64::consts::PI;
+
+use wasm_bindgen::prelude::*;
+
+/// Cyclic colormap from phase to colour, passed to `Swarmalator::colors`.
+///
+/// Every map wraps around, so phases either side of `0 ≡ 2π` get the same colour.
+///
+/// - `Hsv`: Fully saturated hue wheel, red at `0`, green at `2π/3` and blue at
+///   `4π/3`.
+/// - `Twilight`: Light at `0`, through blue to dark at `π` and back through red.
+///   Interpolated from control points of matplotlib's `twilight`, so close to it
+///   but not identical.
+/// - `ViridisCyclic`: Viridis from dark at `0` to yellow at `π`, mirrored back to
+///   dark at `2π`. Phases `φ` and `2π - φ` share a colour.
+#[wasm_bindgen]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Colormap {
+    Hsv,
+    Twilight,
+    ViridisCyclic,
+}
+
+/// `twilight` at eighths of a turn, from `0` up to but excluding `2π`.
+const TWILIGHT: [[f64; 3]; 8] = [
+    [0.886, 0.850, 0.888],
+    [0.659, 0.729, 0.808],
+    [0.384, 0.529, 0.761],
+    [0.333, 0.259, 0.604],
+    [0.186, 0.073, 0.232],
+    [0.455, 0.118, 0.282],
+    [0.690, 0.322, 0.271],
+    [0.788, 0.616, 0.541],
+];
+
+/// Polynomial fit of `viridis` over `[0, 1]`, coefficients lowest order first.
+const VIRIDIS: [[f64; 3]; 7] = [
+    [0.277727, 0.005407, 0.334100],
+    [0.105093, 1.404614, 1.384590],
+    [-0.330862, 0.214848, 0.095095],
+    [-4.634230, -5.799101, -19.332441],
+    [6.228270, 14.179933, 56.690553],
+    [4.776385, -13.745145, -65.353033],
+    [-5.435456, 4.645853, 26.312435],
+];
+
+impl Colormap {
+    /// Returns the colour of `phase` as opaque RGBA bytes.
+    pub fn rgba(self, phase: f64) -> [u8; 4] {
+        // Fraction of a turn in [0, 1)
+        let t = phase.rem_euclid(2.0 * PI) / (2.0 * PI);
+        let rgb = match self {
+            Colormap::Hsv => hue(t),
+            Colormap::Twilight => twilight(t),
+            Colormap::ViridisCyclic => viridis(1.0 - (1.0 - 2.0 * t).abs()),
+        };
+
+        let byte = |c: f64| (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+        [byte(rgb[0]), byte(rgb[1]), byte(rgb[2]), 255]
+    }
+}
+
+/// Fully saturated, full value colour of hue `t` turns.
+fn hue(t: f64) -> [f64; 3] {
+    let h = t * 6.0;
+    let x = 1.0 - (h % 2.0 - 1.0).abs();
+    match h as usize {
+        0 => [1.0, x, 0.0],
+        1 => [x, 1.0, 0.0],
+        2 => [0.0, 1.0, x],
+        3 => [0.0, x, 1.0],
+        4 => [x, 0.0, 1.0],
+        _ => [1.0, 0.0, x],
+    }
+}
+
+/// Linearly interpolates `TWILIGHT` at `t` turns.
+fn twilight(t: f64) -> [f64; 3] {
+    let position = t * TWILIGHT.len() as f64;
+    let below = (position as usize).min(TWILIGHT.len() - 1);
+    let above = (below + 1) % TWILIGHT.len();
+    let weight = position - below as f64;
+
+    let mut rgb = [0.0; 3];
+    for (k, c) in rgb.iter_mut().enumerate() {
+        *c = TWILIGHT[below][k] * (1.0 - weight) + TWILIGHT[above][k] * weight;
+    }
+    rgb
+}
+
+/// Evaluates `VIRIDIS` at `t` in `[0, 1]`.
+fn viridis(t: f64) -> [f64; 3] {
+    let mut rgb = [0.0; 3];
+    for (k, c) in rgb.iter_mut().enumerate() {
+        *c = VIRIDIS
+            .iter()
+            .rev()
+            .fold(0.0, |sum, coefficients| sum * t + coefficients[k]);
+    }
+    rgb
+}
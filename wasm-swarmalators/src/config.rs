@@ -0,0 +1,389 @@
+use rand::SeedableRng;
+use rand_chacha::ChaCha12Rng;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+use crate::collision::Collisions;
+use crate::schedule::Schedule;
+use crate::{Boundary, Error, IntegratorKind, NanPolicy, Noise, Scheme, Swarmalator};
+
+/// Version written by `to_config_json`. Bumped whenever a field changes meaning or
+/// becomes required, so older readers reject configurations they would misread.
+const CONFIG_VERSION: u32 = 1;
+
+/// The parameters of a system, without any of its dynamical state.
+///
+/// Every field of version 1 is required, so a configuration never silently falls
+/// back to a default, and unknown fields are rejected so typos don't go unnoticed.
+/// Fields added since default to the behaviour from before they existed.
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    version: u32,
+    agents: usize,
+    dim: usize,
+    A: f64,
+    B: f64,
+    K: f64,
+    J: f64,
+    #[serde(default = "crate::default_target_gain")]
+    target_gain: f64,
+    chiral: Option<Vec<f64>>,
+    phase_lag: f64,
+    phase_harmonic: u32,
+    phase_repulsion_coupling: f64,
+    repulsion_exponent: f64,
+    attraction_exponent: f64,
+    phase_coupling_exponent: f64,
+    min_distance: f64,
+    softening: f64,
+    frequency_adaptation: f64,
+    frequency_gradient: (f64, f64),
+    fix_center_of_mass: bool,
+    boundary: BoundaryConfig,
+    noise: Option<NoiseConfig>,
+    integrator: IntegratorConfig,
+    cutoff: Option<f64>,
+    far_field: bool,
+    mean_field: bool,
+    max_speed: Option<f64>,
+    collisions: Option<Collisions>,
+    nan_policy: NanPolicy,
+    max_displacement: Option<f64>,
+    fixed_timestep: Option<f64>,
+    schedules: Vec<Schedule>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BoundaryConfig {
+    kind: Boundary,
+    size: f64,
+    stiffness: f64,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NoiseConfig {
+    position_sigma: f64,
+    phase_sigma: f64,
+    seed: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct IntegratorConfig {
+    kind: IntegratorKind,
+    scheme: Scheme,
+    tolerance: f64,
+}
+
+impl Config {
+    /// Checks every parameter against the ranges its setter enforces.
+    fn validate(&self) -> Result<(), String> {
+        if self.dim != 2 && self.dim != 3 {
+            return Err("Dimension must be 2 or 3".to_string());
+        }
+
+        let finite = [
+            ("A", self.A),
+            ("B", self.B),
+            ("K", self.K),
+            ("J", self.J),
+            ("Target gain", self.target_gain),
+            ("Phase lag", self.phase_lag),
+            ("Phase repulsion coupling", self.phase_repulsion_coupling),
+            ("Repulsion exponent", self.repulsion_exponent),
+            ("Attraction exponent", self.attraction_exponent),
+            ("Phase coupling exponent", self.phase_coupling_exponent),
+            ("Adaptation rate", self.frequency_adaptation),
+            ("Frequency gradient", self.frequency_gradient.0),
+            ("Frequency gradient", self.frequency_gradient.1),
+            ("Stiffness", self.boundary.stiffness),
+        ];
+        for (name, value) in finite {
+            if !value.is_finite() {
+                return Err(format!("{} must be finite", name));
+            }
+        }
+
+        if let Some(chiral) = self.chiral.as_ref() {
+            if chiral.len() != self.agents {
+                return Err("Chiral array must have agents elements".to_string());
+            }
+            if chiral.iter().any(|c| !c.is_finite()) {
+                return Err("Chiral array must only contain finite values".to_string());
+            }
+        }
+
+        if !self.min_distance.is_finite() || self.min_distance <= 0.0 {
+            return Err("Minimum distance must be positive and finite".to_string());
+        }
+
+        if self.phase_harmonic == 0 {
+            return Err("Phase harmonic must be at least 1".to_string());
+        }
+
+        if self.boundary.kind != Boundary::Open
+            && !(self.boundary.size.is_finite() && self.boundary.size > 0.0)
+        {
+            return Err("Arena size must be positive and finite".to_string());
+        }
+
+        if self.integrator.tolerance.is_nan() || self.integrator.tolerance <= 0.0 {
+            return Err("Tolerance must be positive".to_string());
+        }
+
+        if self
+            .cutoff
+            .is_some_and(|cutoff| cutoff.is_nan() || cutoff <= 0.0)
+        {
+            return Err("Cutoff radius must be positive".to_string());
+        }
+
+        if !self.softening.is_finite() || self.softening < 0.0 {
+            return Err("Softening must be non-negative and finite".to_string());
+        }
+
+        if let Some(noise) = self.noise.as_ref() {
+            if !noise.position_sigma.is_finite() || !noise.phase_sigma.is_finite() {
+                return Err("Noise sigmas must be finite".to_string());
+            }
+        }
+
+        if let Some(collisions) = self.collisions {
+            if !collisions.radius.is_finite() || collisions.radius <= 0.0 {
+                return Err("Body radius must be positive and finite".to_string());
+            }
+            if !collisions.stiffness.is_finite() || collisions.stiffness < 0.0 {
+                return Err("Collision stiffness must be non-negative and finite".to_string());
+            }
+        }
+
+        let positive = [
+            ("Maximum displacement", self.max_displacement),
+            ("Fixed time step", self.fixed_timestep),
+        ];
+        for (name, value) in positive {
+            if value.is_some_and(|value| !value.is_finite() || value <= 0.0) {
+                return Err(format!("{} must be positive and finite", name));
+            }
+        }
+
+        if self
+            .max_speed
+            .is_some_and(|max_speed| !max_speed.is_finite() || max_speed < 0.0)
+        {
+            return Err("Maximum speed must be non-negative and finite".to_string());
+        }
+
+        for schedule in &self.schedules {
+            schedule.validate()?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Swarmalator {
+    /// Creates a system with agents as in `random` and the parameters of a
+    /// configuration from `config_to_json`.
+    pub fn from_config(json: &str, seed: u64, spread: f64) -> Result<Swarmalator, String> {
+        let config = parse(json)?;
+        let mut swarmalator = Swarmalator::random_with_dimension(
+            config.dim,
+            config.agents,
+            seed,
+            config.K,
+            config.J,
+            spread,
+        )
+        .map_err(|e| e.to_string())?;
+        swarmalator.apply_config(json)?;
+
+        Ok(swarmalator)
+    }
+
+    /// Returns the parameters of the system as a versioned JSON configuration.
+    pub fn config_to_json(&self) -> String {
+        let config = Config {
+            version: CONFIG_VERSION,
+            agents: self.agents,
+            dim: self.dim,
+            A: self.A,
+            B: self.B,
+            K: self.K,
+            J: self.J,
+            target_gain: self.target_gain,
+            chiral: self.chiral.clone(),
+            phase_lag: self.phase_lag,
+            phase_harmonic: self.phase_harmonic,
+            phase_repulsion_coupling: self.phase_repulsion_coupling,
+            repulsion_exponent: self.repulsion_exponent,
+            attraction_exponent: self.attraction_exponent,
+            phase_coupling_exponent: self.phase_coupling_exponent,
+            min_distance: self.min_distance,
+            softening: self.softening,
+            frequency_adaptation: self.frequency_adaptation,
+            frequency_gradient: self.frequency_gradient,
+            fix_center_of_mass: self.fix_center_of_mass,
+            boundary: BoundaryConfig {
+                kind: self.boundary,
+                size: self.arena_size,
+                stiffness: self.confinement_stiffness,
+            },
+            noise: self.noise.as_ref().map(|noise| NoiseConfig {
+                position_sigma: noise.position_sigma,
+                phase_sigma: noise.phase_sigma,
+                seed: noise.seed,
+            }),
+            integrator: IntegratorConfig {
+                kind: self.integrator,
+                scheme: self.integration_scheme,
+                tolerance: self.tolerance,
+            },
+            cutoff: self.cutoff,
+            far_field: self.far_field,
+            mean_field: self.mean_field,
+            max_speed: self.max_speed,
+            collisions: self.collisions,
+            nan_policy: self.nan_policy,
+            max_displacement: self.max_displacement,
+            fixed_timestep: self.fixed_timestep,
+            schedules: self.schedules.clone(),
+        };
+
+        serde_json::to_string_pretty(&config).expect("Configuration is always serializable")
+    }
+
+    /// Replaces the parameters of the system with a configuration from
+    /// `config_to_json`, leaving the system unchanged on error.
+    pub fn apply_config(&mut self, json: &str) -> Result<(), String> {
+        let config = parse(json)?;
+        if config.agents != self.agents || config.dim != self.dim {
+            return Err(format!(
+                "Configuration is for {} agents in {}D but the system has {} in {}D",
+                config.agents, config.dim, self.agents, self.dim
+            ));
+        }
+
+        self.A = config.A;
+        self.B = config.B;
+        self.K = config.K;
+        self.K_vec = None;
+        self.K_matrix = None;
+        self.J = config.J;
+        self.J_vec = None;
+        self.J_matrix = None;
+        self.target_gain = config.target_gain;
+        self.chiral = config.chiral;
+        self.phase_lag = config.phase_lag;
+        self.phase_harmonic = config.phase_harmonic;
+        self.phase_repulsion_coupling = config.phase_repulsion_coupling;
+        self.repulsion_exponent = config.repulsion_exponent;
+        self.attraction_exponent = config.attraction_exponent;
+        self.phase_coupling_exponent = config.phase_coupling_exponent;
+        self.min_distance = config.min_distance;
+        self.softening = config.softening;
+        self.frequency_adaptation = config.frequency_adaptation;
+        self.frequency_gradient = config.frequency_gradient;
+        self.fix_center_of_mass = config.fix_center_of_mass;
+        self.boundary = config.boundary.kind;
+        self.arena_size = config.boundary.size;
+        self.confinement_stiffness = config.boundary.stiffness;
+        self.noise = config.noise.map(|noise| Noise {
+            position_sigma: noise.position_sigma,
+            phase_sigma: noise.phase_sigma,
+            seed: noise.seed,
+            rng: ChaCha12Rng::seed_from_u64(noise.seed),
+        });
+        self.integrator = config.integrator.kind;
+        self.integration_scheme = config.integrator.scheme;
+        self.tolerance = config.integrator.tolerance;
+        self.cutoff = config.cutoff;
+        self.far_field = config.far_field;
+        self.mean_field = config.mean_field;
+        self.max_speed = config.max_speed;
+        self.collisions = config.collisions;
+        self.nan_policy = config.nan_policy;
+        self.max_displacement = config.max_displacement;
+        self.fixed_timestep = config.fixed_timestep;
+        self.accumulator = 0.0;
+        self.schedules = config.schedules;
+
+        self.enforce_boundary();
+        self.positions_changed();
+
+        Ok(())
+    }
+}
+
+/// Parses and checks a configuration, rejecting versions this build can't read.
+fn parse(json: &str) -> Result<Config, String> {
+    #[derive(Deserialize)]
+    struct Version {
+        version: Option<u32>,
+    }
+
+    // Read the version on its own first, so a newer file reports that rather than
+    // whichever field changed
+    let version = serde_json::from_str::<Version>(json)
+        .map_err(|e| format!("Invalid configuration: {}", e))?
+        .version
+        .ok_or("Configuration has no version")?;
+    if version == 0 || version > CONFIG_VERSION {
+        return Err(format!(
+            "Configuration version {} is not supported, expected at most {}",
+            version, CONFIG_VERSION
+        ));
+    }
+
+    let config: Config =
+        serde_json::from_str(json).map_err(|e| format!("Invalid configuration: {}", e))?;
+    config.validate()?;
+
+    Ok(config)
+}
+
+#[wasm_bindgen]
+impl Swarmalator {
+    /// Saves the parameters of the system as versioned JSON.
+    ///
+    /// Unlike `save_state`, only the parameters are saved: the couplings, chiral
+    /// values, boundary, noise, integrator and schedules, but not the positions,
+    /// phases or natural frequencies. The output is stable and pretty-printed, so
+    /// configurations can be diffed and shared between the demo and the native
+    /// runner's `--config` option.
+    pub fn to_config_json(&self) -> String {
+        self.config_to_json()
+    }
+
+    /// Creates a Swarmalator with agents as in `random` and the parameters of a
+    /// configuration saved by `to_config_json`.
+    ///
+    /// # Arguments
+    /// - `json`: Saved configuration.
+    /// - `seed`: Seed for the random number generator drawing the agents.
+    /// - `spread`: Half-width of the uniform distribution of natural frequencies.
+    ///
+    /// # Errors
+    /// Returns an error if `json` is not a valid configuration, its version is newer
+    /// than this build understands, or any parameter is out of range.
+    pub fn from_config_json(json: &str, seed: u64, spread: f64) -> Result<Swarmalator, Error> {
+        Swarmalator::from_config(json, seed, spread).map_err(Error::from)
+    }
+
+    /// Replaces the parameters of the system with a configuration saved by
+    /// `to_config_json`, keeping the agents where they are.
+    ///
+    /// Per-agent and per-pair `K` and `J` are cleared, as with `set_K` and `set_J`.
+    /// On error the system is left unchanged.
+    /// # Arguments
+    /// - `json`: Saved configuration.
+    /// # Errors
+    /// Returns an error if `json` is not a valid configuration, is for a different
+    /// number of agents or dimensions, or any parameter is out of range.
+    pub fn apply_config_json(&mut self, json: &str) -> Result<(), Error> {
+        self.apply_config(json).map_err(Error::from)
+    }
+}
@@ -0,0 +1,66 @@
+use wasm_bindgen::prelude::*;
+
+use crate::{cos, sin};
+
+/// Order parameters of the system at one instant, returned by
+/// `Swarmalator::diagnostics`.
+///
+/// - `phase_coherence`: Kuramoto order parameter `R = |Σ e^{iφ_j}| / N`.
+/// - `s_plus`, `s_minus`: Rainbow order parameters `S± = |Σ e^{i(θ_j ± φ_j)}| / N`,
+///   where `θ_j` is the angular position of agent `j` in the xy-plane.
+/// - `phase_space_correlation`: `S = max(S+, S-)`, 1 when phase is perfectly
+///   correlated with angular position and 0 when they are independent.
+/// - `mean_radius`: Mean distance of the agents from their centroid.
+/// - `mean_speed`: Mean speed `|v_i|` from the last step.
+///
+/// Together they tell the standard states apart: static sync has `R ≈ 1`, static
+/// async has `R ≈ S ≈ 0`, the static phase wave has `S ≈ 1`, and the splintered
+/// and active phase waves have `0 < S < 1` with agents still moving.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug)]
+pub struct Diagnostics {
+    pub phase_coherence: f64,
+    pub s_plus: f64,
+    pub s_minus: f64,
+    pub phase_space_correlation: f64,
+    pub mean_radius: f64,
+    pub mean_speed: f64,
+}
+
+/// Returns the coherence of the `n`th phase harmonic, `|Σ e^{inφ_j}| / N`, or 0
+/// if there are no phases.
+pub fn harmonic_coherence(phases: &[f64], n: f64) -> f64 {
+    if phases.is_empty() {
+        return 0.0;
+    }
+
+    let sum_cos: f64 = phases.iter().map(|&phase| cos(n * phase)).sum();
+    let sum_sin: f64 = phases.iter().map(|&phase| sin(n * phase)).sum();
+
+    (sum_cos * sum_cos + sum_sin * sum_sin).sqrt() / phases.len() as f64
+}
+
+/// Returns the magnitudes of the rainbow order parameters
+/// `S± = |Σ e^{i(θ_j ± φ_j)}| / N` for `positions` with stride `dim`, where `θ_j`
+/// is the angular position of agent `j` in the xy-plane.
+pub fn rainbow_order(positions: &[f64], phases: &[f64], dim: usize) -> (f64, f64) {
+    if phases.is_empty() {
+        return (0.0, 0.0);
+    }
+
+    let mut plus = (0.0, 0.0);
+    let mut minus = (0.0, 0.0);
+    for (i, phase) in phases.iter().enumerate() {
+        let theta = positions[i * dim + 1].atan2(positions[i * dim]);
+        plus.0 += cos(theta + phase);
+        plus.1 += sin(theta + phase);
+        minus.0 += cos(theta - phase);
+        minus.1 += sin(theta - phase);
+    }
+
+    let n = phases.len() as f64;
+    (
+        (plus.0 * plus.0 + plus.1 * plus.1).sqrt() / n,
+        (minus.0 * minus.0 + minus.1 * minus.1).sqrt() / n,
+    )
+}
@@ -0,0 +1,1703 @@
+//! The simulation core: stepping, derivatives and boundaries of a `Swarmalator`.
+//!
+//! Nothing here touches JS, so it builds and runs the same natively as on the web
+//! and the `wasm_bindgen` methods in the crate root stay a thin layer over it.
+
+#[cfg(any(
+    all(feature = "parallel", not(target_arch = "wasm32")),
+    feature = "wasm-threads"
+))]
+use rayon::prelude::*;
+
+use std::f64::consts::PI;
+use std::sync::OnceLock;
+
+use rand::Rng;
+use rand_distr::StandardNormal;
+
+use crate::formation::Formation;
+use crate::grid::SpatialGrid;
+#[cfg(feature = "wasm-simd")]
+use crate::simd;
+use crate::topology::Topology;
+use crate::{
+    cos, diagnostics, sin, Boundary, CollisionKind, IntegratorKind, NanPolicy, Noise,
+    ScheduledParameter, Scheme, Swarmalator,
+};
+
+/// Most substeps the automatic time step splits a step into.
+const MAX_SUBSTEPS: usize = 64;
+
+/// The agents of one grid cell, aggregated so that they can act as a single
+/// pseudo-agent on agents far away from the cell.
+struct CellSummary {
+    members: Vec<usize>,
+    centroid: [f64; 3],
+    radius: f64,
+    phases: PhaseSums,
+}
+
+/// Every agent aggregated for the mean-field approximation, from which the agents
+/// beyond the cutoff of any one agent are found by subtracting the rest.
+pub(crate) struct MeanField {
+    sum_position: [f64; 3],
+    phases: PhaseSums,
+}
+
+/// Sums over a group of agents of `cos φ_j`, `sin φ_j`, `cos(n φ_j)` and
+/// `sin(n φ_j)`, from which the group's coupling to any phase follows.
+#[derive(Clone, Copy, Default)]
+struct PhaseSums {
+    cos: f64,
+    sin: f64,
+    cos_harmonic: f64,
+    sin_harmonic: f64,
+}
+
+impl PhaseSums {
+    fn add(&mut self, phase: f64, harmonic: f64) {
+        self.cos += cos(phase);
+        self.sin += sin(phase);
+        self.cos_harmonic += cos(harmonic * phase);
+        self.sin_harmonic += sin(harmonic * phase);
+    }
+
+    fn sub(&mut self, other: &PhaseSums) {
+        self.cos -= other.cos;
+        self.sin -= other.sin;
+        self.cos_harmonic -= other.cos_harmonic;
+        self.sin_harmonic -= other.sin_harmonic;
+    }
+}
+
+/// Everything a step changes besides the time and the records, kept to undo it
+/// if it diverges.
+struct Snapshot {
+    positions: Vec<f64>,
+    phases: Vec<f64>,
+    velocities: Vec<f64>,
+    delta_phases: Vec<f64>,
+    natural_frequencies: Vec<f64>,
+    /// Scheduled parameters `[A, B, K, J, phase_lag]`.
+    parameters: [f64; 5],
+    topology: Option<Topology>,
+    noise: Option<Noise>,
+}
+
+impl Swarmalator {
+    /// Advances the system by `dt`, as `update` does once `dt` has been checked,
+    /// split into as many substeps as the automatic time step asks for.
+    ///
+    /// Returns an error describing the divergence if a substep produces non-finite
+    /// values under `NanPolicy::Error`, with that substep undone.
+    pub(crate) fn step(&mut self, dt: f64) -> Result<(), String> {
+        let substeps = self.substeps(dt);
+        self.step_in(dt, substeps)
+    }
+
+    /// Advances the system by `dt` in `substeps` equal substeps, first redoing the
+    /// target shape assignment if agents were added or removed.
+    ///
+    /// The positions before the update, the time averages and the recording are
+    /// kept once per update however many substeps it takes. An update stopped by a
+    /// diverging substep still counts if earlier substeps went through.
+    pub(crate) fn step_in(&mut self, dt: f64, substeps: usize) -> Result<(), String> {
+        if self.formation.as_ref().is_some_and(Formation::is_stale) {
+            self.assign_formation();
+        }
+
+        let start = self.positions.clone();
+        let h = dt / substeps as f64;
+
+        let mut taken = 0;
+        let mut stepped = Ok(());
+        while taken < substeps {
+            stepped = self.step_once(h);
+            if stepped.is_err() {
+                break;
+            }
+            taken += 1;
+        }
+
+        if taken > 0 {
+            self.previous_positions = start;
+            self.record_step(h * taken as f64);
+        }
+
+        stepped
+    }
+
+    /// Adds the state after an update of `dt` to the time averages and recording.
+    fn record_step(&mut self, dt: f64) {
+        if let Some(mut averaging) = self.averaging {
+            let (s_plus, s_minus) = self.rainbow_order();
+            averaging[0].push(self.phase_coherence());
+            averaging[1].push(s_plus);
+            averaging[2].push(s_minus);
+            self.averaging = Some(averaging);
+        }
+
+        if let Some(recording) = self.recording.as_mut() {
+            recording.observe(dt, &self.positions, &self.phases);
+        }
+    }
+
+    /// Returns how many substeps `dt` needs so that, at the current speeds, no agent
+    /// moves further than the automatic time step allows in one.
+    fn substeps(&self, dt: f64) -> usize {
+        let Some(max_displacement) = self.max_displacement else {
+            return 1;
+        };
+
+        let (velocities, _) = self.compute_derivatives(&self.positions, &self.phases);
+        let max_speed = velocities
+            .chunks_exact(self.dim)
+            .map(|v| v.iter().map(|v| v * v).sum::<f64>().sqrt())
+            .filter(|speed| speed.is_finite())
+            .fold(0.0, f64::max);
+
+        ((max_speed * dt.abs() / max_displacement).ceil() as usize).clamp(1, MAX_SUBSTEPS)
+    }
+
+    /// Advances the system by a single step of `dt`, applying the NaN policy.
+    pub(crate) fn step_once(&mut self, dt: f64) -> Result<(), String> {
+        let snapshot = (self.nan_policy != NanPolicy::Ignore).then(|| Snapshot {
+            positions: self.positions.clone(),
+            phases: self.phases.clone(),
+            velocities: self.velocities.clone(),
+            delta_phases: self.delta_phases.clone(),
+            natural_frequencies: self.natural_frequencies.clone(),
+            parameters: [self.A, self.B, self.K, self.J, self.phase_lag],
+            topology: self.topology.clone(),
+            noise: self.noise.clone(),
+        });
+
+        self.apply_schedules();
+        self.refresh_topology();
+
+        let center_before = self.center_of_mass();
+
+        match (self.integrator, self.integration_scheme) {
+            (IntegratorKind::Rk4, _) => {
+                self.update_derivatives_rk4(dt);
+                self.advance_phases(dt);
+                self.advance_positions(dt);
+            }
+            (IntegratorKind::Rk45, _) => {
+                self.update_derivatives_rk45(dt);
+                self.advance_phases(dt);
+                self.advance_positions(dt);
+            }
+            (IntegratorKind::Euler, Scheme::Explicit) => {
+                self.update_derivatives();
+                self.advance_phases(dt);
+                self.advance_positions(dt);
+            }
+            (IntegratorKind::Euler, Scheme::SemiImplicit) => {
+                self.update_derivatives();
+                self.advance_positions(dt);
+
+                // Phase velocities are re-evaluated at the new positions
+                let (_, delta_phases) = self.compute_derivatives(&self.positions, &self.phases);
+                self.delta_phases.copy_from_slice(&delta_phases);
+
+                self.advance_phases(dt);
+            }
+        }
+
+        self.apply_noise(dt);
+
+        if let Some(snapshot) = snapshot {
+            self.recover_divergence(snapshot)?;
+        }
+
+        // Undo any net drift so the centre of mass stays pinned. Pinned agents can't
+        // move, so the unpinned ones absorb the whole correction
+        let unpinned = self.pinned.iter().filter(|&&pinned| !pinned).count();
+        if self.fix_center_of_mass && self.periodic().is_none() && unpinned > 0 {
+            let center_after = self.center_of_mass();
+            let scale = self.agents as f64 / unpinned as f64;
+            for i in (0..self.agents).filter(|&i| !self.pinned[i]) {
+                for k in 0..self.dim {
+                    self.positions[i * self.dim + k] -=
+                        (center_after[k] - center_before[k]) * scale;
+                }
+            }
+        }
+
+        self.resolve_overlaps();
+        self.enforce_boundary();
+
+        self.positions_changed();
+
+        self.time += dt;
+
+        Ok(())
+    }
+
+    /// Applies the NaN policy to any agent left with a non-finite position, phase or
+    /// velocity by the step that started from `snapshot`.
+    fn recover_divergence(&mut self, snapshot: Snapshot) -> Result<(), String> {
+        let dim = self.dim;
+        let diverged: Vec<usize> = (0..self.agents)
+            .filter(|&i| {
+                !self.phases[i].is_finite()
+                    || !self.delta_phases[i].is_finite()
+                    || self.positions[i * dim..(i + 1) * dim]
+                        .iter()
+                        .chain(&self.velocities[i * dim..(i + 1) * dim])
+                        .any(|x| !x.is_finite())
+            })
+            .collect();
+        if diverged.is_empty() {
+            return Ok(());
+        }
+
+        match self.nan_policy {
+            NanPolicy::Ignore => Ok(()),
+            NanPolicy::Error => {
+                self.positions = snapshot.positions;
+                self.phases = snapshot.phases;
+                self.velocities = snapshot.velocities;
+                self.delta_phases = snapshot.delta_phases;
+                self.natural_frequencies = snapshot.natural_frequencies;
+                [self.A, self.B, self.K, self.J, self.phase_lag] = snapshot.parameters;
+                self.topology = snapshot.topology;
+                self.noise = snapshot.noise;
+
+                Err(format!(
+                    "Step diverged: {} agents reached non-finite values, e.g. agent {}",
+                    diverged.len(),
+                    diverged[0]
+                ))
+            }
+            NanPolicy::Reset => {
+                for i in diverged {
+                    self.positions[i * dim..(i + 1) * dim]
+                        .copy_from_slice(&snapshot.positions[i * dim..(i + 1) * dim]);
+                    self.velocities[i * dim..(i + 1) * dim].fill(0.0);
+                    self.phases[i] = snapshot.phases[i];
+                    self.delta_phases[i] = 0.0;
+                    self.natural_frequencies[i] = snapshot.natural_frequencies[i];
+                }
+
+                Ok(())
+            }
+        }
+    }
+
+    /// Moves overlapping hard-core agents apart until they just touch. Separating one
+    /// pair can push another together, so this sweeps over the overlapping pairs until
+    /// they all touch to within a small tolerance, capped so dense clusters settle
+    /// without looping forever.
+    pub(crate) fn resolve_overlaps(&mut self) {
+        const SWEEPS: usize = 50;
+        const TOLERANCE: f64 = 1e-3;
+
+        let Some(collisions) = self.collisions else {
+            return;
+        };
+        if collisions.kind != CollisionKind::Hard {
+            return;
+        }
+
+        let diameter = collisions.diameter();
+        for _ in 0..SWEEPS {
+            let grid = SpatialGrid::build(&self.positions, self.dim, diameter);
+            let mut pairs = Vec::new();
+            for i in 0..self.agents {
+                let point = self.position(&self.positions, i);
+                self.grid_query(Some(&grid), &self.positions, point, diameter, |j| {
+                    if j > i {
+                        pairs.push((i, j));
+                    }
+                });
+            }
+
+            let mut max_overlap: f64 = 0.0;
+            for (i, j) in pairs {
+                if self.pinned[i] && self.pinned[j] {
+                    continue;
+                }
+
+                let d = self.displacement(
+                    self.position(&self.positions, i),
+                    self.position(&self.positions, j),
+                );
+                let dist = norm(&d);
+                if dist >= diameter {
+                    continue;
+                }
+
+                // Coincident agents are split along x
+                let (direction, overlap) = if dist > 0.0 {
+                    (d.map(|d| d / dist), diameter - dist)
+                } else {
+                    ([1.0, 0.0, 0.0], diameter)
+                };
+                max_overlap = max_overlap.max(overlap);
+                let (share_i, share_j) = match (self.pinned[i], self.pinned[j]) {
+                    (true, _) => (0.0, 1.0),
+                    (_, true) => (1.0, 0.0),
+                    _ => (0.5, 0.5),
+                };
+                for (k, direction) in direction.iter().enumerate().take(self.dim) {
+                    self.positions[i * self.dim + k] -= share_i * overlap * direction;
+                    self.positions[j * self.dim + k] += share_j * overlap * direction;
+                }
+            }
+
+            if max_overlap <= TOLERANCE * diameter {
+                return;
+            }
+        }
+    }
+
+    /// Matches the agents to the points of the target shape by squared distance.
+    pub(crate) fn assign_formation(&mut self) {
+        let Some(mut formation) = self.formation.take() else {
+            return;
+        };
+
+        formation.assign(self.agents, self.dim, |i, point| {
+            self.formation_cost(i, point)
+        });
+
+        self.formation = Some(formation);
+    }
+
+    /// Puts the agents from `first` on, just added, on their nearest points of the
+    /// target shape, leaving the full assignment to the next step.
+    pub(crate) fn extend_formation(&mut self, first: usize) {
+        let Some(mut formation) = self.formation.take() else {
+            return;
+        };
+
+        for i in first..self.agents {
+            let nearest = (0..formation.len(self.dim))
+                .min_by(|&a, &b| {
+                    self.formation_cost(i, formation.point(a, self.dim))
+                        .total_cmp(&self.formation_cost(i, formation.point(b, self.dim)))
+                })
+                .unwrap_or(0);
+            formation.push(nearest);
+        }
+
+        self.formation = Some(formation);
+    }
+
+    /// Returns the squared distance from agent `i` to `point` of the target shape.
+    fn formation_cost(&self, i: usize, point: &[f64]) -> f64 {
+        let d = self.displacement(self.position(&self.positions, i), point);
+        d[0] * d[0] + d[1] * d[1] + d[2] * d[2]
+    }
+
+    /// Recomputes the nearest neighbours of a k-nearest-neighbour topology when due.
+    fn refresh_topology(&mut self) {
+        let Some(k) = self.topology.as_mut().and_then(|topology| topology.tick()) else {
+            return;
+        };
+
+        let neighbours = (0..self.agents)
+            .map(|i| {
+                let point = self.position(&self.positions, i);
+                let mut others: Vec<(f64, usize)> = (0..self.agents)
+                    .filter(|&j| j != i)
+                    .map(|j| {
+                        let d = self.displacement(point, self.position(&self.positions, j));
+                        (norm(&d), j)
+                    })
+                    .collect();
+
+                if k < others.len() {
+                    others.select_nth_unstable_by(k, |a, b| a.0.total_cmp(&b.0));
+                    others.truncate(k);
+                }
+                others.into_iter().map(|(_, j)| j).collect()
+            })
+            .collect();
+
+        if let Some(topology) = self.topology.as_mut() {
+            topology.set_neighbours(neighbours);
+        }
+    }
+
+    /// Sets every scheduled parameter to its value at the current time.
+    fn apply_schedules(&mut self) {
+        for schedule in &self.schedules {
+            let value = schedule.value(self.time);
+            match schedule.parameter {
+                ScheduledParameter::A => self.A = value,
+                ScheduledParameter::B => self.B = value,
+                ScheduledParameter::K => self.K = value,
+                ScheduledParameter::J => self.J = value,
+                ScheduledParameter::PhaseLag => self.phase_lag = value,
+            }
+        }
+    }
+
+    /// Computes `velocities` and `delta_phases` from the current state.
+    fn update_derivatives(&mut self) {
+        let (velocities, delta_phases) = self.compute_derivatives(&self.positions, &self.phases);
+        self.velocities.copy_from_slice(&velocities);
+        self.delta_phases.copy_from_slice(&delta_phases);
+    }
+
+    /// Sets `velocities` and `delta_phases` to the RK4 weighted average of the
+    /// derivatives over a step of `dt`, so that advancing by them takes an RK4 step.
+    fn update_derivatives_rk4(&mut self, dt: f64) {
+        let (v1, w1) = self.compute_derivatives(&self.positions, &self.phases);
+        let (v2, w2) = self.compute_derivatives(
+            &offset(&self.positions, &v1, dt / 2.0),
+            &offset(&self.phases, &w1, dt / 2.0),
+        );
+        let (v3, w3) = self.compute_derivatives(
+            &offset(&self.positions, &v2, dt / 2.0),
+            &offset(&self.phases, &w2, dt / 2.0),
+        );
+        let (v4, w4) = self.compute_derivatives(
+            &offset(&self.positions, &v3, dt),
+            &offset(&self.phases, &w3, dt),
+        );
+
+        for k in 0..self.velocities.len() {
+            self.velocities[k] = (v1[k] + 2.0 * v2[k] + 2.0 * v3[k] + v4[k]) / 6.0;
+        }
+        for k in 0..self.delta_phases.len() {
+            self.delta_phases[k] = (w1[k] + 2.0 * w2[k] + 2.0 * w3[k] + w4[k]) / 6.0;
+        }
+    }
+
+    /// Sets `velocities` and `delta_phases` to the average derivatives over a step
+    /// of `dt` taken with adaptive Dormand-Prince substeps, so that advancing by
+    /// them lands on the end of the adaptive step.
+    fn update_derivatives_rk45(&mut self, dt: f64) {
+        // An empty step goes nowhere, so the rates are just those of the current state
+        if dt == 0.0 {
+            self.update_derivatives();
+            return;
+        }
+
+        // Substep lengths are controlled on their magnitude, and a negative `dt` steps
+        // backwards in time. Substeps never shrink below `min_step` so a step always
+        // finishes
+        let span = dt.abs();
+        let min_step = span * 1e-6;
+
+        let mut positions = self.positions.clone();
+        let mut phases = self.phases.clone();
+        let mut t = 0.0;
+        let mut h = span;
+        loop {
+            let last = h >= span - t;
+            if last {
+                h = span - t;
+            }
+
+            let (next_positions, next_phases, error) =
+                self.dormand_prince_step(&positions, &phases, h.copysign(dt));
+
+            // A non-finite error means the state diverged, which shrinking won't fix
+            if error <= 1.0 || !error.is_finite() || h <= min_step {
+                positions = next_positions;
+                phases = next_phases;
+                t += h;
+                if last {
+                    break;
+                }
+            }
+
+            let factor = if error == 0.0 {
+                5.0
+            } else {
+                (0.9 * error.powf(-0.2)).clamp(0.2, 5.0)
+            };
+            h = (h * factor).max(min_step);
+        }
+
+        for (k, end) in positions.iter().enumerate() {
+            self.velocities[k] = (end - self.positions[k]) / dt;
+        }
+        for (k, end) in phases.iter().enumerate() {
+            self.delta_phases[k] = (end - self.phases[k]) / dt;
+        }
+    }
+
+    /// Takes a Dormand-Prince 5(4) step of `h` from the given state, returning the
+    /// fifth-order positions and phases and the error estimate scaled by the
+    /// tolerance, which is at most 1 when the step is acceptable.
+    fn dormand_prince_step(
+        &self,
+        positions: &[f64],
+        phases: &[f64],
+        h: f64,
+    ) -> (Vec<f64>, Vec<f64>, f64) {
+        const A: [&[f64]; 6] = [
+            &[1.0 / 5.0],
+            &[3.0 / 40.0, 9.0 / 40.0],
+            &[44.0 / 45.0, -56.0 / 15.0, 32.0 / 9.0],
+            &[
+                19372.0 / 6561.0,
+                -25360.0 / 2187.0,
+                64448.0 / 6561.0,
+                -212.0 / 729.0,
+            ],
+            &[
+                9017.0 / 3168.0,
+                -355.0 / 33.0,
+                46732.0 / 5247.0,
+                49.0 / 176.0,
+                -5103.0 / 18656.0,
+            ],
+            &[
+                35.0 / 384.0,
+                0.0,
+                500.0 / 1113.0,
+                125.0 / 192.0,
+                -2187.0 / 6784.0,
+                11.0 / 84.0,
+            ],
+        ];
+        // Difference between the fifth and fourth order weights
+        const E: [f64; 7] = [
+            71.0 / 57600.0,
+            0.0,
+            -71.0 / 16695.0,
+            71.0 / 1920.0,
+            -17253.0 / 339200.0,
+            22.0 / 525.0,
+            -1.0 / 40.0,
+        ];
+
+        let (v1, w1) = self.compute_derivatives(positions, phases);
+        let mut vs = vec![v1];
+        let mut ws = vec![w1];
+        for weights in A {
+            let (v, w) = self.compute_derivatives(
+                &combine(positions, &vs, weights, h),
+                &combine(phases, &ws, weights, h),
+            );
+            vs.push(v);
+            ws.push(w);
+        }
+
+        // The last stage is evaluated at the fifth-order solution
+        let next_positions = combine(positions, &vs[..6], A[5], h);
+        let next_phases = combine(phases, &ws[..6], A[5], h);
+
+        let mut sum_sq = 0.0;
+        for (values, next, rates) in [
+            (positions, &next_positions, &vs),
+            (phases, &next_phases, &ws),
+        ] {
+            for k in 0..values.len() {
+                let error: f64 = h * E.iter().zip(rates).map(|(e, r)| e * r[k]).sum::<f64>();
+                let scale = self.tolerance * (1.0 + values[k].abs().max(next[k].abs()));
+                sum_sq += (error / scale).powi(2);
+            }
+        }
+        let count = positions.len() + phases.len();
+        let error = if count == 0 {
+            0.0
+        } else {
+            (sum_sq / count as f64).sqrt()
+        };
+
+        (next_positions, next_phases, error)
+    }
+
+    /// Computes the velocities and phase velocities of every agent in the state
+    /// given by `positions` and `phases`.
+    fn compute_derivatives(&self, positions: &[f64], phases: &[f64]) -> (Vec<f64>, Vec<f64>) {
+        let mut Js = match self.J_vec.as_ref() {
+            Some(J_vec) => J_vec.clone(),
+            None => vec![self.J; self.agents],
+        };
+
+        // How close each agent is to its target, from 1 (closest) to 0 (furthest)
+        let mut proximities = vec![0.0; self.agents];
+
+        // If we have targets we need to recalculate the J values
+        if let Some(targets) = self.target.as_ref() {
+            let mut chasing = vec![0; self.agents];
+            let mut dists_to_target = vec![0.0; self.agents];
+            for i in 0..self.agents {
+                let point = self.position(positions, i);
+                (chasing[i], dists_to_target[i]) = match self.target_assignment.as_ref() {
+                    Some(assignment) => (
+                        assignment[i],
+                        norm(&self.displacement(point, self.target_point(targets, assignment[i]))),
+                    ),
+                    None => self.nearest_target(targets, point),
+                };
+            }
+
+            // Rescale within each group of agents chasing the same target
+            for target in 0..targets.len() / self.dim {
+                let group: Vec<usize> =
+                    (0..self.agents).filter(|&i| chasing[i] == target).collect();
+
+                let max_dist = group
+                    .iter()
+                    .fold(f64::NAN, |m, &i| dists_to_target[i].max(m));
+                let min_dist = group
+                    .iter()
+                    .fold(f64::NAN, |m, &i| dists_to_target[i].min(m));
+
+                // If every agent is equally far away there's nothing to rescale by, so keep J
+                if max_dist - min_dist < 1e-12 {
+                    for &i in &group {
+                        proximities[i] = 1.0;
+                    }
+                } else {
+                    for &i in &group {
+                        Js[i] = self.target_gain * f64::abs(dists_to_target[i] - min_dist)
+                            / (max_dist - min_dist);
+                        proximities[i] =
+                            1.0 - (dists_to_target[i] - min_dist) / (max_dist - min_dist);
+                    }
+                }
+            }
+        }
+
+        // With a cutoff only agents in nearby cells can interact, unless a topology
+        // says which agents do
+        let grid = self
+            .cutoff
+            .filter(|_| self.topology.is_none())
+            .map(|cutoff| SpatialGrid::build(positions, self.dim, cutoff));
+
+        // Distant cells may stand in for their agents instead of being ignored
+        let far_field = match grid.as_ref() {
+            Some(grid) if self.far_field && !self.mean_field => {
+                Some(self.summarise_cells(grid, positions, phases))
+            }
+            _ => None,
+        };
+
+        // Or every distant agent may stand in as one
+        let mean_field = (self.mean_field && self.topology.is_none())
+            .then(|| self.summarise_all(positions, phases));
+
+        // The plain all-to-all interaction can use the vectorised pairwise loop
+        #[cfg(feature = "wasm-simd")]
+        let pairwise = self.vectorisable().then(|| {
+            simd::PairwiseState::new(positions, phases, self.dim, self.phase_harmonic as f64)
+        });
+
+        // Each agent's derivatives only depend on the current state so they can be
+        // computed independently before being written back
+        let derivatives = |i: usize| {
+            #[cfg(feature = "webgpu")]
+            if let Some(sums) = self.pairwise.as_deref() {
+                return self.agent_derivatives_from_pairwise(i, positions, phases, &Js, sums);
+            }
+
+            #[cfg(feature = "wasm-simd")]
+            if let Some(pairwise) = pairwise.as_ref() {
+                return self.agent_derivatives_vectorised(i, positions, phases, &Js, pairwise);
+            }
+
+            self.agent_derivatives(
+                i,
+                positions,
+                phases,
+                &Js,
+                grid.as_ref(),
+                far_field.as_deref(),
+                mean_field.as_ref(),
+            )
+        };
+
+        #[cfg(any(
+            all(feature = "parallel", not(target_arch = "wasm32")),
+            feature = "wasm-threads"
+        ))]
+        let derivatives: Vec<([f64; 3], f64)> =
+            (0..self.agents).into_par_iter().map(derivatives).collect();
+
+        #[cfg(not(any(
+            all(feature = "parallel", not(target_arch = "wasm32")),
+            feature = "wasm-threads"
+        )))]
+        let derivatives: Vec<([f64; 3], f64)> = (0..self.agents).map(derivatives).collect();
+
+        let mut velocities = vec![0.0; self.agents * self.dim];
+        let mut delta_phases = vec![0.0; self.agents];
+        for (i, (velocity, delta_phase)) in derivatives.into_iter().enumerate() {
+            velocities[i * self.dim..(i + 1) * self.dim].copy_from_slice(&velocity[..self.dim]);
+            delta_phases[i] = delta_phase;
+        }
+
+        // Agents near the target are also pulled toward the target phase
+        let (target_phase, strength) = self.phase_target;
+        if self.target.is_some() && strength != 0.0 {
+            for (i, proximity) in proximities.iter().enumerate() {
+                delta_phases[i] += strength * proximity * sin(target_phase - phases[i]);
+            }
+        }
+
+        // Each agent may also be pulled toward its own target phase
+        if let Some((targets, strength)) = self.phase_targets.as_ref() {
+            for (i, target) in targets.iter().enumerate() {
+                delta_phases[i] += strength * sin(target - phases[i]);
+            }
+        }
+
+        // Agents outside a soft arena are pulled back toward it
+        if self.boundary == Boundary::SoftCircular {
+            for i in 0..self.agents {
+                let mut x = [0.0; 3];
+                x[..self.dim].copy_from_slice(self.position(positions, i));
+                let r = norm(&x);
+                if r > self.arena_size {
+                    let pull = self.confinement_stiffness * (self.arena_size - r) / r;
+                    for k in 0..self.dim {
+                        velocities[i * self.dim + k] += pull * x[k];
+                    }
+                }
+            }
+        }
+
+        // Obstacles and flow fields add to the velocities independently of the swarm
+        if !self.environment.is_empty() {
+            for i in 0..self.agents {
+                let velocity = self.environment.velocity(self.position(positions, i));
+                for k in 0..self.dim {
+                    velocities[i * self.dim + k] += velocity[k];
+                }
+            }
+        }
+
+        // Agents are pulled toward their points of the target shape
+        if let Some(formation) = self.formation.as_ref() {
+            for (i, &p) in formation.assignment().iter().enumerate() {
+                let d =
+                    self.displacement(self.position(positions, i), formation.point(p, self.dim));
+                for k in 0..self.dim {
+                    velocities[i * self.dim + k] += formation.strength * d[k];
+                }
+            }
+        }
+
+        // A pointer held over the arena nudges the agents under it
+        if let Some(pointer) = self.pointer {
+            for i in 0..self.agents {
+                let (velocity, delta_phase) = pointer.influence(self.position(positions, i));
+                for k in 0..self.dim {
+                    velocities[i * self.dim + k] += velocity[k];
+                }
+                delta_phases[i] += delta_phase;
+            }
+        }
+
+        // Overlapping soft bodies push each other apart
+        if let Some(collisions) = self.collisions {
+            if collisions.kind == CollisionKind::Soft {
+                let diameter = collisions.diameter();
+                let grid = SpatialGrid::build(positions, self.dim, diameter);
+                for i in 0..self.agents {
+                    let point = self.position(positions, i);
+                    self.grid_query(Some(&grid), positions, point, diameter, |j| {
+                        let d = self.displacement(point, self.position(positions, j));
+                        let dist = norm(&d);
+                        if j == i || dist == 0.0 {
+                            return;
+                        }
+
+                        let push = collisions.stiffness * (diameter - dist) / dist;
+                        for k in 0..self.dim {
+                            velocities[i * self.dim + k] -= push * d[k];
+                        }
+                    });
+                }
+            }
+        }
+
+        // Fast agents are slowed to the speed limit
+        if let Some(max_speed) = self.max_speed {
+            for velocity in velocities.chunks_mut(self.dim) {
+                let mut v = [0.0; 3];
+                v[..self.dim].copy_from_slice(velocity);
+                let speed = norm(&v);
+                if speed > max_speed {
+                    velocity.iter_mut().for_each(|v| *v *= max_speed / speed);
+                }
+            }
+        }
+
+        // Pinned agents never move or change phase
+        for (i, _) in self.pinned.iter().enumerate().filter(|(_, &pinned)| pinned) {
+            velocities[i * self.dim..(i + 1) * self.dim].fill(0.0);
+            delta_phases[i] = 0.0;
+        }
+
+        (velocities, delta_phases)
+    }
+
+    /// Advances the phases (and adapting natural frequencies) by `dt` using `delta_phases`.
+    fn advance_phases(&mut self, dt: f64) {
+        for i in 0..self.agents {
+            if self.pinned[i] {
+                continue;
+            }
+
+            self.phases[i] += self.delta_phases[i] * dt;
+            self.phases[i] %= 2.0 * PI;
+
+            // Natural frequencies relax toward the instantaneous frequency
+            if self.frequency_adaptation != 0.0 {
+                self.natural_frequencies[i] += self.frequency_adaptation
+                    * (self.delta_phases[i] - self.natural_frequencies[i])
+                    * dt;
+            }
+        }
+    }
+
+    /// Adds Gaussian increments with standard deviation `sigma * sqrt(dt)` to every
+    /// unpinned agent's position and phase (Euler-Maruyama).
+    fn apply_noise(&mut self, dt: f64) {
+        let Some(noise) = self.noise.as_mut() else {
+            return;
+        };
+
+        let scale = dt.sqrt();
+        for i in (0..self.agents).filter(|&i| !self.pinned[i]) {
+            for k in 0..self.dim {
+                let dx: f64 = noise.rng.sample(StandardNormal);
+                self.positions[i * self.dim + k] += noise.position_sigma * scale * dx;
+            }
+
+            let dphase: f64 = noise.rng.sample(StandardNormal);
+
+            self.phases[i] += noise.phase_sigma * scale * dphase;
+            self.phases[i] %= 2.0 * PI;
+        }
+    }
+
+    /// Advances the positions by `dt` using `velocities`.
+    fn advance_positions(&mut self, dt: f64) {
+        for i in 0..self.agents {
+            if self.pinned[i] {
+                continue;
+            }
+
+            for k in i * self.dim..(i + 1) * self.dim {
+                self.positions[k] += self.velocities[k] * dt;
+            }
+        }
+    }
+
+    /// Computes the velocity `[vx, vy, vz]` (with `vz = 0` in 2D) and phase velocity
+    /// of agent `i` in the given state, given the per-agent spatial-phase coupling
+    /// `Js`. If a `grid` is given, only agents within the cutoff are considered,
+    /// unless the `far_field` cell summaries are given too, in which case agents in
+    /// distant cells contribute through their cell's summary. Given the `mean_field`
+    /// summary instead, every agent beyond the cutoff (or every agent, without a
+    /// cutoff) contributes through a single pseudo-agent.
+    #[allow(clippy::too_many_arguments)]
+    fn agent_derivatives(
+        &self,
+        i: usize,
+        positions: &[f64],
+        phases: &[f64],
+        Js: &[f64],
+        grid: Option<&SpatialGrid>,
+        far_field: Option<&[CellSummary]>,
+        mean_field: Option<&MeanField>,
+    ) -> ([f64; 3], f64) {
+        let (mut velocity, mut delta_phase) = self.own_derivatives(i, positions, phases);
+
+        let harmonic = self.phase_harmonic as f64;
+        let K = self.K_vec.as_ref().map_or(self.K, |K_vec| K_vec[i]);
+
+        let mut interact = |j: usize| {
+            if i == j {
+                return;
+            }
+
+            let d = self.displacement(self.position(positions, i), self.position(positions, j));
+
+            // Soften and clamp the distance so coincident agents don't divide by zero
+            let dist = self.separation(&d);
+
+            // We may have frequency coupling
+            let mut freq_diff_xy: f64 = 0.0;
+            let mut freq_diff_phase: f64 = 0.0;
+
+            if self.chiral.is_some() {
+                freq_diff_xy =
+                    (PI / 2.0) * f64::abs(self.rotation_sense(j) - self.rotation_sense(i));
+
+                freq_diff_phase = freq_diff_xy / 2.0;
+            }
+
+            // Species and then per-pair coefficients override the per-agent ones
+            let (mut A, mut B, mut J, mut K) = (self.A, self.B, Js[i], K);
+            if let Some(coupling) = self.species_coupling.as_ref() {
+                let pair = self.species[i] as usize * coupling.count + self.species[j] as usize;
+                (A, B, K) = (coupling.A[pair], coupling.B[pair], coupling.K[pair]);
+                if self.target.is_none() {
+                    J = coupling.J[pair];
+                }
+            }
+            if let Some(J_matrix) = self.J_matrix.as_ref() {
+                if self.target.is_none() {
+                    J = J_matrix[i * self.agents + j];
+                }
+            }
+            if let Some(K_matrix) = self.K_matrix.as_ref() {
+                K = K_matrix[i * self.agents + j];
+            }
+
+            // Repulsion may also depend on how in-phase the agents are
+            let repulsion = if self.phase_repulsion_coupling != 0.0 {
+                B * (1.0 + self.phase_repulsion_coupling * cos(phases[j] - phases[i]))
+            } else {
+                B
+            };
+
+            let attraction_falloff = kernel_falloff(dist, self.attraction_exponent);
+            let repulsion_falloff = kernel_falloff(dist, self.repulsion_exponent);
+
+            let attraction = A + J * cos(phases[j] - phases[i] - freq_diff_xy);
+            for k in 0..self.dim {
+                let velocity_contribution: f64 = (d[k] / attraction_falloff) * attraction
+                    - (repulsion * d[k] / repulsion_falloff);
+
+                velocity[k] += (1.0 / self.agents as f64) * velocity_contribution;
+            }
+
+            delta_phase += (K / (self.agents as f64))
+                * sin(harmonic * (phases[j] - phases[i] - freq_diff_phase) - self.phase_lag)
+                / kernel_falloff(dist, self.phase_coupling_exponent);
+        };
+
+        let mut far_velocity = [0.0; 3];
+        let mut far_delta_phase = 0.0;
+        let has_pair_coefficients =
+            self.K_matrix.is_some() || self.J_matrix.is_some() || self.species_coupling.is_some();
+
+        match (
+            self.topology.as_ref(),
+            far_field,
+            mean_field,
+            grid,
+            self.cutoff,
+        ) {
+            (Some(topology), ..) => topology.neighbours(i).iter().for_each(|&j| interact(j)),
+            (None, Some(cells), _, _, Some(cutoff)) => {
+                for cell in cells {
+                    let d = self.displacement(self.position(positions, i), &cell.centroid);
+                    let centroid_dist = norm(&d);
+
+                    // Cells that may hold agents within the cutoff interact exactly, as
+                    // do all cells when per-pair coefficients can't be aggregated
+                    if centroid_dist - cell.radius <= cutoff || has_pair_coefficients {
+                        cell.members.iter().for_each(|&j| interact(j));
+                        continue;
+                    }
+
+                    let (velocity, delta_phase) = self.group_derivatives(
+                        i,
+                        phases,
+                        Js[i],
+                        K,
+                        cell.members.len() as f64,
+                        &cell.phases,
+                        &d,
+                        self.separation(&d),
+                    );
+                    for k in 0..self.dim {
+                        far_velocity[k] += velocity[k];
+                    }
+                    far_delta_phase += delta_phase;
+                }
+            }
+            (None, None, Some(summary), grid, cutoff) if !has_pair_coefficients => {
+                let point = self.position(positions, i);
+
+                // Agents within the cutoff interact exactly and are taken out of the
+                // summary, leaving every other agent as one pseudo-agent
+                let mut near = MeanField {
+                    sum_position: [0.0; 3],
+                    phases: PhaseSums::default(),
+                };
+                near.phases.add(phases[i], harmonic);
+                for (k, x) in point.iter().enumerate() {
+                    near.sum_position[k] += x;
+                }
+                let mut near_count = 1;
+                if let Some(cutoff) = cutoff {
+                    self.grid_query(grid, positions, point, cutoff, |j| {
+                        if j == i {
+                            return;
+                        }
+                        interact(j);
+                        near.phases.add(phases[j], harmonic);
+                        for (k, x) in self.position(positions, j).iter().enumerate() {
+                            near.sum_position[k] += x;
+                        }
+                        near_count += 1;
+                    });
+                }
+
+                let far_count = self.agents - near_count;
+                if far_count > 0 {
+                    let mut far_phases = summary.phases;
+                    far_phases.sub(&near.phases);
+                    let mut centroid = [0.0; 3];
+                    for (k, x) in centroid.iter_mut().enumerate().take(self.dim) {
+                        *x = (summary.sum_position[k] - near.sum_position[k]) / far_count as f64;
+                    }
+
+                    // Every far agent is beyond the cutoff, even if their centroid isn't
+                    let d = self.displacement(point, &centroid);
+                    let dist = self.separation(&d).max(cutoff.unwrap_or(0.0));
+                    (far_velocity, far_delta_phase) = self.group_derivatives(
+                        i,
+                        phases,
+                        Js[i],
+                        K,
+                        far_count as f64,
+                        &far_phases,
+                        &d,
+                        dist,
+                    );
+                }
+            }
+            // Per-pair coefficients can't be aggregated, so every agent interacts exactly
+            (None, None, Some(_), ..) => (0..self.agents).for_each(interact),
+            (None, None, None, Some(grid), Some(cutoff)) => self.grid_query(
+                Some(grid),
+                positions,
+                self.position(positions, i),
+                cutoff,
+                interact,
+            ),
+            _ => (0..self.agents).for_each(interact),
+        }
+
+        if far_field.is_some() || mean_field.is_some() {
+            for k in 0..self.dim {
+                velocity[k] += far_velocity[k];
+            }
+            delta_phase += far_delta_phase;
+        }
+
+        (velocity, delta_phase)
+    }
+
+    /// Computes the velocity and phase velocity agent `i` gains from `count` agents
+    /// with phase sums `sums` acting as a single pseudo-agent, displaced by `d` from
+    /// agent `i` at distance `dist`, given agent `i`'s couplings `J` and `K`.
+    #[allow(clippy::too_many_arguments)]
+    fn group_derivatives(
+        &self,
+        i: usize,
+        phases: &[f64],
+        J: f64,
+        K: f64,
+        count: f64,
+        sums: &PhaseSums,
+        d: &[f64; 3],
+        dist: f64,
+    ) -> ([f64; 3], f64) {
+        let harmonic = self.phase_harmonic as f64;
+        let (cos_i, sin_i) = (cos(phases[i]), sin(phases[i]));
+        let (cos_harmonic_i, sin_harmonic_i) =
+            (cos(harmonic * phases[i]), sin(harmonic * phases[i]));
+
+        // Σ cos(φ_j - φ_i) and Σ sin(n(φ_j - φ_i) - α) over the group
+        let sum_cos = sums.cos * cos_i + sums.sin * sin_i;
+        let sum_sin_harmonic =
+            sums.sin_harmonic * cos_harmonic_i - sums.cos_harmonic * sin_harmonic_i;
+        let sum_cos_harmonic =
+            sums.cos_harmonic * cos_harmonic_i + sums.sin_harmonic * sin_harmonic_i;
+        let sum_coupling =
+            sum_sin_harmonic * cos(self.phase_lag) - sum_cos_harmonic * sin(self.phase_lag);
+
+        let attraction = count * self.A + J * sum_cos;
+        let repulsion = if self.phase_repulsion_coupling != 0.0 {
+            self.B * (count + self.phase_repulsion_coupling * sum_cos)
+        } else {
+            self.B * count
+        };
+        let attraction_falloff = kernel_falloff(dist, self.attraction_exponent);
+        let repulsion_falloff = kernel_falloff(dist, self.repulsion_exponent);
+
+        let mut velocity = [0.0; 3];
+        for k in 0..self.dim {
+            let velocity_contribution: f64 =
+                (d[k] / attraction_falloff) * attraction - (repulsion * d[k] / repulsion_falloff);
+
+            velocity[k] = (1.0 / self.agents as f64) * velocity_contribution;
+        }
+
+        let delta_phase = (K / (self.agents as f64)) * sum_coupling
+            / kernel_falloff(dist, self.phase_coupling_exponent);
+
+        (velocity, delta_phase)
+    }
+
+    /// Computes the part of agent `i`'s velocity and phase velocity that doesn't
+    /// depend on the other agents: the chiral velocity and the (possibly
+    /// position-dependent) natural frequency.
+    fn own_derivatives(&self, i: usize, positions: &[f64], phases: &[f64]) -> ([f64; 3], f64) {
+        // The chiral velocity rotates with the phase so it acts in the xy-plane
+        let velocity = match self.chiral.as_ref() {
+            Some(chiral) => [
+                chiral[i] * cos(phases[i] + PI / 2.0),
+                chiral[i] * sin(phases[i] + PI / 2.0),
+                0.0,
+            ],
+            None => [0.0; 3],
+        };
+
+        // Natural frequnecy always contributes to delta phase
+        let mut delta_phase = self.natural_frequencies[i];
+
+        // The frequency field shifts it depending on where the agent is
+        let (gx, gy) = self.frequency_gradient;
+        if gx != 0.0 || gy != 0.0 {
+            delta_phase += gx * positions[i * self.dim] + gy * positions[i * self.dim + 1];
+        }
+
+        (velocity, delta_phase)
+    }
+
+    /// Whether the pairwise interactions have the plain all-to-all form that
+    /// `agent_derivatives_vectorised` handles: the standard kernels, no cutoff, mean
+    /// field, softening or periodic images, no chirality and no per-pair or
+    /// phase-dependent coefficients.
+    #[cfg(any(feature = "wasm-simd", feature = "webgpu"))]
+    pub(crate) fn vectorisable(&self) -> bool {
+        self.cutoff.is_none()
+            && self.topology.is_none()
+            && !self.mean_field
+            && self.softening == 0.0
+            && self.periodic().is_none()
+            && self.chiral.is_none()
+            && self.K_matrix.is_none()
+            && self.J_matrix.is_none()
+            && self.species_coupling.is_none()
+            && self.phase_repulsion_coupling == 0.0
+            && self.attraction_exponent == 1.0
+            && self.repulsion_exponent == 2.0
+            && self.phase_coupling_exponent == 1.0
+    }
+
+    /// Returns the sense agent `i` turns in for the chiral frequency-difference term:
+    /// the sign of its natural frequency, or of its chiral value if the frequency is
+    /// (near) zero, or `0` if both are.
+    fn rotation_sense(&self, i: usize) -> f64 {
+        let sign = |x: f64| if x.abs() < 1e-12 { 0.0 } else { x.signum() };
+        let sense = sign(self.natural_frequencies[i]);
+        if sense != 0.0 {
+            return sense;
+        }
+
+        self.chiral.as_ref().map_or(0.0, |chiral| sign(chiral[i]))
+    }
+
+    /// Computes the same derivatives as `agent_derivatives` with the pairwise loop
+    /// running over the structure-of-arrays `pairwise` state, using WASM SIMD where
+    /// available. Only valid when `vectorisable` holds. Results agree with
+    /// `agent_derivatives` up to rounding.
+    #[cfg(feature = "wasm-simd")]
+    fn agent_derivatives_vectorised(
+        &self,
+        i: usize,
+        positions: &[f64],
+        phases: &[f64],
+        Js: &[f64],
+        pairwise: &simd::PairwiseState,
+    ) -> ([f64; 3], f64) {
+        let (mut velocity, mut delta_phase) = self.own_derivatives(i, positions, phases);
+
+        let sums = pairwise.accumulate(
+            i,
+            &simd::Coefficients {
+                A: self.A,
+                B: self.B,
+                J: Js[i],
+                min_distance: self.min_distance,
+            },
+        );
+
+        let n = self.agents as f64;
+        for (v, sum) in velocity.iter_mut().zip(sums.velocity).take(self.dim) {
+            *v += sum / n;
+        }
+
+        // Σ sin(n(φ_j - φ_i) - α) / r
+        let K = self.K_vec.as_ref().map_or(self.K, |K_vec| K_vec[i]);
+        let coupling =
+            sums.sin_harmonic * cos(self.phase_lag) - sums.cos_harmonic * sin(self.phase_lag);
+        delta_phase += K / n * coupling;
+
+        (velocity, delta_phase)
+    }
+
+    /// Computes the same derivatives as `agent_derivatives` from pairwise sums
+    /// computed elsewhere, laid out as `GpuPairwise` writes them. Only valid when
+    /// `vectorisable` holds and `sums` were computed from `positions` and `phases`.
+    #[cfg(feature = "webgpu")]
+    fn agent_derivatives_from_pairwise(
+        &self,
+        i: usize,
+        positions: &[f64],
+        phases: &[f64],
+        Js: &[f64],
+        sums: &[f32],
+    ) -> ([f64; 3], f64) {
+        let (mut velocity, mut delta_phase) = self.own_derivatives(i, positions, phases);
+
+        // Σ d / r, Σ cos(φ_j - φ_i) d / r and Σ d / r², each followed by a phase sum
+        let sums = &sums[i * 12..(i + 1) * 12];
+        let n = self.agents as f64;
+        for (k, v) in velocity.iter_mut().enumerate().take(self.dim) {
+            *v += (self.A * sums[k] as f64 + Js[i] * sums[4 + k] as f64
+                - self.B * sums[8 + k] as f64)
+                / n;
+        }
+
+        // Σ sin(n(φ_j - φ_i) - α) / r
+        let K = self.K_vec.as_ref().map_or(self.K, |K_vec| K_vec[i]);
+        let coupling = sums[3] as f64 * cos(self.phase_lag) - sums[7] as f64 * sin(self.phase_lag);
+        delta_phase += K / n * coupling;
+
+        (velocity, delta_phase)
+    }
+
+    /// Summarises the agents in each cell of `grid` for the far-field approximation.
+    fn summarise_cells(
+        &self,
+        grid: &SpatialGrid,
+        positions: &[f64],
+        phases: &[f64],
+    ) -> Vec<CellSummary> {
+        let harmonic = self.phase_harmonic as f64;
+
+        let mut cells = Vec::new();
+        grid.for_each_cell(|members| {
+            let mut centroid = [0.0; 3];
+            for &j in members {
+                for (k, x) in self.position(positions, j).iter().enumerate() {
+                    centroid[k] += x;
+                }
+            }
+            let centroid = centroid.map(|sum| sum / members.len() as f64);
+
+            let radius = members
+                .iter()
+                .map(|&j| norm(&self.displacement(&centroid, self.position(positions, j))))
+                .fold(0.0, f64::max);
+
+            let mut sums = PhaseSums::default();
+            for &j in members {
+                sums.add(phases[j], harmonic);
+            }
+
+            cells.push(CellSummary {
+                members: members.to_vec(),
+                centroid,
+                radius,
+                phases: sums,
+            });
+        });
+
+        cells
+    }
+
+    /// Sums the positions and phases of every agent for the mean-field approximation.
+    fn summarise_all(&self, positions: &[f64], phases: &[f64]) -> MeanField {
+        let harmonic = self.phase_harmonic as f64;
+
+        let mut summary = MeanField {
+            sum_position: [0.0; 3],
+            phases: PhaseSums::default(),
+        };
+        for (i, &phase) in phases.iter().enumerate() {
+            for (k, x) in self.position(positions, i).iter().enumerate() {
+                summary.sum_position[k] += x;
+            }
+            summary.phases.add(phase, harmonic);
+        }
+
+        summary
+    }
+
+    /// Returns the coordinates of target `t` in `targets`.
+    fn target_point<'a>(&self, targets: &'a [f64], t: usize) -> &'a [f64] {
+        &targets[t * self.dim..(t + 1) * self.dim]
+    }
+
+    /// Returns the index of the target in `targets` nearest to `point` and the
+    /// distance to it. Ties go to the lower index.
+    pub(crate) fn nearest_target(&self, targets: &[f64], point: &[f64]) -> (usize, f64) {
+        (0..targets.len() / self.dim)
+            .map(|t| {
+                (
+                    t,
+                    norm(&self.displacement(point, self.target_point(targets, t))),
+                )
+            })
+            .fold((0, f64::NAN), |(best, best_dist), (t, dist)| {
+                if t == 0 || dist < best_dist {
+                    (t, dist)
+                } else {
+                    (best, best_dist)
+                }
+            })
+    }
+
+    /// Returns the coordinates of agent `i` in `values` (positions or velocities).
+    pub(crate) fn position<'a>(&self, values: &'a [f64], i: usize) -> &'a [f64] {
+        &values[i * self.dim..(i + 1) * self.dim]
+    }
+
+    /// Returns the displacement from `a` to `b`, using the nearest periodic image
+    /// when boundaries are periodic. Components past `dim` are zero.
+    pub(crate) fn displacement(&self, a: &[f64], b: &[f64]) -> [f64; 3] {
+        let mut d = [0.0; 3];
+        for k in 0..self.dim {
+            d[k] = match self.periodic() {
+                Some(size) => minimum_image(b[k] - a[k], size),
+                None => b[k] - a[k],
+            };
+        }
+
+        d
+    }
+
+    /// Returns the distance the pairwise kernels use for the separation `d`, softened
+    /// to `sqrt(|d|² + ε²)` and clamped to at least the minimum distance.
+    fn separation(&self, d: &[f64; 3]) -> f64 {
+        let dist_sq: f64 = d.iter().map(|x| x * x).sum();
+        (dist_sq + self.softening * self.softening)
+            .sqrt()
+            .max(self.min_distance)
+    }
+
+    /// Returns the side length of the periodic box, if boundaries are periodic.
+    fn periodic(&self) -> Option<f64> {
+        match self.boundary {
+            Boundary::Periodic => Some(self.arena_size),
+            _ => None,
+        }
+    }
+
+    /// Wraps every position back into the periodic box, or reflects it off the
+    /// walls, for boundaries that constrain positions directly.
+    pub(crate) fn enforce_boundary(&mut self) {
+        let size = self.arena_size;
+        match self.boundary {
+            Boundary::Periodic => {
+                for x in self.positions.iter_mut() {
+                    *x = x.rem_euclid(size);
+                }
+            }
+            Boundary::Reflective => {
+                // Unfolding over a period of two boxes handles any number of bounces
+                for x in self.positions.iter_mut() {
+                    let folded = x.rem_euclid(2.0 * size);
+                    *x = if folded > size {
+                        2.0 * size - folded
+                    } else {
+                        folded
+                    };
+                }
+            }
+            Boundary::Open | Boundary::SoftCircular => {}
+        }
+    }
+
+    /// Returns the mean position of the agents, or the origin if there are none.
+    /// Components past `dim` are zero.
+    pub(crate) fn center_of_mass(&self) -> [f64; 3] {
+        let mut center = [0.0; 3];
+        if self.agents == 0 {
+            return center;
+        }
+
+        for i in 0..self.agents {
+            for (k, x) in self.position(&self.positions, i).iter().enumerate() {
+                center[k] += x;
+            }
+        }
+
+        let n = self.agents as f64;
+        center.map(|sum| sum / n)
+    }
+
+    /// Returns the Kuramoto phase coherence `R = |Σ e^{iφ_j}| / N`.
+    pub(crate) fn phase_coherence(&self) -> f64 {
+        self.harmonic_coherence(1.0)
+    }
+
+    /// Returns the coherence of the `n`th phase harmonic, `|Σ e^{inφ_j}| / N`.
+    pub(crate) fn harmonic_coherence(&self, n: f64) -> f64 {
+        diagnostics::harmonic_coherence(&self.phases, n)
+    }
+
+    /// Returns the magnitudes of the rainbow order parameters `[S+, S-]`.
+    pub(crate) fn rainbow_order(&self) -> (f64, f64) {
+        diagnostics::rainbow_order(&self.positions, &self.phases, self.dim)
+    }
+
+    /// Checks that the per-agent arrays and parameters are consistent.
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        if self.dim != 2 && self.dim != 3 {
+            return Err("Dimension must be 2 or 3".to_string());
+        }
+
+        let per_agent = [
+            ("Positions", self.positions.len(), self.dim),
+            ("Velocities", self.velocities.len(), self.dim),
+            ("Phases", self.phases.len(), 1),
+            ("Delta phases", self.delta_phases.len(), 1),
+            ("Pinned", self.pinned.len(), 1),
+            ("K", self.K_vec.as_ref().map_or(self.agents, Vec::len), 1),
+            ("J", self.J_vec.as_ref().map_or(self.agents, Vec::len), 1),
+            ("Species", self.species.len(), 1),
+            (
+                "K matrix",
+                self.K_matrix
+                    .as_ref()
+                    .map_or(self.agents * self.agents, Vec::len),
+                self.agents,
+            ),
+            (
+                "J matrix",
+                self.J_matrix
+                    .as_ref()
+                    .map_or(self.agents * self.agents, Vec::len),
+                self.agents,
+            ),
+            ("Natural frequencies", self.natural_frequencies.len(), 1),
+            (
+                "Chiral",
+                self.chiral.as_ref().map_or(self.agents, Vec::len),
+                1,
+            ),
+            (
+                "Target assignment",
+                self.target_assignment
+                    .as_ref()
+                    .map_or(self.agents, Vec::len),
+                1,
+            ),
+            (
+                "Phase targets",
+                self.phase_targets
+                    .as_ref()
+                    .map_or(self.agents, |(targets, _)| targets.len()),
+                1,
+            ),
+        ];
+        for (name, len, stride) in per_agent {
+            if len != self.agents * stride {
+                return Err(format!(
+                    "{} array has {} elements but {} agents need {}",
+                    name,
+                    len,
+                    self.agents,
+                    self.agents * stride
+                ));
+            }
+        }
+
+        if let Some(targets) = self.target.as_ref() {
+            if targets.is_empty() || !targets.len().is_multiple_of(self.dim) {
+                return Err(format!(
+                    "Targets array must have a non-zero multiple of {} elements",
+                    self.dim
+                ));
+            }
+
+            let count = targets.len() / self.dim;
+            if let Some(assignment) = self.target_assignment.as_ref() {
+                if assignment.iter().any(|&t| t >= count) {
+                    return Err(
+                        "Target assignment index must be less than the number of targets"
+                            .to_string(),
+                    );
+                }
+            }
+        } else if self.target_assignment.is_some() {
+            return Err("Target assignment is set without targets".to_string());
+        }
+
+        self.environment.validate(self.dim)?;
+
+        for schedule in &self.schedules {
+            schedule.validate()?;
+        }
+
+        if let Some(topology) = self.topology.as_ref() {
+            topology.validate(self.agents)?;
+        }
+
+        if let Some(formation) = self.formation.as_ref() {
+            formation.validate(self.agents, self.dim)?;
+        }
+
+        if let Some(collisions) = self.collisions {
+            if collisions.radius.is_nan() || collisions.radius <= 0.0 {
+                return Err("Body radius must be positive".to_string());
+            }
+        }
+
+        if self.phase_harmonic == 0 {
+            return Err("Phase harmonic must be at least 1".to_string());
+        }
+
+        if self
+            .cutoff
+            .is_some_and(|cutoff| cutoff.is_nan() || cutoff <= 0.0)
+        {
+            return Err("Cutoff radius must be positive".to_string());
+        }
+
+        if self.min_distance.is_nan() || self.min_distance <= 0.0 {
+            return Err("Minimum distance must be positive".to_string());
+        }
+
+        if let Some(coupling) = self.species_coupling.as_ref() {
+            let size = coupling.count * coupling.count;
+            if [&coupling.A, &coupling.B, &coupling.J, &coupling.K]
+                .iter()
+                .any(|m| m.len() != size)
+            {
+                return Err(
+                    "Species coupling matrices must all have species * species elements"
+                        .to_string(),
+                );
+            }
+
+            if self.species.iter().any(|&s| s as usize >= coupling.count) {
+                return Err("Species id must be less than the number of species".to_string());
+            }
+        }
+
+        if self.boundary != Boundary::Open && (self.arena_size.is_nan() || self.arena_size <= 0.0) {
+            return Err("Arena size must be positive".to_string());
+        }
+
+        if self.tolerance.is_nan() || self.tolerance <= 0.0 {
+            return Err("Tolerance must be positive".to_string());
+        }
+
+        Ok(())
+    }
+
+    /// Drops the cached grid after positions were modified.
+    pub(crate) fn positions_changed(&mut self) {
+        self.grid = OnceLock::new();
+    }
+
+    /// Returns the grid over the current positions, building it if this is the first
+    /// query since they changed, or `None` if queries should check every agent.
+    pub(crate) fn cached_grid(&self) -> Option<&SpatialGrid> {
+        if self.cutoff.is_none() && !self.grid_enabled {
+            return None;
+        }
+
+        Some(self.grid.get_or_init(|| {
+            let cell_size = self
+                .cutoff
+                .unwrap_or_else(|| SpatialGrid::default_cell_size(&self.positions, self.dim));
+            SpatialGrid::build(&self.positions, self.dim, cell_size)
+        }))
+    }
+
+    /// Calls `f` with the index of every agent within `radius` of `point`,
+    /// using the cached grid when one is in use.
+    pub(crate) fn for_each_within<F: FnMut(usize)>(&self, point: &[f64], radius: f64, f: F) {
+        self.grid_query(self.cached_grid(), &self.positions, point, radius, f);
+    }
+
+    /// Calls `f` with the index of every agent in `positions` within `radius` of
+    /// `point`, using `grid` (built from `positions`) if given.
+    fn grid_query<F: FnMut(usize)>(
+        &self,
+        grid: Option<&SpatialGrid>,
+        positions: &[f64],
+        point: &[f64],
+        radius: f64,
+        mut f: F,
+    ) {
+        match (grid, self.periodic()) {
+            (Some(grid), None) => grid.for_each_within(positions, point, radius, f),
+            // Search the periodic images of the point, which can't find the same agent
+            // twice while the radius is under half the box
+            (Some(grid), Some(size)) if radius < size / 2.0 => {
+                let shifts = [-size, 0.0, size];
+                for image in 0..3usize.pow(self.dim as u32) {
+                    let mut shifted = [0.0; 3];
+                    for k in 0..self.dim {
+                        let shift = shifts[image / 3usize.pow((self.dim - 1 - k) as u32) % 3];
+                        shifted[k] = point[k].rem_euclid(size) + shift;
+                    }
+                    grid.for_each_within(positions, &shifted[..self.dim], radius, &mut f);
+                }
+            }
+            _ => {
+                for i in 0..self.agents {
+                    let d = self.displacement(point, self.position(positions, i));
+                    if d.iter().map(|d| d * d).sum::<f64>() <= radius * radius {
+                        f(i);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Returns `dist^p`, the denominator of a kernel with exponent `p`.
+fn kernel_falloff(dist: f64, p: f64) -> f64 {
+    // Large exponents can underflow so never divide by zero
+    if p == 1.0 {
+        dist
+    } else if p == 2.0 {
+        dist.powi(2)
+    } else {
+        dist.powf(p).max(f64::MIN_POSITIVE)
+    }
+}
+
+/// Returns the Euclidean length of `v`.
+pub(crate) fn norm(v: &[f64; 3]) -> f64 {
+    (v[0].powi(2) + v[1].powi(2) + v[2].powi(2)).sqrt()
+}
+
+/// Wraps a coordinate difference into `[-size / 2, size / 2]`.
+fn minimum_image(delta: f64, size: f64) -> f64 {
+    delta - size * (delta / size).round()
+}
+
+/// Returns `values + dt * Σ weights[j] * rates[j]` element-wise.
+fn combine(values: &[f64], rates: &[Vec<f64>], weights: &[f64], dt: f64) -> Vec<f64> {
+    values
+        .iter()
+        .enumerate()
+        .map(|(k, value)| {
+            value
+                + dt * weights
+                    .iter()
+                    .zip(rates)
+                    .map(|(weight, rate)| weight * rate[k])
+                    .sum::<f64>()
+        })
+        .collect()
+}
+
+/// Returns `values + rates * dt` element-wise.
+fn offset(values: &[f64], rates: &[f64], dt: f64) -> Vec<f64> {
+    values
+        .iter()
+        .zip(rates)
+        .map(|(value, rate)| value + rate * dt)
+        .collect()
+}
@@ -0,0 +1,300 @@
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+/// Kind of an external flow field, passed to `Swarmalator::set_flow_field`.
+///
+/// Every field adds a velocity to each agent independent of the other agents. The
+/// radial and vortex fields lie in the xy-plane, so in 3D they act on every layer
+/// alike.
+///
+/// - `Uniform`: a constant wind. Params `[vx, vy]`, or `[vx, vy, vz]` in 3D.
+/// - `Radial`: a linear pull toward a centre. Params `[cx, cy, strength]`, adding
+///   `strength * (c - x)`; a negative `strength` pushes agents away instead.
+/// - `Vortex`: a rotation about a centre. Params `[cx, cy, strength, core]`, adding
+///   `strength * (-(y - cy), x - cx) / (r² + core²)`, which is counterclockwise
+///   for positive `strength`, fastest at `r = core` and decays far away.
+#[wasm_bindgen]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum FlowKind {
+    Uniform,
+    Radial,
+    Vortex,
+}
+
+/// What a pointer held over the arena does to the agents under it, passed to
+/// `Swarmalator::apply_pointer_force`.
+///
+/// - `Attract`: pulls agents toward the pointer.
+/// - `Repel`: pushes agents away from the pointer.
+/// - `PhaseKick`: advances the phases of agents under the pointer, or retards them
+///   for a negative strength, without moving them.
+#[wasm_bindgen]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PointerMode {
+    Attract,
+    Repel,
+    PhaseKick,
+}
+
+/// A pointer acting on the agents within `radius` of `center`, with an effect that
+/// fades linearly from `strength` at the centre to zero at the edge.
+#[derive(Clone, Copy)]
+pub struct Pointer {
+    pub center: [f64; 3],
+    pub strength: f64,
+    pub radius: f64,
+    pub mode: PointerMode,
+}
+
+impl Pointer {
+    /// Returns the velocity `[vx, vy, vz]` and phase velocity the pointer gives an
+    /// agent at `point` (stride `dim`).
+    pub fn influence(&self, point: &[f64]) -> ([f64; 3], f64) {
+        let mut d = [0.0; 3];
+        for (k, x) in point.iter().enumerate() {
+            d[k] = self.center[k] - x;
+        }
+        let dist = (d[0] * d[0] + d[1] * d[1] + d[2] * d[2]).sqrt();
+        if dist >= self.radius {
+            return ([0.0; 3], 0.0);
+        }
+
+        let weight = self.strength * (1.0 - dist / self.radius);
+        let toward = if dist > 0.0 {
+            d.map(|d| weight * d / dist)
+        } else {
+            [0.0; 3]
+        };
+        match self.mode {
+            PointerMode::Attract => (toward, 0.0),
+            PointerMode::Repel => (toward.map(|v| -v), 0.0),
+            PointerMode::PhaseKick => ([0.0; 3], weight),
+        }
+    }
+}
+
+/// A static obstacle in the xy-plane.
+#[derive(Clone, Serialize, Deserialize)]
+enum Obstacle {
+    Circle { center: [f64; 2], radius: f64 },
+    Polygon { vertices: Vec<[f64; 2]> },
+}
+
+/// A flow field and its parameters.
+#[derive(Clone, Serialize, Deserialize)]
+struct FlowField {
+    kind: FlowKind,
+    params: Vec<f64>,
+}
+
+/// Obstacles and flow fields that act on every agent on top of the swarmalator
+/// interactions.
+///
+/// Agents closer than `obstacle_range` to an obstacle's edge, or inside it, are
+/// pushed out along the direction away from the nearest point of the edge with a
+/// speed of `obstacle_strength * (obstacle_range - s) / obstacle_range`, where `s`
+/// is the signed distance to the edge (negative inside). The push grows without
+/// bound inside so agents can't tunnel through.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Environment {
+    obstacles: Vec<Obstacle>,
+    flows: Vec<FlowField>,
+    obstacle_strength: f64,
+    obstacle_range: f64,
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Environment {
+            obstacles: Vec::new(),
+            flows: Vec::new(),
+            obstacle_strength: 1.0,
+            obstacle_range: 0.2,
+        }
+    }
+}
+
+impl Environment {
+    /// Whether there are no obstacles or flow fields, so nothing needs evaluating.
+    pub fn is_empty(&self) -> bool {
+        self.obstacles.is_empty() && self.flows.is_empty()
+    }
+
+    /// Adds a circular obstacle and returns its index.
+    pub fn add_circle(&mut self, x: f64, y: f64, radius: f64) -> usize {
+        self.obstacles.push(Obstacle::Circle {
+            center: [x, y],
+            radius,
+        });
+        self.obstacles.len() - 1
+    }
+
+    /// Adds a polygonal obstacle from flattened `[x0, y0, x1, y1, ...]` vertices and
+    /// returns its index.
+    pub fn add_polygon(&mut self, vertices: &[f64]) -> usize {
+        self.obstacles.push(Obstacle::Polygon {
+            vertices: vertices.chunks(2).map(|v| [v[0], v[1]]).collect(),
+        });
+        self.obstacles.len() - 1
+    }
+
+    /// Removes every obstacle.
+    pub fn clear_obstacles(&mut self) {
+        self.obstacles.clear();
+    }
+
+    /// Sets how strongly and from how far away obstacles repel agents.
+    pub fn set_obstacle_repulsion(&mut self, strength: f64, range: f64) {
+        self.obstacle_strength = strength;
+        self.obstacle_range = range;
+    }
+
+    /// Adds a flow field on top of any existing ones.
+    pub fn add_flow(&mut self, kind: FlowKind, params: Vec<f64>) {
+        self.flows.push(FlowField { kind, params });
+    }
+
+    /// Removes every flow field.
+    pub fn clear_flows(&mut self) {
+        self.flows.clear();
+    }
+
+    /// Returns the velocity `[vx, vy, vz]` the obstacles and flow fields give an
+    /// agent at `point` (stride `dim`).
+    pub fn velocity(&self, point: &[f64]) -> [f64; 3] {
+        let mut velocity = [0.0; 3];
+        let (x, y) = (point[0], point[1]);
+
+        for flow in &self.flows {
+            let p = &flow.params;
+            match flow.kind {
+                FlowKind::Uniform => {
+                    for (k, v) in p.iter().enumerate().take(point.len()) {
+                        velocity[k] += v;
+                    }
+                }
+                FlowKind::Radial => {
+                    velocity[0] += p[2] * (p[0] - x);
+                    velocity[1] += p[2] * (p[1] - y);
+                }
+                FlowKind::Vortex => {
+                    let (dx, dy) = (x - p[0], y - p[1]);
+                    let scale = p[2] / (dx * dx + dy * dy + p[3] * p[3]);
+                    velocity[0] -= scale * dy;
+                    velocity[1] += scale * dx;
+                }
+            }
+        }
+
+        for obstacle in &self.obstacles {
+            let (signed_dist, normal) = obstacle.signed_distance(x, y);
+            if signed_dist < self.obstacle_range && normal != [0.0, 0.0] {
+                let push = self.obstacle_strength * (self.obstacle_range - signed_dist)
+                    / self.obstacle_range;
+                velocity[0] += push * normal[0];
+                velocity[1] += push * normal[1];
+            }
+        }
+
+        velocity
+    }
+
+    /// Checks the obstacles and flow fields are well formed for `dim` dimensions.
+    pub fn validate(&self, dim: usize) -> Result<(), String> {
+        if self.obstacle_range.is_nan() || self.obstacle_range <= 0.0 {
+            return Err("Obstacle range must be positive".to_string());
+        }
+
+        for obstacle in &self.obstacles {
+            match obstacle {
+                Obstacle::Circle { radius, .. } if radius.is_nan() || *radius <= 0.0 => {
+                    return Err("Obstacle radius must be positive".to_string());
+                }
+                Obstacle::Polygon { vertices } if vertices.len() < 3 => {
+                    return Err("Polygon obstacles must have at least 3 vertices".to_string());
+                }
+                _ => {}
+            }
+        }
+
+        for flow in &self.flows {
+            check_flow(flow.kind, &flow.params, dim)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Checks `params` has the right number of finite values for a flow field of `kind`.
+pub fn check_flow(kind: FlowKind, params: &[f64], dim: usize) -> Result<(), String> {
+    let (expected, message) = match kind {
+        FlowKind::Uniform => (dim, "Uniform flow params must have dim elements"),
+        FlowKind::Radial => (3, "Radial flow params must be [cx, cy, strength]"),
+        FlowKind::Vortex => (4, "Vortex flow params must be [cx, cy, strength, core]"),
+    };
+
+    if params.len() != expected {
+        return Err(message.to_string());
+    }
+
+    if params.iter().any(|p| !p.is_finite()) {
+        return Err("Flow params must be finite".to_string());
+    }
+
+    Ok(())
+}
+
+impl Obstacle {
+    /// Returns the signed distance from `(x, y)` to the edge of the obstacle,
+    /// negative inside, and the unit direction pointing out of the obstacle, or
+    /// zero if the point is exactly on the edge.
+    fn signed_distance(&self, x: f64, y: f64) -> (f64, [f64; 2]) {
+        match self {
+            Obstacle::Circle { center, radius } => {
+                let (dx, dy) = (x - center[0], y - center[1]);
+                let r = (dx * dx + dy * dy).sqrt();
+                let normal = if r > 0.0 {
+                    [dx / r, dy / r]
+                } else {
+                    [1.0, 0.0]
+                };
+                (r - radius, normal)
+            }
+            Obstacle::Polygon { vertices } => {
+                let mut nearest = (f64::INFINITY, [0.0; 2]);
+                let mut inside = false;
+
+                for (e, a) in vertices.iter().enumerate() {
+                    let b = &vertices[(e + 1) % vertices.len()];
+
+                    // Closest point on the edge from a to b
+                    let (ex, ey) = (b[0] - a[0], b[1] - a[1]);
+                    let length_sq = ex * ex + ey * ey;
+                    let t = if length_sq > 0.0 {
+                        (((x - a[0]) * ex + (y - a[1]) * ey) / length_sq).clamp(0.0, 1.0)
+                    } else {
+                        0.0
+                    };
+                    let (dx, dy) = (x - (a[0] + t * ex), y - (a[1] + t * ey));
+                    let dist = (dx * dx + dy * dy).sqrt();
+                    if dist < nearest.0 {
+                        nearest = (dist, [dx, dy]);
+                    }
+
+                    // Even-odd rule: count edges crossed by a ray toward +x
+                    if (a[1] > y) != (b[1] > y) && x < a[0] + (y - a[1]) / (b[1] - a[1]) * ex {
+                        inside = !inside;
+                    }
+                }
+
+                let (dist, [dx, dy]) = nearest;
+                if dist == 0.0 {
+                    return (0.0, [0.0, 0.0]);
+                }
+
+                let sign = if inside { -1.0 } else { 1.0 };
+                (sign * dist, [sign * dx / dist, sign * dy / dist])
+            }
+        }
+    }
+}
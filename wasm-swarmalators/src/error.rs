@@ -0,0 +1,42 @@
+use std::fmt;
+
+use wasm_bindgen::prelude::*;
+
+/// Error returned by every fallible method, with a descriptive message.
+///
+/// The WASM bindings throw it as a JS `Error`. Creating a `JsError` calls into JS
+/// and aborts on native targets, so the core only converts at that boundary and
+/// native callers get the message instead.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Error(String);
+
+impl Error {
+    pub fn new(message: &str) -> Error {
+        Error(message.to_string())
+    }
+
+    /// Returns the message describing what went wrong.
+    pub fn message(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<String> for Error {
+    fn from(message: String) -> Error {
+        Error(message)
+    }
+}
+
+impl From<Error> for JsValue {
+    fn from(error: Error) -> JsValue {
+        JsError::new(&error.0).into()
+    }
+}
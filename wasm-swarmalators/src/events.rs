@@ -0,0 +1,428 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+
+use wasm_bindgen::prelude::*;
+use web_sys::js_sys::Function;
+
+use crate::{Diagnostics, Error, Swarmalator};
+
+/// An order parameter that can be watched, passed to `Swarmalator::on_threshold` and
+/// `Swarmalator::on_stable`. Each is the field of the same name in `Diagnostics`.
+#[wasm_bindgen]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Metric {
+    PhaseCoherence,
+    PhaseSpaceCorrelation,
+    MeanRadius,
+    MeanSpeed,
+}
+
+/// Direction of a threshold crossing that fires a callback.
+///
+/// - `Rising`: the metric goes from below the threshold to at or above it.
+/// - `Falling`: the metric goes from at or above the threshold to below it.
+/// - `Either`: both.
+#[wasm_bindgen]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Crossing {
+    Rising,
+    Falling,
+    Either,
+}
+
+/// What a watch looks for after every step, and what it saw last.
+enum Condition {
+    Threshold {
+        metric: Metric,
+        threshold: f64,
+        crossing: Crossing,
+        above: bool,
+    },
+    Stable {
+        metric: Metric,
+        tolerance: f64,
+        steps: usize,
+        reference: f64,
+        count: usize,
+    },
+    Bounds {
+        min: [f64; 2],
+        max: [f64; 2],
+        outside: Vec<bool>,
+    },
+}
+
+/// A condition and the id of the callback it fires.
+pub struct Watch {
+    id: u32,
+    condition: Condition,
+}
+
+// JS functions can't be shared between threads, so they live here rather than in the
+// system, which the parallel update shares with its worker threads
+thread_local! {
+    static CALLBACKS: RefCell<HashMap<u32, Function>> = RefCell::new(HashMap::new());
+    static NEXT_ID: Cell<u32> = const { Cell::new(0) };
+}
+
+impl Metric {
+    /// Picks the metric out of `diagnostics`.
+    fn of(self, diagnostics: &Diagnostics) -> f64 {
+        match self {
+            Metric::PhaseCoherence => diagnostics.phase_coherence,
+            Metric::PhaseSpaceCorrelation => diagnostics.phase_space_correlation,
+            Metric::MeanRadius => diagnostics.mean_radius,
+            Metric::MeanSpeed => diagnostics.mean_speed,
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl Swarmalator {
+    /// Calls `callback(value, time)` whenever `metric` crosses `threshold`.
+    ///
+    /// The metric is checked after every step while any watch is registered, which
+    /// costs about as much as `diagnostics`. Only crossings after registration fire,
+    /// so a metric already past the threshold doesn't fire until it crosses again.
+    /// Callbacks run during `update` and can't call methods of this system; note what
+    /// happened and act once `update` returns.
+    /// # Arguments
+    /// - `metric`: Order parameter to watch.
+    /// - `threshold`: Value the metric crosses.
+    /// - `crossing`: Which direction of crossing fires the callback.
+    /// - `callback`: Called with the metric and the simulation time.
+    /// # Errors
+    /// Returns an error if `threshold` is not finite.
+    pub fn on_threshold(
+        &mut self,
+        metric: Metric,
+        threshold: f64,
+        crossing: Crossing,
+        callback: Function,
+    ) -> Result<u32, Error> {
+        if !threshold.is_finite() {
+            return Err(Error::new("Threshold must be finite"));
+        }
+
+        let value = metric.of(&self.diagnostics());
+        Ok(self.watch(
+            Condition::Threshold {
+                metric,
+                threshold,
+                crossing,
+                above: value >= threshold,
+            },
+            callback,
+        ))
+    }
+
+    /// Calls `callback(value, time)` once `metric` has settled, staying within
+    /// `tolerance` of where it started settling for `steps` steps in a row.
+    ///
+    /// Fires once per settling: the metric has to move more than `tolerance` away
+    /// before it can fire again. See `on_threshold` for what callbacks may do.
+    /// # Arguments
+    /// - `metric`: Order parameter to watch.
+    /// - `tolerance`: How far the metric may drift while still settled.
+    /// - `steps`: Number of steps the metric must stay settled for.
+    /// - `callback`: Called with the metric and the simulation time.
+    /// # Errors
+    /// Returns an error if `tolerance` is negative or not finite, or `steps` is zero.
+    pub fn on_stable(
+        &mut self,
+        metric: Metric,
+        tolerance: f64,
+        steps: usize,
+        callback: Function,
+    ) -> Result<u32, Error> {
+        if !tolerance.is_finite() || tolerance < 0.0 {
+            return Err(Error::new("Tolerance must be non-negative and finite"));
+        }
+        if steps == 0 {
+            return Err(Error::new("Number of steps must be positive"));
+        }
+
+        let reference = metric.of(&self.diagnostics());
+        Ok(self.watch(
+            Condition::Stable {
+                metric,
+                tolerance,
+                steps,
+                reference,
+                count: 0,
+            },
+            callback,
+        ))
+    }
+
+    /// Calls `callback(index, time)` whenever an agent leaves the box
+    /// `[min_x, max_x] × [min_y, max_y]` in the xy-plane.
+    ///
+    /// Fires again for the same agent only once it has come back inside. Agents
+    /// added or removed later are picked up without firing. See `on_threshold` for
+    /// what callbacks may do.
+    /// # Arguments
+    /// - `min_x`, `min_y`: Lower corner of the box.
+    /// - `max_x`, `max_y`: Upper corner of the box.
+    /// - `callback`: Called with the agent index and the simulation time.
+    /// # Errors
+    /// Returns an error if any bound is not finite or the box is empty.
+    pub fn on_leave_bounds(
+        &mut self,
+        min_x: f64,
+        min_y: f64,
+        max_x: f64,
+        max_y: f64,
+        callback: Function,
+    ) -> Result<u32, Error> {
+        if [min_x, min_y, max_x, max_y].iter().any(|x| !x.is_finite()) {
+            return Err(Error::new("Bounds must be finite"));
+        }
+        if min_x > max_x || min_y > max_y {
+            return Err(Error::new("Lower bounds must not exceed upper bounds"));
+        }
+
+        let (min, max) = ([min_x, min_y], [max_x, max_y]);
+        let outside = self.outside(min, max);
+        Ok(self.watch(Condition::Bounds { min, max, outside }, callback))
+    }
+
+    /// Removes the watch with id `id`, returned when it was registered. Does nothing
+    /// if this system has no such watch.
+    pub fn unwatch(&mut self, id: u32) {
+        if let Some(index) = self.watches.iter().position(|watch| watch.id == id) {
+            self.watches.remove(index);
+            CALLBACKS.with(|callbacks| callbacks.borrow_mut().remove(&id));
+        }
+    }
+
+    /// Removes every watch.
+    pub fn clear_watches(&mut self) {
+        CALLBACKS.with(|callbacks| {
+            let mut callbacks = callbacks.borrow_mut();
+            for watch in &self.watches {
+                callbacks.remove(&watch.id);
+            }
+        });
+        self.watches.clear();
+    }
+}
+
+impl Swarmalator {
+    /// Checks every watch against the state after a step, calling the callbacks of
+    /// those that fire.
+    pub(crate) fn fire_events(&mut self) -> Result<(), Error> {
+        for (id, value) in self.check_watches() {
+            let callback = CALLBACKS.with(|callbacks| callbacks.borrow().get(&id).cloned());
+            if let Some(callback) = callback {
+                callback
+                    .call2(&JsValue::NULL, &value.into(), &self.time.into())
+                    .map_err(|e| Error::new(&format!("Event callback failed: {:?}", e)))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks every watch against the state after a step, returning the id of each
+    /// one that fires and the value passed to its callback.
+    fn check_watches(&mut self) -> Vec<(u32, f64)> {
+        if self.watches.is_empty() {
+            return Vec::new();
+        }
+
+        let watches_metrics = self
+            .watches
+            .iter()
+            .any(|watch| !matches!(watch.condition, Condition::Bounds { .. }));
+        let diagnostics = watches_metrics.then(|| self.diagnostics());
+
+        let mut fired = Vec::new();
+        let mut watches = std::mem::take(&mut self.watches);
+        for watch in watches.iter_mut() {
+            match &mut watch.condition {
+                Condition::Threshold {
+                    metric,
+                    threshold,
+                    crossing,
+                    above,
+                } => {
+                    let value = metric.of(diagnostics.as_ref().unwrap());
+                    let now_above = value >= *threshold;
+                    let fires = match crossing {
+                        Crossing::Rising => now_above && !*above,
+                        Crossing::Falling => !now_above && *above,
+                        Crossing::Either => now_above != *above,
+                    };
+                    *above = now_above;
+                    if fires {
+                        fired.push((watch.id, value));
+                    }
+                }
+                Condition::Stable {
+                    metric,
+                    tolerance,
+                    steps,
+                    reference,
+                    count,
+                } => {
+                    let value = metric.of(diagnostics.as_ref().unwrap());
+                    if (value - *reference).abs() > *tolerance {
+                        *reference = value;
+                        *count = 0;
+                    } else if *count < *steps {
+                        *count += 1;
+                        if *count == *steps {
+                            fired.push((watch.id, value));
+                        }
+                    }
+                }
+                Condition::Bounds { min, max, outside } => {
+                    let now_outside = self.outside(*min, *max);
+                    if outside.len() == now_outside.len() {
+                        for (i, (was, is)) in outside.iter().zip(&now_outside).enumerate() {
+                            if *is && !*was {
+                                fired.push((watch.id, i as f64));
+                            }
+                        }
+                    }
+                    *outside = now_outside;
+                }
+            }
+        }
+        self.watches = watches;
+
+        fired
+    }
+
+    /// Registers `callback` and starts watching for `condition`, returning the id.
+    fn watch(&mut self, condition: Condition, callback: Function) -> u32 {
+        let id = NEXT_ID.with(|next| {
+            let id = next.get();
+            next.set(id.wrapping_add(1));
+            id
+        });
+        CALLBACKS.with(|callbacks| callbacks.borrow_mut().insert(id, callback));
+        self.watches.push(Watch { id, condition });
+
+        id
+    }
+
+    /// Returns whether each agent is outside the box from `min` to `max` in the
+    /// xy-plane.
+    fn outside(&self, min: [f64; 2], max: [f64; 2]) -> Vec<bool> {
+        (0..self.agents)
+            .map(|i| {
+                let position = self.position(&self.positions, i);
+                (0..2).any(|k| position[k] < min[k] || position[k] > max[k])
+            })
+            .collect()
+    }
+}
+
+impl Drop for Swarmalator {
+    fn drop(&mut self) {
+        self.clear_watches();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FlowKind;
+
+    // Steps with `step` rather than `update`, which would check the watches itself
+
+    /// Watches for `condition` without a callback, as id 0.
+    fn watch(system: &mut Swarmalator, condition: Condition) {
+        system.watches.push(Watch { id: 0, condition });
+    }
+
+    #[test]
+    fn thresholds_fire_once_per_crossing() {
+        let mut system = Swarmalator::random(20, 21, 1.0, 0.0, 0.0).unwrap();
+        let coherence = system.diagnostics().phase_coherence;
+        assert!(coherence < 0.95);
+        watch(
+            &mut system,
+            Condition::Threshold {
+                metric: Metric::PhaseCoherence,
+                threshold: 0.95,
+                crossing: Crossing::Rising,
+                above: false,
+            },
+        );
+
+        let mut fired = Vec::new();
+        for _ in 0..200 {
+            system.step(0.05).unwrap();
+            fired.extend(system.check_watches());
+        }
+
+        // The phases synchronise and stay synchronised
+        assert_eq!(fired.len(), 1);
+        assert!(fired[0].1 >= 0.95);
+    }
+
+    #[test]
+    fn settling_fires_once_per_settling() {
+        let mut system = Swarmalator::random(20, 22, 0.0, 0.0, 0.0).unwrap();
+        system.set_A(0.0).unwrap();
+        system.set_B(0.0).unwrap();
+        let reference = system.diagnostics().mean_radius;
+        watch(
+            &mut system,
+            Condition::Stable {
+                metric: Metric::MeanRadius,
+                tolerance: 1e-3,
+                steps: 10,
+                reference,
+                count: 0,
+            },
+        );
+
+        let mut fired = Vec::new();
+        for step in 0..50 {
+            // Nothing moves, except for one jump
+            if step == 20 {
+                system.set_agent_position(0, 5.0, 5.0, None).unwrap();
+            }
+            system.step(0.05).unwrap();
+            fired.extend(system.check_watches().into_iter().map(|_| step));
+        }
+
+        assert_eq!(fired, vec![9, 30]);
+    }
+
+    #[test]
+    fn agents_leaving_the_box_fire_once_each() {
+        let mut system = Swarmalator::new(
+            2,
+            vec![0.0, 0.0, 0.5, 0.0],
+            vec![0.0; 2],
+            vec![0.0; 2],
+            0.0,
+            0.0,
+            None,
+            None,
+        )
+        .unwrap();
+        system.set_A(0.0).unwrap();
+        system.set_B(0.0).unwrap();
+        system
+            .set_flow_field(Some(FlowKind::Uniform), vec![1.0, 0.0])
+            .unwrap();
+        let (min, max) = ([-1.0, -1.0], [1.0, 1.0]);
+        let outside = system.outside(min, max);
+        watch(&mut system, Condition::Bounds { min, max, outside });
+
+        let mut fired = Vec::new();
+        for _ in 0..20 {
+            system.step(0.1).unwrap();
+            fired.extend(system.check_watches());
+        }
+
+        // Agent 1 leaves after about 0.5 and agent 0 after about 1.0
+        assert_eq!(fired, vec![(0, 1.0), (0, 0.0)]);
+    }
+}
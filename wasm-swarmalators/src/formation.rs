@@ -0,0 +1,239 @@
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+/// How agents are matched to the points of a target shape, passed to
+/// `Swarmalator::set_target_shape`.
+///
+/// Both match each agent to its own point, keeping the squared distances travelled
+/// small, which also keeps paths from crossing. When there are more agents than
+/// points, the points are reused as evenly as possible.
+///
+/// - `Greedy`: repeatedly matches the closest unmatched agent and point, ranking
+///   only the 8 nearest points of each agent. O(N M) time and O(N) memory for N
+///   agents and M points, fast enough to rerun often, but can leave a few agents
+///   crossing the shape.
+/// - `Optimal`: the Hungarian algorithm, giving the smallest total. O(N² M) time,
+///   so it is limited to 1000 agents; swarms grown past that by adding agents
+///   fall back to the greedy matching.
+#[wasm_bindgen]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum AssignmentMethod {
+    Greedy,
+    Optimal,
+}
+
+/// Most agents the optimal assignment is run for.
+pub const MAX_OPTIMAL_AGENTS: usize = 1000;
+
+/// Number of nearest points of each agent the greedy matching ranks.
+const CANDIDATES: usize = 8;
+
+/// A point cloud the agents are pulled into, one point per agent.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Formation {
+    points: Vec<f64>,
+    pub strength: f64,
+    method: AssignmentMethod,
+    assignment: Vec<usize>,
+    /// Whether agents were added or removed since the last assignment, so that it
+    /// is redone before the next step.
+    #[serde(default)]
+    stale: bool,
+}
+
+impl Formation {
+    /// Creates a formation from `points` (stride `dim`), not yet assigned.
+    pub fn new(points: Vec<f64>, strength: f64, method: AssignmentMethod) -> Formation {
+        Formation {
+            points,
+            strength,
+            method,
+            assignment: Vec::new(),
+            stale: false,
+        }
+    }
+
+    /// Returns the number of points.
+    pub fn len(&self, dim: usize) -> usize {
+        self.points.len() / dim
+    }
+
+    /// Returns point `p` of the shape.
+    pub fn point(&self, p: usize, dim: usize) -> &[f64] {
+        &self.points[p * dim..(p + 1) * dim]
+    }
+
+    /// Returns the point each agent is assigned to.
+    pub fn assignment(&self) -> &[usize] {
+        &self.assignment
+    }
+
+    /// Whether agents were added or removed since the last assignment.
+    pub fn is_stale(&self) -> bool {
+        self.stale
+    }
+
+    /// Assigns a newly added agent to point `p` until the next assignment.
+    pub fn push(&mut self, p: usize) {
+        self.assignment.push(p);
+        self.stale = true;
+    }
+
+    /// Drops the assignment of the removed agent `index`.
+    pub fn remove(&mut self, index: usize) {
+        self.assignment.remove(index);
+        self.stale = true;
+    }
+
+    /// Matches `agents` agents to the points, where `cost(i, point)` is the cost of
+    /// sending agent `i` to `point`.
+    pub fn assign<F: Fn(usize, &[f64]) -> f64>(&mut self, agents: usize, dim: usize, cost: F) {
+        self.stale = false;
+        let points = self.len(dim);
+        if agents == 0 || points == 0 {
+            self.assignment = vec![0; agents];
+            return;
+        }
+
+        // Each point is offered as many times as needed for every agent to get one
+        let slots = points.max(agents);
+        self.assignment = match self.method {
+            AssignmentMethod::Optimal if agents <= MAX_OPTIMAL_AGENTS => {
+                hungarian(agents, slots, |i, s| cost(i, self.point(s % points, dim)))
+                    .into_iter()
+                    .map(|s| s % points)
+                    .collect()
+            }
+            _ => {
+                let capacity = (0..points)
+                    .map(|p| slots / points + usize::from(p < slots % points))
+                    .collect();
+                greedy(agents, capacity, |i, p| cost(i, self.point(p, dim)))
+            }
+        };
+    }
+
+    /// Checks the shape has points and the assignment covers `agents` agents.
+    pub fn validate(&self, agents: usize, dim: usize) -> Result<(), String> {
+        if self.points.is_empty() || !self.points.len().is_multiple_of(dim) {
+            return Err("Target shape must have a non-zero multiple of dim elements".to_string());
+        }
+        if self.assignment.len() != agents || self.assignment.iter().any(|&p| p >= self.len(dim)) {
+            return Err("Target shape assignment must cover every agent".to_string());
+        }
+
+        Ok(())
+    }
+}
+
+/// Matches each of `n` rows to a column, where column `p` takes at most
+/// `capacity[p]` rows, cheapest pairs first. Only the `CANDIDATES` cheapest
+/// columns of each row are ranked, and a row whose candidates all fill up takes
+/// the cheapest column left. Needs the capacities to add up to at least `n`.
+fn greedy<F: Fn(usize, usize) -> f64>(n: usize, mut capacity: Vec<usize>, cost: F) -> Vec<usize> {
+    let m = capacity.len();
+    let k = CANDIDATES.min(m);
+
+    let mut pairs: Vec<(f64, usize, usize)> = Vec::with_capacity(n * k);
+    for row in 0..n {
+        let mut columns: Vec<(f64, usize)> = (0..m).map(|p| (cost(row, p), p)).collect();
+        if k < m {
+            columns.select_nth_unstable_by(k - 1, |a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+            columns.truncate(k);
+        }
+        pairs.extend(columns.into_iter().map(|(c, p)| (c, row, p)));
+    }
+    pairs.sort_unstable_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)).then(a.2.cmp(&b.2)));
+
+    let mut matched = vec![usize::MAX; n];
+    for (_, row, column) in pairs {
+        if matched[row] == usize::MAX && capacity[column] > 0 {
+            matched[row] = column;
+            capacity[column] -= 1;
+        }
+    }
+
+    for (row, column) in matched.iter_mut().enumerate() {
+        if *column != usize::MAX {
+            continue;
+        }
+        let cheapest = (0..m)
+            .filter(|&p| capacity[p] > 0)
+            .min_by(|&a, &b| cost(row, a).total_cmp(&cost(row, b)))
+            .expect("Capacities must cover every row");
+        *column = cheapest;
+        capacity[cheapest] -= 1;
+    }
+
+    matched
+}
+
+/// Matches each of `n` rows to a distinct one of `m` columns with the smallest total
+/// `cost(row, column)`, by the Hungarian algorithm with potentials. Costs are
+/// computed as needed rather than stored. Needs `n <= m`.
+fn hungarian<F: Fn(usize, usize) -> f64>(n: usize, m: usize, cost: F) -> Vec<usize> {
+    // Rows and columns are 1-based here, with row and column 0 as sentinels
+    let mut u = vec![0.0; n + 1];
+    let mut v = vec![0.0; m + 1];
+    let mut row_of = vec![0; m + 1];
+    let mut way = vec![0; m + 1];
+
+    for row in 1..=n {
+        row_of[0] = row;
+        let mut column = 0;
+        let mut min_slack = vec![f64::INFINITY; m + 1];
+        let mut used = vec![false; m + 1];
+
+        // Grow an alternating tree from the row until it reaches a free column
+        loop {
+            used[column] = true;
+            let current = row_of[column];
+            let mut delta = f64::INFINITY;
+            let mut next = 0;
+            for j in 1..=m {
+                if used[j] {
+                    continue;
+                }
+                let slack = cost(current - 1, j - 1) - u[current] - v[j];
+                if slack < min_slack[j] {
+                    min_slack[j] = slack;
+                    way[j] = column;
+                }
+                if min_slack[j] < delta {
+                    delta = min_slack[j];
+                    next = j;
+                }
+            }
+
+            for j in 0..=m {
+                if used[j] {
+                    u[row_of[j]] += delta;
+                    v[j] -= delta;
+                } else {
+                    min_slack[j] -= delta;
+                }
+            }
+
+            column = next;
+            if row_of[column] == 0 {
+                break;
+            }
+        }
+
+        // Flip the matching along the augmenting path
+        while column != 0 {
+            let previous = way[column];
+            row_of[column] = row_of[previous];
+            column = previous;
+        }
+    }
+
+    let mut matched = vec![0; n];
+    for j in 1..=m {
+        if row_of[j] != 0 {
+            matched[row_of[j] - 1] = j - 1;
+        }
+    }
+
+    matched
+}
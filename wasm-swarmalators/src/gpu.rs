@@ -0,0 +1,369 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::future_to_promise;
+use web_sys::js_sys::{Float32Array, Promise};
+
+use crate::{check_finite, Error, IntegratorKind, Scheme, Swarmalator};
+
+/// Floats per agent in the input: `x, y, z, phase`.
+const INPUT_STRIDE: usize = 4;
+/// Floats per agent in the sums: three `vec4`s, see `pairwise.wgsl`.
+const SUMS_STRIDE: usize = 12;
+/// Floats before the agents in the input: `harmonic, min_distance` and padding.
+const HEADER: usize = 4;
+/// Invocations per workgroup, matching `@workgroup_size` in `pairwise.wgsl`.
+const WORKGROUP_SIZE: u32 = 64;
+
+/// Computes the all-to-all pairwise sums of a system on the GPU.
+///
+/// The O(N²) loop runs as a compute shader in `f32`; everything else in the step
+/// stays on the CPU. A step is split in three so the GPU can be awaited between
+/// borrows of the system:
+///
+/// ```js
+/// const gpu = await GpuPairwise.create();
+/// const sums = await gpu.pairwise(system.gpu_input());
+/// system.update_with_pairwise(dt, sums);
+/// ```
+///
+/// Only the plain all-to-all interaction with the explicit Euler integrator is
+/// supported, the same interaction the `wasm-simd` loop covers; `gpu_supported`
+/// says whether a system qualifies, and `update` remains the fallback otherwise or
+/// when no adapter is available. The buffers live on this module's own device, so
+/// rendering still reads the state through the `Float64Array` views.
+#[wasm_bindgen]
+#[derive(Clone)]
+pub struct GpuPairwise {
+    inner: Rc<Inner>,
+}
+
+struct Inner {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    pipeline: wgpu::ComputePipeline,
+    buffers: RefCell<Option<Rc<Buffers>>>,
+    // Set while a readback is in flight, as the readback buffer can only be mapped once
+    busy: Cell<bool>,
+}
+
+/// Buffers sized for a number of agents, recreated when it changes.
+struct Buffers {
+    agents: usize,
+    params: wgpu::Buffer,
+    input: wgpu::Buffer,
+    output: wgpu::Buffer,
+    readback: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+#[wasm_bindgen]
+impl GpuPairwise {
+    /// Requests a GPU adapter and device and compiles the pairwise shader.
+    ///
+    /// # Errors
+    /// Rejects if no adapter with compute shaders is available, e.g. in browsers
+    /// without WebGPU, in which case keep using `update`.
+    pub async fn create() -> Result<GpuPairwise, Error> {
+        GpuPairwise::request().await.map_err(Error::from)
+    }
+
+    /// Computes the pairwise sums for the state packed by `Swarmalator::gpu_input`,
+    /// resolving to a `Float32Array` for `Swarmalator::update_with_pairwise`.
+    ///
+    /// Await each call before starting the next.
+    /// # Arguments
+    /// - `input`: State packed by `gpu_input`.
+    /// # Errors
+    /// Rejects if `input` is malformed, a previous call is still in flight, or the
+    /// results can't be read back.
+    pub fn pairwise(&self, input: Vec<f32>) -> Promise {
+        let gpu = self.clone();
+        future_to_promise(async move {
+            match gpu.compute(&input).await {
+                Ok(sums) => Ok(Float32Array::from(&sums[..]).into()),
+                Err(e) => Err(Error::new(&e).into()),
+            }
+        })
+    }
+}
+
+impl GpuPairwise {
+    /// Requests a GPU adapter and device and compiles the pairwise shader.
+    pub async fn request() -> Result<GpuPairwise, String> {
+        // Creating an instance where there's no WebGPU panics rather than failing
+        #[cfg(target_arch = "wasm32")]
+        if !wgpu::util::is_browser_webgpu_supported().await {
+            return Err("WebGPU is not available".to_string());
+        }
+
+        let instance = wgpu::Instance::default();
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::HighPerformance,
+                ..Default::default()
+            })
+            .await
+            .ok_or("No GPU adapter is available")?;
+
+        if !adapter
+            .get_downlevel_capabilities()
+            .flags
+            .contains(wgpu::DownlevelFlags::COMPUTE_SHADERS)
+        {
+            return Err("GPU adapter doesn't support compute shaders".to_string());
+        }
+
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    label: Some("swarmalators"),
+                    required_features: wgpu::Features::empty(),
+                    required_limits: wgpu::Limits::downlevel_defaults()
+                        .using_resolution(adapter.limits()),
+                    memory_hints: wgpu::MemoryHints::Performance,
+                },
+                None,
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let module = device.create_shader_module(wgpu::include_wgsl!("pairwise.wgsl"));
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("pairwise"),
+            layout: None,
+            module: &module,
+            entry_point: Some("main"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+
+        Ok(GpuPairwise {
+            inner: Rc::new(Inner {
+                device,
+                queue,
+                pipeline,
+                buffers: RefCell::new(None),
+                busy: Cell::new(false),
+            }),
+        })
+    }
+
+    /// Computes the pairwise sums for the state packed by `Swarmalator::gpu_input`.
+    pub async fn compute(&self, input: &[f32]) -> Result<Vec<f32>, String> {
+        if input.len() < HEADER || !(input.len() - HEADER).is_multiple_of(INPUT_STRIDE) {
+            return Err("Input must come from gpu_input".to_string());
+        }
+        let agents = (input.len() - HEADER) / INPUT_STRIDE;
+        if agents == 0 {
+            return Ok(Vec::new());
+        }
+        if self.inner.busy.get() {
+            return Err("A previous pairwise call is still in flight".to_string());
+        }
+
+        self.inner.busy.set(true);
+        let sums = self.dispatch(agents, input).await;
+        self.inner.busy.set(false);
+
+        sums
+    }
+
+    /// Uploads `input`, runs the shader over `agents` agents and reads back the sums.
+    async fn dispatch(&self, agents: usize, input: &[f32]) -> Result<Vec<f32>, String> {
+        let Inner { device, queue, .. } = &*self.inner;
+        let buffers = self.buffers(agents);
+
+        let mut params = Vec::with_capacity(16);
+        params.extend_from_slice(&(agents as u32).to_le_bytes());
+        for value in &input[..HEADER - 1] {
+            params.extend_from_slice(&value.to_le_bytes());
+        }
+        queue.write_buffer(&buffers.params, 0, &params);
+        queue.write_buffer(&buffers.input, 0, &to_bytes(&input[HEADER..]));
+
+        let mut encoder = device.create_command_encoder(&Default::default());
+        {
+            let mut pass = encoder.begin_compute_pass(&Default::default());
+            pass.set_pipeline(&self.inner.pipeline);
+            pass.set_bind_group(0, &buffers.bind_group, &[]);
+            pass.dispatch_workgroups((agents as u32).div_ceil(WORKGROUP_SIZE), 1, 1);
+        }
+        encoder.copy_buffer_to_buffer(
+            &buffers.output,
+            0,
+            &buffers.readback,
+            0,
+            buffers.readback.size(),
+        );
+        queue.submit([encoder.finish()]);
+
+        let slice = buffers.readback.slice(..);
+        let (sender, receiver) = futures_channel::oneshot::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        // Drives the map natively; on the web the browser does
+        device.poll(wgpu::Maintain::Wait);
+        receiver
+            .await
+            .map_err(|_| "GPU device was lost".to_string())?
+            .map_err(|e| e.to_string())?;
+
+        let sums = slice
+            .get_mapped_range()
+            .chunks_exact(4)
+            .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .collect();
+        buffers.readback.unmap();
+
+        Ok(sums)
+    }
+
+    /// Returns the buffers for `agents` agents, recreating them if the count changed.
+    fn buffers(&self, agents: usize) -> Rc<Buffers> {
+        let mut cached = self.inner.buffers.borrow_mut();
+        if let Some(buffers) = cached.as_ref().filter(|buffers| buffers.agents == agents) {
+            return buffers.clone();
+        }
+
+        let device = &self.inner.device;
+        let buffer = |label: &str, floats: usize, usage: wgpu::BufferUsages| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size: (floats * 4) as u64,
+                usage,
+                mapped_at_creation: false,
+            })
+        };
+
+        use wgpu::BufferUsages as Usage;
+        let params = buffer("params", 4, Usage::UNIFORM | Usage::COPY_DST);
+        let input = buffer(
+            "agents",
+            agents * INPUT_STRIDE,
+            Usage::STORAGE | Usage::COPY_DST,
+        );
+        let output = buffer(
+            "sums",
+            agents * SUMS_STRIDE,
+            Usage::STORAGE | Usage::COPY_SRC,
+        );
+        let readback = buffer(
+            "readback",
+            agents * SUMS_STRIDE,
+            Usage::MAP_READ | Usage::COPY_DST,
+        );
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("pairwise"),
+            layout: &self.inner.pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: params.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: input.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: output.as_entire_binding(),
+                },
+            ],
+        });
+
+        let buffers = Rc::new(Buffers {
+            agents,
+            params,
+            input,
+            output,
+            readback,
+            bind_group,
+        });
+        *cached = Some(buffers.clone());
+        buffers
+    }
+}
+
+fn to_bytes(values: &[f32]) -> Vec<u8> {
+    values
+        .iter()
+        .flat_map(|value| value.to_le_bytes())
+        .collect()
+}
+
+#[wasm_bindgen]
+impl Swarmalator {
+    /// Whether `update_with_pairwise` can step this system: the plain all-to-all
+    /// interaction (no cutoff, topology, periodic images, chirality, coupling
+    /// matrices, species or non-standard kernels) with the explicit Euler integrator.
+    pub fn gpu_supported(&self) -> bool {
+        self.vectorisable()
+            && self.integrator == IntegratorKind::Euler
+            && self.integration_scheme == Scheme::Explicit
+    }
+
+    /// Packs the state `GpuPairwise::pairwise` needs for the next step.
+    ///
+    /// # Errors
+    /// Returns an error unless `gpu_supported` holds.
+    pub fn gpu_input(&self) -> Result<Vec<f32>, Error> {
+        if !self.gpu_supported() {
+            return Err(Error::new("System isn't supported by the GPU backend"));
+        }
+
+        let mut input = Vec::with_capacity(HEADER + self.agents * INPUT_STRIDE);
+        input.extend_from_slice(&[
+            self.phase_harmonic as f32,
+            self.min_distance as f32,
+            0.0,
+            0.0,
+        ]);
+        for i in 0..self.agents {
+            let mut point = [0.0; 3];
+            point[..self.dim].copy_from_slice(self.position(&self.positions, i));
+            input.extend(point.iter().map(|&x| x as f32));
+            input.push(self.phases[i] as f32);
+        }
+
+        Ok(input)
+    }
+
+    /// Updates the state like `update`, using pairwise sums from `GpuPairwise` in
+    /// place of the CPU loop.
+    ///
+    /// The sums must come from `gpu_input` with no changes to the agents since. They
+    /// are `f32`, so trajectories drift from `update`'s over long runs. The step is
+    /// never split by the automatic time step.
+    /// # Arguments
+    /// - `dt`: Time step for the update.
+    /// - `sums`: Sums resolved by `GpuPairwise::pairwise`.
+    /// # Errors
+    /// Returns an error if `dt` is not finite, `gpu_supported` doesn't hold or `sums`
+    /// has the wrong length, leaving the system unchanged, or like `update` if the
+    /// step diverges.
+    pub fn update_with_pairwise(&mut self, dt: f64, sums: Vec<f32>) -> Result<(), Error> {
+        check_finite("Time step", dt)?;
+        if !self.gpu_supported() {
+            return Err(Error::new("System isn't supported by the GPU backend"));
+        }
+        if sums.len() != self.agents * SUMS_STRIDE {
+            return Err(Error::new(&format!(
+                "Expected {} pairwise sums but got {}",
+                self.agents * SUMS_STRIDE,
+                sums.len()
+            )));
+        }
+
+        // The sums only hold for the current state, so the step is never split
+        self.pairwise = Some(sums);
+        let stepped = self.step_in(dt, 1);
+        self.pairwise = None;
+        stepped?;
+
+        self.fire_events()
+    }
+}
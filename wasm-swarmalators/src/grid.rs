@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+
+/// A uniform grid that buckets agents by cell so neighbourhood queries only
+/// have to look at nearby cells instead of every agent.
+///
+/// Only occupied cells are stored, hashed by their integer coordinates, so memory
+/// grows with the number of agents rather than with how far apart they are. Works
+/// in two or three dimensions. Unused axes always have coordinate 0.
+pub struct SpatialGrid {
+    dim: usize,
+    cell_size: f64,
+    /// Corner of cell 0, at the smallest finite coordinate on each axis.
+    origin: [f64; 3],
+    cells: HashMap<[i64; 3], Vec<usize>>,
+    /// Occupied cells ordered by layer, row and then column, the order they are
+    /// visited in so results don't depend on the hasher.
+    order: Vec<[i64; 3]>,
+    /// Smallest and largest occupied cell coordinate on each axis.
+    lower: [i64; 3],
+    upper: [i64; 3],
+}
+
+/// Cell coordinates are clamped to this magnitude, far beyond any real arena, so
+/// non-finite or absurd positions can't overflow the arithmetic on them.
+const MAX_COORDINATE: f64 = 1e15;
+
+impl SpatialGrid {
+    /// Buckets the agents in `positions` (stride `dim`) into cubic cells of side `cell_size`.
+    pub fn build(positions: &[f64], dim: usize, cell_size: f64) -> SpatialGrid {
+        let agents = positions.len() / dim;
+
+        let mut origin = [0.0; 3];
+        for (axis, corner) in origin.iter_mut().enumerate().take(dim) {
+            let min = (0..agents)
+                .map(|i| positions[i * dim + axis])
+                .filter(|x| x.is_finite())
+                .fold(f64::INFINITY, f64::min);
+            if min.is_finite() {
+                *corner = min;
+            }
+        }
+
+        let mut grid = SpatialGrid {
+            dim,
+            cell_size,
+            origin,
+            cells: HashMap::new(),
+            order: Vec::new(),
+            lower: [0; 3],
+            upper: [0; 3],
+        };
+        for axis in 0..dim {
+            grid.lower[axis] = i64::MAX;
+            grid.upper[axis] = i64::MIN;
+        }
+
+        for i in 0..agents {
+            let cell = grid.cell_of(&positions[i * dim..(i + 1) * dim]);
+            for (axis, &c) in cell.iter().enumerate().take(dim) {
+                grid.lower[axis] = grid.lower[axis].min(c);
+                grid.upper[axis] = grid.upper[axis].max(c);
+            }
+            grid.cells.entry(cell).or_default().push(i);
+        }
+
+        grid.order = grid.cells.keys().copied().collect();
+        grid.order
+            .sort_unstable_by_key(|cell| (cell[2], cell[1], cell[0]));
+
+        grid
+    }
+
+    /// Picks a cell size giving roughly one agent per cell for the current spread of agents.
+    pub fn default_cell_size(positions: &[f64], dim: usize) -> f64 {
+        let agents = positions.len() / dim;
+
+        let mut extent: f64 = 0.0;
+        for axis in 0..dim {
+            let (min, max) = (0..agents)
+                .map(|i| positions[i * dim + axis])
+                .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), v| {
+                    (lo.min(v), hi.max(v))
+                });
+            extent = extent.max(max - min);
+        }
+
+        let per_side = if dim == 3 {
+            (agents as f64).cbrt()
+        } else {
+            (agents as f64).sqrt()
+        };
+        let cell_size = extent / per_side.ceil().max(1.0);
+
+        if cell_size.is_finite() && cell_size > 0.0 {
+            cell_size
+        } else {
+            1.0
+        }
+    }
+
+    /// Calls `f` with the index of every agent within `radius` of `point`.
+    pub fn for_each_within<F: FnMut(usize)>(
+        &self,
+        positions: &[f64],
+        point: &[f64],
+        radius: f64,
+        mut f: F,
+    ) {
+        // Cells overlapping the ball's bounding box, limited to the occupied ones
+        let mut min_cell = [0; 3];
+        let mut max_cell = [0; 3];
+        for (axis, &x) in point.iter().enumerate().take(self.dim) {
+            min_cell[axis] = self.coordinate(axis, x - radius).max(self.lower[axis]);
+            max_cell[axis] = self.coordinate(axis, x + radius).min(self.upper[axis]);
+
+            // Nothing to find if the ball misses every occupied cell
+            if min_cell[axis] > max_cell[axis] {
+                return;
+            }
+        }
+
+        let mut visit = |members: &[usize]| {
+            for &i in members {
+                let dist_sq: f64 = (0..self.dim)
+                    .map(|axis| (positions[i * self.dim + axis] - point[axis]).powi(2))
+                    .sum();
+                if dist_sq <= radius * radius {
+                    f(i);
+                }
+            }
+        };
+
+        // A count too large for a u128 is certainly more than are occupied
+        let spanned = (0..3).try_fold(1u128, |cells, axis| {
+            cells.checked_mul((max_cell[axis] - min_cell[axis]) as u128 + 1)
+        });
+        if spanned.is_none_or(|cells| cells > self.order.len() as u128) {
+            // Fewer cells are occupied than overlap the ball, e.g. with a far outlier,
+            // so filter those instead of probing mostly empty cells
+            let inside = |cell: &[i64; 3]| {
+                (0..3).all(|axis| (min_cell[axis]..=max_cell[axis]).contains(&cell[axis]))
+            };
+            for cell in self.order.iter().filter(|cell| inside(cell)) {
+                visit(&self.cells[cell]);
+            }
+            return;
+        }
+
+        for layer in min_cell[2]..=max_cell[2] {
+            for row in min_cell[1]..=max_cell[1] {
+                for col in min_cell[0]..=max_cell[0] {
+                    if let Some(members) = self.cells.get(&[col, row, layer]) {
+                        visit(members);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Calls `f` with the agents in each non-empty cell.
+    pub fn for_each_cell<F: FnMut(&[usize])>(&self, mut f: F) {
+        for cell in &self.order {
+            f(&self.cells[cell]);
+        }
+    }
+
+    /// Returns the coordinates of the cell containing `point`.
+    fn cell_of(&self, point: &[f64]) -> [i64; 3] {
+        let mut cell = [0; 3];
+        for axis in 0..self.dim {
+            cell[axis] = self.coordinate(axis, point[axis]);
+        }
+
+        cell
+    }
+
+    /// Returns the cell coordinate of `x` along `axis`, with `NaN` in cell 0.
+    fn coordinate(&self, axis: usize, x: f64) -> i64 {
+        ((x - self.origin[axis]) / self.cell_size)
+            .floor()
+            .clamp(-MAX_COORDINATE, MAX_COORDINATE) as i64
+    }
+}
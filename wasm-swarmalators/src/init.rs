@@ -0,0 +1,182 @@
+use std::f64::consts::PI;
+
+use rand::Rng;
+use rand_chacha::ChaCha12Rng;
+use rand_distr::StandardNormal;
+use wasm_bindgen::prelude::*;
+
+/// Region the initial positions are drawn uniformly from, of half-width `size`.
+///
+/// - `Box`: the square `[-size, size]²`, or the cube `[-size, size]³` in 3D.
+/// - `Disk`: the disk of radius `size` about the origin, or the ball in 3D.
+#[wasm_bindgen]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PositionDistribution {
+    Box,
+    Disk,
+}
+
+/// Distribution of the natural frequencies about `frequency_center`, with a spread
+/// set by `frequency_width`.
+///
+/// - `Uniform`: uniform in `[center - width, center + width]`.
+/// - `Normal`: normal with standard deviation `width`.
+/// - `Lorentzian`: Cauchy with half-width at half-maximum `width`.
+/// - `Bimodal`: each agent gets `center + width` or `center - width` with equal odds.
+#[wasm_bindgen]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FrequencyDistribution {
+    Uniform,
+    Normal,
+    Lorentzian,
+    Bimodal,
+}
+
+/// How `Swarmalator::random_with_config` draws the initial state of the agents.
+///
+/// Phases are always uniform in `[0, 2π)`.
+///
+/// - `dim`: Number of spatial dimensions, 2 or 3.
+/// - `positions`: Region the positions are drawn from.
+/// - `size`: Half-width of the box, or radius of the disk.
+/// - `frequencies`: Distribution of the natural frequencies.
+/// - `frequency_center`: Centre of the natural frequency distribution.
+/// - `frequency_width`: Spread of the natural frequency distribution.
+/// - `chirality`: Magnitude of the chiral values, or `0` for a system that isn't
+///   chiral.
+/// - `chiral_fraction`: Fraction of agents, on average, with a positive chiral value;
+///   the rest get `-chirality`.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug)]
+pub struct InitConfig {
+    pub dim: usize,
+    pub positions: PositionDistribution,
+    pub size: f64,
+    pub frequencies: FrequencyDistribution,
+    pub frequency_center: f64,
+    pub frequency_width: f64,
+    pub chirality: f64,
+    pub chiral_fraction: f64,
+}
+
+impl Default for InitConfig {
+    fn default() -> Self {
+        InitConfig {
+            dim: 2,
+            positions: PositionDistribution::Box,
+            size: 1.0,
+            frequencies: FrequencyDistribution::Uniform,
+            frequency_center: 0.0,
+            frequency_width: 0.0,
+            chirality: 0.0,
+            chiral_fraction: 0.5,
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl InitConfig {
+    /// Creates a config for a 2D system in the box `[-1, 1]²` with identical agents,
+    /// matching `Swarmalator::random` with no frequency spread.
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> InitConfig {
+        InitConfig::default()
+    }
+}
+
+/// Initial arrays drawn from an `InitConfig`.
+pub struct InitialState {
+    pub positions: Vec<f64>,
+    pub phases: Vec<f64>,
+    pub natural_frequencies: Vec<f64>,
+    pub chiral: Option<Vec<f64>>,
+}
+
+impl InitConfig {
+    /// Checks every parameter is in range, leaving `dim` to the constructor.
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.size.is_finite() || self.size <= 0.0 {
+            return Err("Size must be positive and finite".to_string());
+        }
+        if !self.frequency_center.is_finite() {
+            return Err("Frequency center must be finite".to_string());
+        }
+        if !self.frequency_width.is_finite() || self.frequency_width < 0.0 {
+            return Err("Frequency width must be non-negative and finite".to_string());
+        }
+        if !self.chirality.is_finite() {
+            return Err("Chirality must be finite".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.chiral_fraction) {
+            return Err("Chiral fraction must be between 0 and 1".to_string());
+        }
+
+        Ok(())
+    }
+
+    /// Draws the initial state of `agents` agents from `rng`.
+    pub fn sample(&self, agents: usize, rng: &mut ChaCha12Rng) -> InitialState {
+        let mut positions = Vec::with_capacity(agents * self.dim);
+        for _ in 0..agents {
+            positions.extend(self.sample_position(rng));
+        }
+
+        let phases = (0..agents).map(|_| rng.gen_range(0.0..2.0 * PI)).collect();
+
+        let (center, width) = (self.frequency_center, self.frequency_width);
+        let natural_frequencies = (0..agents)
+            .map(|_| match self.frequencies {
+                FrequencyDistribution::Uniform => center + width * rng.gen_range(-1.0..=1.0),
+                FrequencyDistribution::Normal => {
+                    let z: f64 = rng.sample(StandardNormal);
+                    center + width * z
+                }
+                FrequencyDistribution::Lorentzian => {
+                    center + width * (PI * (rng.gen::<f64>() - 0.5)).tan()
+                }
+                FrequencyDistribution::Bimodal => {
+                    if rng.gen_bool(0.5) {
+                        center + width
+                    } else {
+                        center - width
+                    }
+                }
+            })
+            .collect();
+
+        let chiral = (self.chirality != 0.0).then(|| {
+            (0..agents)
+                .map(|_| {
+                    if rng.gen_bool(self.chiral_fraction) {
+                        self.chirality
+                    } else {
+                        -self.chirality
+                    }
+                })
+                .collect()
+        });
+
+        InitialState {
+            positions,
+            phases,
+            natural_frequencies,
+            chiral,
+        }
+    }
+
+    /// Draws one position, `dim` components, from the configured region.
+    fn sample_position(&self, rng: &mut ChaCha12Rng) -> Vec<f64> {
+        loop {
+            let point: Vec<f64> = (0..self.dim).map(|_| rng.gen_range(-1.0..1.0)).collect();
+
+            // Rejection sampling keeps the disk uniform without any trigonometry
+            let inside = match self.positions {
+                PositionDistribution::Box => true,
+                PositionDistribution::Disk => point.iter().map(|x| x * x).sum::<f64>() <= 1.0,
+            };
+            if inside {
+                return point.iter().map(|x| self.size * x).collect();
+            }
+        }
+    }
+}
@@ -1,17 +1,88 @@
+#![allow(non_snake_case)]
+
 extern crate rand;
 extern crate wasm_bindgen;
 extern crate web_sys;
 
+mod cluster;
+mod collision;
+mod colormap;
+mod config;
+mod diagnostics;
+mod engine;
+mod environment;
+mod error;
+mod events;
+mod formation;
+#[cfg(feature = "webgpu")]
+mod gpu;
+mod grid;
+mod init;
+mod params;
+mod presets;
+mod recording;
+mod schedule;
+#[cfg(feature = "wasm-simd")]
+mod simd;
+mod stats;
+mod topology;
 mod utils;
 use std::f64::consts::PI;
-use std::string::FromUtf8Error;
+use std::sync::OnceLock;
 use std::vec;
 
+pub use collision::CollisionKind;
+use collision::Collisions;
+pub use colormap::Colormap;
+pub use diagnostics::Diagnostics;
+use engine::norm;
+use environment::{Environment, Pointer};
+pub use environment::{FlowKind, PointerMode};
+pub use error::Error;
+use events::Watch;
+pub use events::{Crossing, Metric};
+pub use formation::AssignmentMethod;
+use formation::{Formation, MAX_OPTIMAL_AGENTS};
+#[cfg(feature = "webgpu")]
+pub use gpu::GpuPairwise;
+use grid::SpatialGrid;
+pub use init::{FrequencyDistribution, InitConfig, PositionDistribution};
+pub use presets::Preset;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+use rand_distr::StandardNormal;
+use recording::Recording;
+pub use recording::RecordingFormat;
+use schedule::Schedule;
+pub use schedule::{Interpolation, ScheduledParameter};
+use serde::{Deserialize, Serialize};
+use stats::RunningStats;
+use topology::Topology;
 use wasm_bindgen::prelude::*;
-use web_sys::js_sys::Math::cos;
-use web_sys::js_sys::Math::sin;
+/// Starts the web worker pool the `wasm-threads` feature steps on. Must be awaited
+/// once from JS, e.g. `await initThreadPool(navigator.hardwareConcurrency)`, before
+/// the first update.
+#[cfg(all(feature = "wasm-threads", target_arch = "wasm32"))]
+pub use wasm_bindgen_rayon::init_thread_pool;
+use web_sys::js_sys::Float64Array;
+
+/// Most steps `tick` takes at once before dropping the rest of the frame time.
+const MAX_TICK_STEPS: usize = 16;
+
+// Rust's own trig keeps the update loop from crossing into JS on every call and lets
+// the core build and run natively
+#[inline]
+fn cos(x: f64) -> f64 {
+    x.cos()
+}
+
+#[inline]
+fn sin(x: f64) -> f64 {
+    x.sin()
+}
 
 // A macro to provide `println!(..)`-style syntax for `console.log` logging.
+#[allow(unused_macros)]
 macro_rules! log {
     ( $( $t:tt )* ) => {
         web_sys::console::log_1(&format!( $( $t )* ).into());
@@ -22,31 +93,304 @@ macro_rules! log {
 ///
 /// # Fields
 /// - `agents`: Number of agents.
+/// - `dim`: Number of spatial dimensions, 2 or 3.
 /// - `A`, `B`: Coefficients for velocity contributions.
 /// - `K`, `J`: Coupling constants.
+/// - `K_vec`, `J_vec`: Optional per-agent coupling constants, used instead of `K` and `J`.
+/// - `K_matrix`, `J_matrix`: Optional per-pair coupling constants, row-major `agents × agents`.
+/// - `species`: Species id of each agent.
+/// - `species_coupling`: Coupling constants between each pair of species, if any.
 /// - `chiral`: Boolean indicating if the system is chiral.
-/// - `target`: Optional target positions.
+/// - `target`: Optional target positions, `dim` components each.
+/// - `target_assignment`: Index of the target each agent chases, or `None` to chase the nearest.
 /// - `inherent_velocities`: Inherent velocities of the agents.
 /// - `natural_frequencies`: Natural frequencies of the agents.
 /// - `c`: Additional constant values.
 /// - `velocities`: Current velocities of the agents.
 /// - `phases`: Current phases of the agents.
 /// - `delta_phases`: Changes in phases.
-/// - `positions`: Current positions of the agents.
+/// - `positions`: Current positions of the agents, `dim` components each.
+/// - `grid`: Cached spatial grid, built on the first query after the positions change.
+/// - `grid_enabled`: Whether queries use the grid without a cutoff set.
+/// - `frequency_adaptation`: Rate at which natural frequencies drift toward the instantaneous frequencies.
+/// - `frequency_gradient`: Spatial gradient `(gx, gy)` added to the natural frequencies.
+/// - `fix_center_of_mass`: Whether the centre of mass is held fixed every step.
+/// - `phase_target`: Phase `(phase, strength)` that agents near the target are entrained to.
+/// - `phase_targets`: Per-agent target phases and the strength pulling agents toward them.
+/// - `phase_repulsion_coupling`: Gain of the phase dependence of the repulsion.
+/// - `phase_harmonic`: Harmonic `n` of the phase coupling `sin(n(φ_j - φ_i))`.
+/// - `integration_scheme`: How positions and phases are stepped relative to each other.
+/// - `min_distance`: Separation below which pairwise distances are clamped.
+/// - `integrator`: Method used to integrate each step.
+/// - `boundary`: Boundary conditions at the edge of the arena.
+/// - `arena_size`: Side length of the box, or radius of the circle, for the boundary.
+/// - `confinement_stiffness`: Strength of the soft circular confinement.
+/// - `cutoff`: Interaction radius beyond which agents don't interact, if any.
+/// - `pinned`: Whether each agent is pinned in place.
+/// - `noise`: Stochastic noise added to positions and phases, if any.
+/// - `repulsion_exponent`: Power `p` of the distance in the repulsion `B * Δx / |Δx|^p`.
+/// - `attraction_exponent`: Power of the distance in the attraction `(A + J cos(Δφ)) * Δx / |Δx|^p`.
+/// - `phase_coupling_exponent`: Power of the distance in the phase coupling `K sin(Δφ) / |Δx|^p`.
+/// - `phase_lag`: Sakaguchi phase lag `α` in the phase coupling `sin(Δφ - α)`.
+/// - `far_field`: Whether agents beyond the cutoff interact through cell aggregates.
+/// - `tolerance`: Error tolerance of the adaptive integrator.
+/// - `environment`: Obstacles and flow fields acting on the agents.
+/// - `recording`: Trajectory recording, if one was started.
+/// - `time`: Simulation time elapsed over every step taken.
+/// - `schedules`: Keyframed schedules driving scalar parameters over time.
+/// - `topology`: Interaction graph restricting which agents couple, if any.
+/// - `collisions`: Body radius and collision response of the agents, if they have bodies.
+/// - `max_speed`: Speed each agent's velocity is clamped to, if any.
+/// - `formation`: Target shape the agents are pulled into, one point each, if any.
+/// - `pointer`: Force from a pointer held over the arena, if any.
+/// - `watches`: Conditions checked after every step that fire JS callbacks.
+/// - `pairwise`: GPU pairwise sums the step in progress uses in place of its own.
+/// - `fixed_timestep`: Step size `tick` advances by, if ticking at a fixed rate.
+/// - `accumulator`: Time passed to `tick` that hasn't been stepped through yet.
+/// - `previous_positions`: Positions before the latest step, for interpolated rendering.
+/// - `mean_field`: Whether agents beyond the cutoff interact through one global aggregate.
+/// - `softening`: Length `ε` added in quadrature to pairwise distances.
+/// - `nan_policy`: What a step does when it produces non-finite values.
+/// - `max_displacement`: Furthest an agent may move per substep, if steps are split.
+/// - `averaging`: Running statistics of the order parameters `[R, S+, S-]`, if averaging.
 #[wasm_bindgen]
+#[derive(Serialize, Deserialize)]
 pub struct Swarmalator {
     agents: usize,
+    #[serde(default = "default_dim")]
+    dim: usize,
     A: f64,
     B: f64,
     K: f64,
     J: f64,
+    #[serde(default)]
+    K_vec: Option<Vec<f64>>,
+    #[serde(default)]
+    J_vec: Option<Vec<f64>>,
+    #[serde(default)]
+    K_matrix: Option<Vec<f64>>,
+    #[serde(default)]
+    J_matrix: Option<Vec<f64>>,
+    #[serde(default)]
+    species: Vec<u32>,
+    #[serde(default)]
+    species_coupling: Option<SpeciesCoupling>,
     target: Option<Vec<f64>>,
+    #[serde(default = "default_target_gain")]
+    target_gain: f64,
+    #[serde(default)]
+    target_assignment: Option<Vec<usize>>,
     natural_frequencies: Vec<f64>,
     chiral: Option<Vec<f64>>,
     velocities: Vec<f64>,
     phases: Vec<f64>,
     delta_phases: Vec<f64>,
     positions: Vec<f64>,
+    #[serde(skip)]
+    grid: OnceLock<SpatialGrid>,
+    grid_enabled: bool,
+    frequency_adaptation: f64,
+    frequency_gradient: (f64, f64),
+    fix_center_of_mass: bool,
+    phase_target: (f64, f64),
+    phase_targets: Option<(Vec<f64>, f64)>,
+    phase_repulsion_coupling: f64,
+    phase_harmonic: u32,
+    integration_scheme: Scheme,
+    min_distance: f64,
+    integrator: IntegratorKind,
+    #[serde(default)]
+    boundary: Boundary,
+    #[serde(default)]
+    arena_size: f64,
+    #[serde(default)]
+    confinement_stiffness: f64,
+    cutoff: Option<f64>,
+    #[serde(default)]
+    pinned: Vec<bool>,
+    #[serde(default)]
+    noise: Option<Noise>,
+    #[serde(default = "default_repulsion_exponent")]
+    repulsion_exponent: f64,
+    #[serde(default = "default_unit_exponent")]
+    attraction_exponent: f64,
+    #[serde(default = "default_unit_exponent")]
+    phase_coupling_exponent: f64,
+    #[serde(default)]
+    phase_lag: f64,
+    #[serde(default)]
+    far_field: bool,
+    #[serde(default = "default_tolerance")]
+    tolerance: f64,
+    #[serde(default)]
+    environment: Environment,
+    #[serde(skip)]
+    recording: Option<Recording>,
+    #[serde(default)]
+    time: f64,
+    #[serde(default)]
+    schedules: Vec<Schedule>,
+    #[serde(default)]
+    topology: Option<Topology>,
+    #[serde(default)]
+    collisions: Option<Collisions>,
+    #[serde(default)]
+    max_speed: Option<f64>,
+    #[serde(default)]
+    formation: Option<Formation>,
+    #[serde(skip)]
+    pointer: Option<Pointer>,
+    #[serde(skip)]
+    watches: Vec<Watch>,
+    #[cfg(feature = "webgpu")]
+    #[serde(skip)]
+    pairwise: Option<Vec<f32>>,
+    #[serde(default)]
+    fixed_timestep: Option<f64>,
+    #[serde(skip)]
+    accumulator: f64,
+    #[serde(skip)]
+    previous_positions: Vec<f64>,
+    #[serde(default)]
+    mean_field: bool,
+    #[serde(default)]
+    softening: f64,
+    #[serde(default)]
+    nan_policy: NanPolicy,
+    #[serde(default)]
+    max_displacement: Option<f64>,
+    averaging: Option<[RunningStats; 3]>,
+}
+
+/// Gaussian noise added to the positions and phases every step.
+#[derive(Clone, Serialize, Deserialize)]
+struct Noise {
+    position_sigma: f64,
+    phase_sigma: f64,
+    #[serde(default)]
+    seed: u64,
+    rng: ChaCha12Rng,
+}
+
+/// Coupling constants between species, each a row-major `count × count` matrix
+/// whose entry `[a * count + b]` applies to an agent of species `a` interacting
+/// with one of species `b`.
+#[derive(Clone, Serialize, Deserialize)]
+struct SpeciesCoupling {
+    count: usize,
+    A: Vec<f64>,
+    B: Vec<f64>,
+    J: Vec<f64>,
+    K: Vec<f64>,
+}
+
+/// Ordering of the position and phase updates within a step.
+///
+/// - `Explicit`: positions and phases both advance using derivatives evaluated at
+///   the start of the step.
+/// - `SemiImplicit`: positions advance first using derivatives at the start of the
+///   step, then the phase velocities are re-evaluated at the new positions (with
+///   the old phases) and the phases advance. This staggering damps oscillations in
+///   stiff regimes at the cost of a small bias and a second force evaluation.
+#[wasm_bindgen]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Scheme {
+    Explicit,
+    SemiImplicit,
+}
+
+/// Boundary conditions at the edge of the arena.
+///
+/// - `Open`: an unbounded domain.
+/// - `Periodic`: a periodic (toroidal) box `[0, size)` in every dimension. Pairwise
+///   separations and distances to targets use the minimum-image convention.
+/// - `Reflective`: hard walls enclosing the box `[0, size]` in every dimension.
+///   Agents that cross a wall during a step are mirrored back inside.
+/// - `SoftCircular`: a circle (sphere in 3D) of radius `size` about the origin.
+///   Agents outside it gain a velocity `stiffness * (size - |x|)` along `x / |x|`,
+///   pulling them back in without a hard edge.
+#[wasm_bindgen]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub enum Boundary {
+    #[default]
+    Open,
+    Periodic,
+    Reflective,
+    SoftCircular,
+}
+
+/// What a step does when it leaves agents with non-finite positions, phases or
+/// velocities, e.g. after a close encounter with a large time step.
+///
+/// - `Ignore`: nothing. The values spread to every other agent within a step.
+/// - `Error`: the step is undone, down to scheduled parameters, the topology and the
+///   noise generator, and `update` returns an error, so the caller can retry with a
+///   smaller time step or different parameters.
+/// - `Reset`: the offending agents go back to their position and phase from before
+///   the step and stop moving, and the step carries on.
+#[wasm_bindgen]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub enum NanPolicy {
+    #[default]
+    Ignore,
+    Error,
+    Reset,
+}
+
+/// Method used to integrate each step.
+///
+/// - `Euler`: a single forward Euler step, ordered according to the `Scheme`.
+/// - `Rk4`: classic fourth-order Runge-Kutta, evaluating the derivatives (including
+///   the target-based `J` rescaling) four times per step. The `Scheme` is ignored.
+/// - `Rk45`: adaptive Dormand-Prince Runge-Kutta 5(4). Each step is split into as
+///   many substeps as needed to keep the estimated local error within the
+///   tolerance, so large steps stay stable in stiff regimes. The `Scheme` is ignored.
+///   Like the fixed-step methods, a zero `dt` leaves the state where it is and a
+///   negative `dt` integrates backwards in time.
+#[wasm_bindgen]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum IntegratorKind {
+    Euler,
+    Rk4,
+    Rk45,
+}
+
+/// Summary of a single step, returned by `update_with_report`.
+///
+/// - `max_velocity`: Largest speed `|v_i|` of any agent during the step.
+/// - `mean_abs_delta_phase`: Mean of `|dφ_i/dt|` over the agents.
+/// - `diverged`: Whether any position or phase is non-finite after the step.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug)]
+pub struct StepReport {
+    pub max_velocity: f64,
+    pub mean_abs_delta_phase: f64,
+    pub diverged: bool,
+}
+
+/// Snapshot of a single agent, returned by `get_agent`.
+///
+/// - `x`, `y`, `z`: Position, with `z` zero in 2D.
+/// - `vx`, `vy`, `vz`: Velocity from the last step, with `vz` zero in 2D.
+/// - `phase`: Phase.
+/// - `omega`: Natural frequency.
+/// - `chiral`: Chiral value, zero if the system isn't chiral.
+/// - `species`: Species id.
+/// - `pinned`: Whether the agent is pinned.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug)]
+pub struct AgentInfo {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub vx: f64,
+    pub vy: f64,
+    pub vz: f64,
+    pub phase: f64,
+    pub omega: f64,
+    pub chiral: f64,
+    pub species: u32,
+    pub pinned: bool,
 }
 
 #[wasm_bindgen]
@@ -63,9 +407,11 @@ impl Swarmalator {
     /// - `chiral`: Optional chiral values
     /// - `target`: Optional target positions.
     ///
-    /// # Panics
-    /// Panics if the length of `positions` is not equal to `2 * agents`.
+    /// # Errors
+    /// Returns an error if the length of `positions` is not equal to `2 * agents`,
+    /// the other arrays don't match the number of agents, or any value is not finite.
     #[wasm_bindgen(constructor)]
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         agents: usize,
         positions: Vec<f64>,
@@ -75,212 +421,3144 @@ impl Swarmalator {
         J: f64,
         chiral: Option<Vec<f64>>,
         target: Option<Vec<f64>>,
-    ) -> Swarmalator {
-        utils::set_panic_hook();
+    ) -> Result<Swarmalator, Error> {
+        Swarmalator::with_dimension(
+            2,
+            agents,
+            positions,
+            phases,
+            natural_frequencies,
+            K,
+            J,
+            chiral,
+            target,
+        )
+    }
 
-        // Check the length of the arrays
-        if positions.len() != agents * 2 {
-            panic!("Positions array must have 2 * agents elements")
+    /// Creates a new three-dimensional Swarmalator instance.
+    ///
+    /// Positions and velocities have three components per agent and every pairwise
+    /// term uses 3D separations. The chiral velocity, being a rotation of the phase,
+    /// acts in the xy-plane.
+    ///
+    /// # Arguments
+    /// - `agents`: Number of agents.
+    /// - `positions`: Initial positions of the agents.
+    /// - `phases`: Initial phases of the agents.
+    /// - `natural_frequencies`: Natural frequencies of the agents.
+    /// - `K`: Phase coupling coefficient
+    /// - `J`: Spatial-phase interaction coefficient
+    /// - `chiral`: Optional chiral values
+    /// - `target`: Optional target positions.
+    ///
+    /// # Errors
+    /// Returns an error if the length of `positions` is not equal to `3 * agents`,
+    /// the other arrays don't match the number of agents, or any value is not finite.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new_3d(
+        agents: usize,
+        positions: Vec<f64>,
+        phases: Vec<f64>,
+        natural_frequencies: Vec<f64>,
+        K: f64,
+        J: f64,
+        chiral: Option<Vec<f64>>,
+        target: Option<Vec<f64>>,
+    ) -> Result<Swarmalator, Error> {
+        Swarmalator::with_dimension(
+            3,
+            agents,
+            positions,
+            phases,
+            natural_frequencies,
+            K,
+            J,
+            chiral,
+            target,
+        )
+    }
+
+    /// Creates a Swarmalator with randomly initialised agents from a seed.
+    ///
+    /// Positions are uniform in the box `[-1, 1]²`, phases uniform in `[0, 2π)` and
+    /// natural frequencies uniform in `[-frequency_spread, frequency_spread]`. The
+    /// generator is `ChaCha12Rng` seeded with `seed`, so the same seed produces the same
+    /// system on every platform.
+    ///
+    /// # Arguments
+    /// - `agents`: Number of agents.
+    /// - `seed`: Seed for the random number generator.
+    /// - `K`: Phase coupling coefficient
+    /// - `J`: Spatial-phase interaction coefficient
+    /// - `frequency_spread`: Half-width of the natural frequency distribution.
+    ///
+    /// # Errors
+    /// Returns an error if `K`, `J` or `frequency_spread` is not finite.
+    pub fn random(
+        agents: usize,
+        seed: u64,
+        K: f64,
+        J: f64,
+        frequency_spread: f64,
+    ) -> Result<Swarmalator, Error> {
+        Swarmalator::random_with_dimension(2, agents, seed, K, J, frequency_spread)
+    }
+
+    /// Creates a three-dimensional Swarmalator with randomly initialised agents from
+    /// a seed.
+    ///
+    /// Like `random`, but with positions uniform in the cube `[-1, 1]³`.
+    ///
+    /// # Arguments
+    /// - `agents`: Number of agents.
+    /// - `seed`: Seed for the random number generator.
+    /// - `K`: Phase coupling coefficient
+    /// - `J`: Spatial-phase interaction coefficient
+    /// - `frequency_spread`: Half-width of the natural frequency distribution.
+    ///
+    /// # Errors
+    /// Returns an error if `K`, `J` or `frequency_spread` is not finite.
+    pub fn random_3d(
+        agents: usize,
+        seed: u64,
+        K: f64,
+        J: f64,
+        frequency_spread: f64,
+    ) -> Result<Swarmalator, Error> {
+        Swarmalator::random_with_dimension(3, agents, seed, K, J, frequency_spread)
+    }
+
+    /// Creates a Swarmalator with agents drawn from the distributions in `config`.
+    ///
+    /// Positions are drawn first, then phases, natural frequencies and chiral values,
+    /// all from `ChaCha12Rng` seeded with `seed`, so the same seed and config produce the
+    /// same system on every platform.
+    ///
+    /// # Arguments
+    /// - `agents`: Number of agents.
+    /// - `seed`: Seed for the random number generator.
+    /// - `K`: Phase coupling coefficient
+    /// - `J`: Spatial-phase interaction coefficient
+    /// - `config`: Distributions to draw the agents from.
+    ///
+    /// # Errors
+    /// Returns an error if `config.dim` is not 2 or 3, any parameter of `config` is
+    /// out of range, or `K` or `J` is not finite.
+    pub fn random_with_config(
+        agents: usize,
+        seed: u64,
+        K: f64,
+        J: f64,
+        config: &InitConfig,
+    ) -> Result<Swarmalator, Error> {
+        if config.dim != 2 && config.dim != 3 {
+            return Err(Error::new("Dimension must be 2 or 3"));
         }
+        config.validate()?;
 
-        if phases.len() != agents {
-            panic!("Phases array must have agents elements")
+        let initial = config.sample(agents, &mut ChaCha12Rng::seed_from_u64(seed));
+
+        Swarmalator::with_dimension(
+            config.dim,
+            agents,
+            initial.positions,
+            initial.phases,
+            initial.natural_frequencies,
+            K,
+            J,
+            initial.chiral,
+            None,
+        )
+    }
+
+    /// Creates a Swarmalator set up to reproduce one of the canonical states.
+    ///
+    /// Agents start as in `random`, with the couplings of `preset` and no spread in
+    /// natural frequencies, so the same seed always settles into the same state.
+    ///
+    /// # Arguments
+    /// - `preset`: State to reproduce.
+    /// - `agents`: Number of agents.
+    /// - `seed`: Seed for the random number generator.
+    pub fn preset(preset: Preset, agents: usize, seed: u64) -> Result<Swarmalator, Error> {
+        let (K, J) = preset.couplings();
+        Swarmalator::random_with_dimension(2, agents, seed, K, J, 0.0)
+    }
+
+    /// Restores a Swarmalator saved with `to_bytes`.
+    ///
+    /// # Arguments
+    /// - `data`: Saved state.
+    ///
+    /// # Errors
+    /// Returns an error if `data` is not a valid saved state, including when its
+    /// arrays don't match the number of agents.
+    pub fn from_bytes(data: Vec<u8>) -> Result<Swarmalator, Error> {
+        let mut swarmalator: Swarmalator = serde_json::from_slice(&data)
+            .map_err(|e| Error::new(&format!("Invalid Swarmalator state: {}", e)))?;
+
+        // States saved before species existed have every agent in species 0
+        if swarmalator.species.is_empty() {
+            swarmalator.species = vec![0; swarmalator.agents];
         }
 
-        if natural_frequencies.len() != agents {
-            panic!("Natural frequencies array must have agents elements")
+        // States saved before pinning have no agents pinned
+        if swarmalator.pinned.is_empty() {
+            swarmalator.pinned = vec![false; swarmalator.agents];
         }
 
-        // All agents start stationary
-        let velocities: Vec<f64> = vec![0.0; agents * 2];
+        // States saved before other boundaries existed only stored the box size
+        let legacy: LegacyBoundary = serde_json::from_slice(&data)
+            .map_err(|e| Error::new(&format!("Invalid Swarmalator state: {}", e)))?;
+        if let (None, Some(size)) = (legacy.boundary, legacy.periodic) {
+            swarmalator.boundary = Boundary::Periodic;
+            swarmalator.arena_size = size;
+        }
 
-        // We store delta_phase so we get the dt from update
-        let delta_phases: Vec<f64> = vec![0.0; agents];
+        swarmalator
+            .validate()
+            .map_err(|e| Error::new(&format!("Invalid Swarmalator state: {}", e)))?;
 
-        // If we have a target, set it
-        let target: Option<Vec<f64>> = match target {
-            Some(t) => Some(t.clone()),
-            None => None,
-        };
+        swarmalator.positions_changed();
+        Ok(swarmalator)
+    }
 
-        Swarmalator {
-            agents,
-            A: 1.0,
-            B: 1.0,
-            K,
-            J,
-            chiral,
-            target,
-            natural_frequencies,
-            velocities,
-            phases,
-            delta_phases,
-            positions: positions.clone(),
+    /// Seeds the agents' velocities instead of starting them stationary.
+    ///
+    /// Intended to be chained onto the constructor, e.g. when resuming a saved run.
+    /// Since `update` recomputes the velocities from the current state, these are
+    /// only what `velocities` reports until the first step.
+    ///
+    /// # Arguments
+    /// - `velocities`: Initial velocities of the agents.
+    ///
+    /// # Errors
+    /// Returns an error if the length of `velocities` is not equal to `dim * agents`,
+    /// or any velocity is not finite.
+    pub fn with_velocities(mut self, velocities: Vec<f64>) -> Result<Swarmalator, Error> {
+        if velocities.len() != self.agents * self.dim {
+            return Err(Error::new(&format!(
+                "Velocities array must have {} * agents elements",
+                self.dim
+            )));
         }
+        check_all_finite("Velocities", &velocities)?;
+
+        self.velocities = velocities;
+        Ok(self)
     }
 
     /// Updates the state of the Swarmalator system.
     ///
     /// # Arguments
     /// - `dt`: Time step for the update.
-    pub fn update(&mut self, dt: f64) {
-        let mut Js = vec![self.J; self.agents];
+    ///
+    /// # Errors
+    /// Returns an error if `dt` is not finite, leaving the system unchanged, or if
+    /// the step diverges under `NanPolicy::Error`, leaving the system as it was
+    /// before the diverging (sub)step.
+    pub fn update(&mut self, dt: f64) -> Result<(), Error> {
+        check_finite("Time step", dt)?;
+        self.step(dt)?;
+        self.fire_events()
+    }
 
-        // If we have a target we need to recalculate the J values
-        if let Some(target) = self.target.as_ref() {
-            let mut dists_to_target = vec![0.0; self.agents];
-            for i in 0..self.agents {
-                dists_to_target[i] = ((self.positions[i * 2] - target[0]).powi(2)
-                    + (self.positions[i * 2 + 1] - target[1]).powi(2))
-                .sqrt();
-            }
+    /// Updates the state like `update` and reports on the step.
+    ///
+    /// Costs an extra pass over the agents, so prefer `update` unless the report
+    /// is needed, e.g. to abandon runs that blew up or to detect a steady state.
+    ///
+    /// # Arguments
+    /// - `dt`: Time step for the update.
+    ///
+    /// # Errors
+    /// Returns an error if `dt` is not finite, leaving the system unchanged.
+    pub fn update_with_report(&mut self, dt: f64) -> Result<StepReport, Error> {
+        self.update(dt)?;
 
-            let max_dist = dists_to_target.iter().fold(0.0 / 0.0, |m, v| v.max(m));
-            let min_dist = dists_to_target.iter().fold(0.0 / 0.0, |m, v| v.min(m));
+        let max_velocity = (0..self.agents)
+            .map(|i| {
+                let v = self.position(&self.velocities, i);
+                v.iter().map(|v| v * v).sum::<f64>().sqrt()
+            })
+            // Let a NaN speed through rather than hiding it
+            .fold(0.0, |max, speed| {
+                if speed > max || speed.is_nan() {
+                    speed
+                } else {
+                    max
+                }
+            });
 
-            for i in 0..self.agents {
-                Js[i] = self.A * f64::abs(dists_to_target[i] - min_dist) / (max_dist - min_dist);
-            }
+        let mean_abs_delta_phase = if self.agents == 0 {
+            0.0
+        } else {
+            self.delta_phases.iter().map(|w| w.abs()).sum::<f64>() / self.agents as f64
+        };
+
+        let diverged = self
+            .positions
+            .iter()
+            .chain(&self.phases)
+            .any(|x| !x.is_finite());
+
+        Ok(StepReport {
+            max_velocity,
+            mean_abs_delta_phase,
+            diverged,
+        })
+    }
+
+    /// Runs `steps` updates in a row without returning to JS.
+    ///
+    /// Identical to calling `update(dt)` `steps` times.
+    ///
+    /// # Arguments
+    /// - `steps`: Number of steps to take.
+    /// - `dt`: Time step for each update.
+    ///
+    /// # Errors
+    /// Returns an error if `dt` is not finite, leaving the system unchanged, or if a
+    /// step diverges under `NanPolicy::Error`, stopping there.
+    pub fn step_many(&mut self, steps: usize, dt: f64) -> Result<(), Error> {
+        check_finite("Time step", dt)?;
+        for _ in 0..steps {
+            self.step(dt)?;
+            self.fire_events()?;
         }
 
-        for i in 0..self.agents {
-            if let Some(chiral) = self.chiral.as_ref() {
-                self.velocities[i * 2] = chiral[i] * cos(self.phases[i] + PI / 2.0);
-                self.velocities[i * 2 + 1] = chiral[i] * sin(self.phases[i] + PI / 2.0);
-            } else {
-                self.velocities[i * 2] = 0.0;
-                self.velocities[i * 2 + 1] = 0.0;
-            }
+        Ok(())
+    }
+
+    /// Advances the system by `total_time` in equal substeps no longer than `max_dt`.
+    ///
+    /// Useful to keep the simulation in step with wall-clock time, e.g. passing
+    /// the time since the last animation frame, or to fast-forward to a steady
+    /// state, without one JS call per step. Use `step_many` for a fixed number of
+    /// steps instead.
+    ///
+    /// # Arguments
+    /// - `total_time`: Time to advance by. Nothing happens unless it is positive.
+    /// - `max_dt`: Largest allowed substep.
+    ///
+    /// # Returns
+    /// The number of substeps taken.
+    ///
+    /// # Errors
+    /// Returns an error if `total_time` is not finite or `max_dt` is not positive.
+    pub fn advance(&mut self, total_time: f64, max_dt: f64) -> Result<usize, Error> {
+        check_finite("Total time", total_time)?;
+        if max_dt.is_nan() || max_dt <= 0.0 {
+            return Err(Error::new("Maximum time step must be positive"));
+        }
 
-            // Natural frequnecy always contributes to delta phase
-            self.delta_phases[i] = self.natural_frequencies[i];
+        if total_time <= 0.0 {
+            return Ok(0);
+        }
 
-            for j in 0..self.agents {
-                if i == j {
-                    continue;
-                }
+        let steps = (total_time / max_dt).ceil() as usize;
+        self.step_many(steps, total_time / steps as f64)?;
+        Ok(steps)
+    }
 
-                let dist: f64 = ((self.positions[i * 2] - self.positions[j * 2]).powi(2)
-                    + (self.positions[i * 2 + 1] - self.positions[j * 2 + 1]).powi(2))
-                .sqrt();
+    /// Set the fixed time step `tick` advances by.
+    ///
+    /// Running the physics at a fixed rate keeps it independent of the display's
+    /// refresh rate: pass each frame's duration to `tick` and draw
+    /// `render_positions(render_alpha())`. Discards any time left over from earlier
+    /// ticks.
+    /// # Arguments
+    /// - `dt`: Time step of each update, or `None` to stop ticking at a fixed rate.
+    /// # Errors
+    /// Returns an error if `dt` is not positive and finite.
+    pub fn set_fixed_timestep(&mut self, dt: Option<f64>) -> Result<(), Error> {
+        if let Some(dt) = dt {
+            if !dt.is_finite() || dt <= 0.0 {
+                return Err(Error::new("Fixed time step must be positive and finite"));
+            }
+        }
 
-                // We may have frequency coupling
-                let mut freq_diff_xy: f64 = 0.0;
-                let mut freq_diff_phase: f64 = 0.0;
+        self.fixed_timestep = dt;
+        self.accumulator = 0.0;
+        Ok(())
+    }
 
-                if self.chiral.is_some() {
-                    freq_diff_xy = (PI / 2.0)
-                        * f64::abs(
-                            self.natural_frequencies[j] / f64::abs(self.natural_frequencies[j])
-                                - self.natural_frequencies[i]
-                                    / f64::abs(self.natural_frequencies[i]),
-                        );
+    /// Returns the fixed time step `tick` advances by, if one is set.
+    pub fn fixed_timestep(&self) -> Option<f64> {
+        self.fixed_timestep
+    }
 
-                    freq_diff_phase = freq_diff_xy / 2.0;
-                }
+    /// Adds `frame_time` to the time waiting to be simulated and takes as many fixed
+    /// time steps as fit into it, keeping the remainder for the next tick.
+    ///
+    /// At most 16 steps are taken per tick and any time beyond
+    /// them is dropped, so a long stall (e.g. a background tab) slows the simulation
+    /// down instead of freezing the page while it catches up.
+    /// # Arguments
+    /// - `frame_time`: Time since the last tick, in simulation time units.
+    /// # Returns
+    /// The number of steps taken.
+    /// # Errors
+    /// Returns an error if no fixed time step is set or `frame_time` is negative or
+    /// not finite.
+    pub fn tick(&mut self, frame_time: f64) -> Result<usize, Error> {
+        let Some(dt) = self.fixed_timestep else {
+            return Err(Error::new("No fixed time step is set"));
+        };
+        check_finite("Frame time", frame_time)?;
+        if frame_time < 0.0 {
+            return Err(Error::new("Frame time must not be negative"));
+        }
 
-                let velocity_contribution_x: f64 =
-                    ((self.positions[j * 2] - self.positions[i * 2]) / dist)
-                        * (self.A + Js[i] * cos(self.phases[j] - self.phases[i] - freq_diff_xy))
-                        - (self.B * (self.positions[j * 2] - self.positions[i * 2]) / dist.powi(2));
+        self.accumulator += frame_time;
+        let steps = ((self.accumulator / dt).floor() as usize).min(MAX_TICK_STEPS);
+        self.accumulator %= dt;
+        self.step_many(steps, dt)?;
 
-                let velocity_contribution_y: f64 =
-                    ((self.positions[j * 2 + 1] - self.positions[i * 2 + 1]) / dist)
-                        * (self.A + Js[i] * cos(self.phases[j] - self.phases[i] - freq_diff_xy))
-                        - (self.B * (self.positions[j * 2 + 1] - self.positions[i * 2 + 1])
-                            / dist.powi(2));
+        Ok(steps)
+    }
 
-                self.velocities[i * 2] += (1.0 / self.agents as f64) * velocity_contribution_x;
-                self.velocities[i * 2 + 1] += (1.0 / self.agents as f64) * velocity_contribution_y;
+    /// Returns how far the time left over from `tick` reaches into the next step, in
+    /// `[0, 1]`, for `render_positions`. Always 1 without a fixed time step.
+    pub fn render_alpha(&self) -> f64 {
+        match self.fixed_timestep {
+            Some(dt) => (self.accumulator / dt).clamp(0.0, 1.0),
+            None => 1.0,
+        }
+    }
 
-                self.delta_phases[i] += (self.K / (self.agents as f64))
-                    * sin(self.phases[j] - self.phases[i] - freq_diff_phase)
-                    / dist;
-            }
+    /// Returns the positions interpolated between the states before and after the
+    /// latest step, `previous + alpha * (current - previous)`.
+    ///
+    /// Drawing the positions at `render_alpha()` trails the simulation by less than
+    /// a step but moves smoothly whatever the frame rate. With periodic boundaries
+    /// agents are interpolated across the nearest image and may be drawn just
+    /// outside the box as they wrap. Before the first step, or right after agents
+    /// are added or removed, the current positions are returned.
+    /// # Arguments
+    /// - `alpha`: Interpolation weight, clamped to `[0, 1]`.
+    /// # Errors
+    /// Returns an error if `alpha` is not finite.
+    pub fn render_positions(&self, alpha: f64) -> Result<Vec<f64>, Error> {
+        check_finite("Alpha", alpha)?;
+        if self.previous_positions.len() != self.positions.len() {
+            return Ok(self.positions.clone());
         }
 
+        let alpha = alpha.clamp(0.0, 1.0);
+        let mut positions = Vec::with_capacity(self.positions.len());
         for i in 0..self.agents {
-            self.phases[i] += self.delta_phases[i] * dt;
-            self.phases[i] = self.phases[i] % (2.0 * PI);
+            let previous = self.position(&self.previous_positions, i);
+            let d = self.displacement(previous, self.position(&self.positions, i));
+            for k in 0..self.dim {
+                positions.push(previous[k] + alpha * d[k]);
+            }
+        }
+
+        Ok(positions)
+    }
 
-            self.positions[i * 2] += self.velocities[i * 2] * dt;
-            self.positions[i * 2 + 1] += self.velocities[i * 2 + 1] * dt;
+    /// Starts recording the positions and phases, discarding any earlier recording.
+    ///
+    /// A snapshot is taken after every `interval`th `update` into a ring buffer
+    /// holding the latest `capacity` snapshots, so long runs keep a bounded amount
+    /// of memory. Snapshots copy the agent arrays inside WASM, which is much
+    /// cheaper than copying them out to JS every frame. Recordings aren't included
+    /// in saved states.
+    /// # Arguments
+    /// - `interval`: Number of steps between snapshots.
+    /// - `capacity`: Maximum number of snapshots kept, 1000 by default.
+    /// # Errors
+    /// Returns an error if `interval` or `capacity` is zero.
+    pub fn start_recording(
+        &mut self,
+        interval: usize,
+        capacity: Option<usize>,
+    ) -> Result<(), Error> {
+        let capacity = capacity.unwrap_or(1000);
+        if interval == 0 || capacity == 0 {
+            return Err(Error::new(
+                "Recording interval and capacity must be positive",
+            ));
         }
+
+        self.recording = Some(Recording::new(self.dim, interval, capacity));
+
+        Ok(())
     }
 
-    /// Returns a pointer to the velocities array.
-    pub fn velocities(&self) -> *const f64 {
-        self.velocities.as_ptr()
+    /// Stops recording. The snapshots taken so far are kept for `export_recording`.
+    pub fn stop_recording(&mut self) {
+        if let Some(recording) = self.recording.as_mut() {
+            recording.stop();
+        }
     }
 
-    /// Returns a pointer to the phases array.
-    pub fn phases(&self) -> *const f64 {
-        self.phases.as_ptr()
+    /// Returns whether a recording is capturing steps.
+    pub fn is_recording(&self) -> bool {
+        self.recording.as_ref().is_some_and(Recording::is_active)
     }
 
-    /// Returns a pointer to the positions array.
-    pub fn positions(&self) -> *const f64 {
-        self.positions.as_ptr()
+    /// Returns the number of snapshots in the recording.
+    pub fn recorded_frames(&self) -> usize {
+        self.recording.as_ref().map_or(0, Recording::len)
     }
 
-    /// Update the target position.
+    /// Exports the recorded snapshots, oldest first, for offline analysis. Each
+    /// snapshot has the time since recording started. See `RecordingFormat` for
+    /// the layouts. The recording is left intact.
     /// # Arguments
-    /// - `target`: New target position.
-    /// # Panics
-    /// Panics if the length of `target` is not equal to 2.
-    pub fn set_target(&mut self, target: Vec<f64>) {
-        if target.len() != 2 {
-            panic!("Target array must have 2 elements")
+    /// - `format`: Format to export in.
+    /// # Returns
+    /// The encoded recording, empty of snapshots if nothing was recorded.
+    pub fn export_recording(&self, format: RecordingFormat) -> Vec<u8> {
+        match self.recording.as_ref() {
+            Some(recording) => recording.export(format),
+            None => Recording::new(self.dim, 1, 1).export(format),
         }
+    }
 
-        self.target = Some(target);
+    /// Discards the recording and its snapshots.
+    pub fn clear_recording(&mut self) {
+        self.recording = None;
     }
 
-    /// Set the phase coupling coefficient.
-    /// # Arguments
-    /// - `K`: New value for K
-    pub fn set_K(&mut self, K: f64) {
-        self.K = K;
+    /// Rebuilds the cached spatial grid from the current positions.
+    ///
+    /// While a cutoff is set, neighbour queries already share a grid that is built
+    /// on the first query after each step or other change to the positions. Calling
+    /// this uses the grid for queries without a cutoff too, and builds it now rather
+    /// than on the next query.
+    pub fn rebuild_grid(&mut self) {
+        self.grid_enabled = true;
+        self.positions_changed();
+        self.cached_grid();
     }
 
-    /// Set the spatial-phase interaction coefficient.
-    /// # Arguments
-    /// - `J`: New value for J
-    pub fn set_J(&mut self, J: f64) {
-        self.J = J;
+    /// Returns the indices of all agents within `radius` of `(x, y)`.
+    ///
+    /// Uses the cached spatial grid when one is available, otherwise checks every agent.
+    /// In 3D the point lies in the plane `z = 0`.
+    pub fn agents_within(&self, x: f64, y: f64, radius: f64) -> Vec<u32> {
+        let mut found = Vec::new();
+        self.for_each_within(&[x, y, 0.0][..self.dim], radius, |i| found.push(i as u32));
+        found
     }
 
-    /// Set the chiral values.
-    /// # Arguments
-    /// - `chiral`: New chiral values.
-    pub fn set_chiral(&mut self, chiral: Option<Vec<f64>>) {
-        self.chiral = chiral;
+    /// Saves the full state of the system, parameters and agents, as JSON.
+    ///
+    /// Restoring it with `from_bytes` gives an identical system, with every `f64`
+    /// preserved exactly. States containing non-finite values can't be restored.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        serde_json::to_vec(self).expect("Swarmalator state is always serializable")
     }
 
-    /// Set the natural frequencies.
+    /// Saves the full state of the system as a JSON string.
+    ///
+    /// The same state as `to_bytes`, as a string that can be stored in IndexedDB
+    /// or `localStorage`, or URI-encoded to share a configuration as a link.
+    pub fn save_state(&self) -> String {
+        serde_json::to_string(self).expect("Swarmalator state is always serializable")
+    }
+
+    /// Replaces this system with a state saved by `save_state`.
+    ///
+    /// Pointers and views into the agent arrays become invalid. On error the system
+    /// is left unchanged.
     /// # Arguments
-    /// - `natural_frequencies`: New natural frequencies.
-    /// # Panics
-    /// Panics if the length of `natural_frequencies` is not equal to the number of agents.
-    pub fn set_natural_frequencies(&mut self, natural_frequencies: Vec<f64>) {
-        if natural_frequencies.len() != self.agents {
-            panic!("Natural frequencies array must have agents elements")
-        }
+    /// - `state`: Saved state.
+    /// # Errors
+    /// Returns an error if `state` is not a valid saved state.
+    pub fn load_state(&mut self, state: &str) -> Result<(), Error> {
+        *self = Swarmalator::from_bytes(state.as_bytes().to_vec())?;
+        Ok(())
+    }
 
-        self.natural_frequencies = natural_frequencies;
+    /// Returns a summary of the parameters, without any of the dynamical state.
+    ///
+    /// The fields are, in order:
+    /// 0. `agents`
+    /// 1. `A`
+    /// 2. `B`
+    /// 3. `K`
+    /// 4. `J`
+    /// 5. `1.0` if chiral values are set, otherwise `0.0`
+    /// 6. `1.0` if a target is set, otherwise `0.0`
+    /// 7. `min_distance`
+    /// 8. `dim`
+    /// 9. `phase_lag`
+    /// 10. `softening`
+    pub fn config(&self) -> Vec<f64> {
+        vec![
+            self.agents as f64,
+            self.A,
+            self.B,
+            self.K,
+            self.J,
+            if self.chiral.is_some() { 1.0 } else { 0.0 },
+            if self.target.is_some() { 1.0 } else { 0.0 },
+            self.min_distance,
+            self.dim as f64,
+            self.phase_lag,
+            self.softening,
+        ]
     }
 
-    /// Set the phases
-    /// # Arguments
-    /// - `phases`: New phases.
+    /// Returns the simulation time, the sum of the time steps taken so far.
     ///
-    /// # Panics
-    /// Panics if the length of `phases` is not equal to the number of agents.
-    pub fn set_phases(&mut self, phases: Vec<f64>) {
-        if phases.len() != self.agents {
-            panic!("Phases array must have agents elements")
-        }
+    /// Saved and restored with the state, so schedules carry on where they left off.
+    pub fn time(&self) -> f64 {
+        self.time
+    }
 
-        self.phases = phases;
+    /// Returns the number of agents.
+    ///
+    /// Changes when agents are added, removed or merged in, so read it again
+    /// rather than caching the count passed to the constructor.
+    pub fn agents(&self) -> usize {
+        self.agents
     }
+
+    /// Returns the number of spatial dimensions, 2 or 3.
+    ///
+    /// Positions and velocities have this many components per agent.
+    pub fn dim(&self) -> usize {
+        self.dim
+    }
+
+    /// Returns a pointer to the velocities array.
+    ///
+    /// The pointer aliases the internal buffer. It is invalidated whenever the
+    /// buffer is reallocated (e.g. by a setter or a change in the number of agents)
+    /// or WASM memory grows, so views over it must be recreated after any such call.
+    /// Prefer `velocities_vec` unless the copy is too slow.
+    pub fn velocities(&self) -> *const f64 {
+        self.velocities.as_ptr()
+    }
+
+    /// Returns a copy of the velocities array.
+    pub fn velocities_vec(&self) -> Vec<f64> {
+        self.velocities.clone()
+    }
+
+    /// Returns a pointer to the phases array.
+    ///
+    /// The pointer aliases the internal buffer. It is invalidated whenever the
+    /// buffer is reallocated (e.g. by a setter or a change in the number of agents)
+    /// or WASM memory grows, so views over it must be recreated after any such call.
+    /// Prefer `phases_vec` unless the copy is too slow.
+    pub fn phases(&self) -> *const f64 {
+        self.phases.as_ptr()
+    }
+
+    /// Returns a copy of the phases array.
+    pub fn phases_vec(&self) -> Vec<f64> {
+        self.phases.clone()
+    }
+
+    /// Returns a pointer to the positions array.
+    ///
+    /// The pointer aliases the internal buffer. It is invalidated whenever the
+    /// buffer is reallocated (e.g. by a setter or a change in the number of agents)
+    /// or WASM memory grows, so views over it must be recreated after any such call.
+    /// Prefer `positions_vec` unless the copy is too slow.
+    pub fn positions(&self) -> *const f64 {
+        self.positions.as_ptr()
+    }
+
+    /// Returns a copy of the positions array.
+    pub fn positions_vec(&self) -> Vec<f64> {
+        self.positions.clone()
+    }
+
+    /// Returns a `Float64Array` view over the velocities array.
+    ///
+    /// The view shares the internal buffer without copying, so it is invalidated
+    /// exactly like the pointer from `velocities`. Recreate it after any call that
+    /// may allocate, and never hold onto it across calls into the module.
+    pub fn velocities_view(&self) -> Float64Array {
+        // The view is only valid until WASM memory next changes, as documented
+        unsafe { Float64Array::view(&self.velocities) }
+    }
+
+    /// Returns a `Float64Array` view over the phases array.
+    ///
+    /// Invalidated like the view from `velocities_view`.
+    pub fn phases_view(&self) -> Float64Array {
+        // The view is only valid until WASM memory next changes, as documented
+        unsafe { Float64Array::view(&self.phases) }
+    }
+
+    /// Returns a `Float64Array` view over the positions array.
+    ///
+    /// Invalidated like the view from `velocities_view`.
+    pub fn positions_view(&self) -> Float64Array {
+        // The view is only valid until WASM memory next changes, as documented
+        unsafe { Float64Array::view(&self.positions) }
+    }
+
+    /// Returns the number of elements in the velocities array, `dim * agents`.
+    pub fn velocities_length(&self) -> usize {
+        self.velocities.len()
+    }
+
+    /// Returns the number of elements in the phases array, `agents`.
+    pub fn phases_length(&self) -> usize {
+        self.phases.len()
+    }
+
+    /// Returns the number of elements in the positions array, `dim * agents`.
+    pub fn positions_length(&self) -> usize {
+        self.positions.len()
+    }
+
+    /// Returns the colour of each agent's phase as packed RGBA bytes.
+    ///
+    /// Four bytes per agent in agent order, ready to upload as a normalised
+    /// `UNSIGNED_BYTE` vertex attribute or to read as `rgb(r, g, b)`. Alpha is
+    /// always 255.
+    ///
+    /// # Arguments
+    /// - `colormap`: Colormap from phase to colour.
+    pub fn colors(&self, colormap: Colormap) -> Vec<u8> {
+        self.phases
+            .iter()
+            .flat_map(|&phase| colormap.rgba(phase))
+            .collect()
+    }
+
+    /// Appends the agents of `other` to this system.
+    ///
+    /// Positions, phases, natural frequencies and chiral values are copied across
+    /// and the new agents start stationary. The parameters of this system govern
+    /// the merged one. If only one of the systems is chiral, the agents of the
+    /// other are given chiral values of zero. The new agents keep their species and
+    /// are wrapped or reflected into the arena like any other. With an edge
+    /// topology the new agents have no edges. With a target shape the new agents
+    /// start on their nearest points, and the whole shape is reassigned on the
+    /// next step.
+    /// # Arguments
+    /// - `other`: System whose agents are added.
+    /// # Errors
+    /// Returns an error if the systems have different dimensions, or if species
+    /// coupling is set and the other system has species this one has no coupling for.
+    pub fn merge(&mut self, other: &Swarmalator) -> Result<(), Error> {
+        if other.dim != self.dim {
+            return Err(Error::new("Merged systems must have the same dimension"));
+        }
+
+        if let Some(coupling) = self.species_coupling.as_ref() {
+            if other.species.iter().any(|&s| s as usize >= coupling.count) {
+                return Err(Error::new(
+                    "Species id must be less than the number of species",
+                ));
+            }
+        }
+
+        self.chiral = match (self.chiral.take(), other.chiral.as_ref()) {
+            (None, None) => None,
+            (own, others) => {
+                let mut chiral = own.unwrap_or_else(|| vec![0.0; self.agents]);
+                match others {
+                    Some(others) => chiral.extend_from_slice(others),
+                    None => chiral.extend(vec![0.0; other.agents]),
+                }
+                Some(chiral)
+            }
+        };
+
+        self.positions.extend_from_slice(&other.positions);
+        self.phases.extend_from_slice(&other.phases);
+        self.natural_frequencies
+            .extend_from_slice(&other.natural_frequencies);
+        self.velocities.extend(vec![0.0; other.agents * self.dim]);
+        self.delta_phases.extend(vec![0.0; other.agents]);
+        self.pinned.extend_from_slice(&other.pinned);
+        if let Some(K_vec) = self.K_vec.as_mut() {
+            K_vec.extend(vec![self.K; other.agents]);
+        }
+        if let Some(J_vec) = self.J_vec.as_mut() {
+            J_vec.extend(vec![self.J; other.agents]);
+        }
+        if let Some(K_matrix) = self.K_matrix.as_mut() {
+            grow_matrix(K_matrix, self.agents, other.agents, self.K);
+        }
+        if let Some(J_matrix) = self.J_matrix.as_mut() {
+            grow_matrix(J_matrix, self.agents, other.agents, self.J);
+        }
+        self.species.extend_from_slice(&other.species);
+        if let Some(topology) = self.topology.as_mut() {
+            topology.grow(other.agents);
+        }
+        if let Some((targets, _)) = self.phase_targets.as_mut() {
+            // The new agents are held at their current phases
+            targets.extend(other.phases.iter().map(|p| p.rem_euclid(2.0 * PI)));
+        }
+        if let (Some(targets), Some(_)) = (self.target.as_ref(), self.target_assignment.as_ref()) {
+            // The new agents chase their nearest target
+            let nearest: Vec<usize> = (0..other.agents)
+                .map(|i| {
+                    self.nearest_target(targets, other.position(&other.positions, i))
+                        .0
+                })
+                .collect();
+            if let Some(assignment) = self.target_assignment.as_mut() {
+                assignment.extend(nearest);
+            }
+        }
+        self.agents += other.agents;
+
+        self.enforce_boundary();
+        self.extend_formation(self.agents - other.agents);
+        self.positions_changed();
+
+        Ok(())
+    }
+
+    /// Adds a stationary agent.
+    ///
+    /// The new agent gets a chiral value of zero if the system is chiral, uses the
+    /// scalar `K` and `J` if per-agent or per-pair coefficients are set, is held at its
+    /// initial phase if phase targets are set, chases its nearest target if
+    /// targets are assigned, belongs to species 0 and has no edges in an edge
+    /// topology. With a target shape it starts on its nearest point, and the whole
+    /// shape is reassigned on the next step. This reallocates the agent
+    /// arrays, so pointers returned by `positions`, `phases` and `velocities` become
+    /// invalid; use the `*_vec` accessors instead when agents are added or removed.
+    /// In 3D the new agent is placed in the plane `z = 0`.
+    /// # Arguments
+    /// - `x`, `y`: Position of the new agent.
+    /// - `phase`: Phase of the new agent.
+    /// - `natural_frequency`: Natural frequency of the new agent.
+    /// # Errors
+    /// Returns an error if any argument is not finite.
+    pub fn add_agent(
+        &mut self,
+        x: f64,
+        y: f64,
+        phase: f64,
+        natural_frequency: f64,
+    ) -> Result<(), Error> {
+        check_finite("Position", x)?;
+        check_finite("Position", y)?;
+        check_finite("Phase", phase)?;
+        check_finite("Natural frequency", natural_frequency)?;
+
+        self.positions.extend(&[x, y, 0.0][..self.dim]);
+        self.velocities.extend(vec![0.0; self.dim]);
+        self.phases.push(phase);
+        self.delta_phases.push(0.0);
+        self.natural_frequencies.push(natural_frequency);
+        self.pinned.push(false);
+        if let Some(chiral) = self.chiral.as_mut() {
+            chiral.push(0.0);
+        }
+        if let Some(K_vec) = self.K_vec.as_mut() {
+            K_vec.push(self.K);
+        }
+        if let Some(J_vec) = self.J_vec.as_mut() {
+            J_vec.push(self.J);
+        }
+        if let Some(K_matrix) = self.K_matrix.as_mut() {
+            grow_matrix(K_matrix, self.agents, 1, self.K);
+        }
+        if let Some(J_matrix) = self.J_matrix.as_mut() {
+            grow_matrix(J_matrix, self.agents, 1, self.J);
+        }
+        self.species.push(0);
+        if let Some(topology) = self.topology.as_mut() {
+            topology.grow(1);
+        }
+        if let Some((targets, _)) = self.phase_targets.as_mut() {
+            targets.push(phase.rem_euclid(2.0 * PI));
+        }
+        if let Some(targets) = self.target.as_ref() {
+            let (nearest, _) = self.nearest_target(targets, &[x, y, 0.0][..self.dim]);
+            if let Some(assignment) = self.target_assignment.as_mut() {
+                assignment.push(nearest);
+            }
+        }
+        self.agents += 1;
+
+        self.enforce_boundary();
+        self.extend_formation(self.agents - 1);
+        self.positions_changed();
+
+        Ok(())
+    }
+
+    /// Removes an agent. Later agents move down one index.
+    ///
+    /// Like `add_agent`, this invalidates pointers into the agent arrays and has any
+    /// target shape reassigned on the next step.
+    /// # Arguments
+    /// - `index`: Index of the agent to remove.
+    /// # Errors
+    /// Returns an error if `index` is not less than the number of agents.
+    pub fn remove_agent(&mut self, index: usize) -> Result<(), Error> {
+        if index >= self.agents {
+            return Err(Error::new("Agent index must be less than agents"));
+        }
+
+        self.positions
+            .drain(index * self.dim..(index + 1) * self.dim);
+        self.velocities
+            .drain(index * self.dim..(index + 1) * self.dim);
+        self.phases.remove(index);
+        self.delta_phases.remove(index);
+        self.natural_frequencies.remove(index);
+        self.pinned.remove(index);
+        if let Some(chiral) = self.chiral.as_mut() {
+            chiral.remove(index);
+        }
+        if let Some(K_vec) = self.K_vec.as_mut() {
+            K_vec.remove(index);
+        }
+        if let Some(J_vec) = self.J_vec.as_mut() {
+            J_vec.remove(index);
+        }
+        if let Some(K_matrix) = self.K_matrix.as_mut() {
+            shrink_matrix(K_matrix, self.agents, index);
+        }
+        if let Some(J_matrix) = self.J_matrix.as_mut() {
+            shrink_matrix(J_matrix, self.agents, index);
+        }
+        self.species.remove(index);
+        if let Some(topology) = self.topology.as_mut() {
+            topology.remove(index);
+        }
+        if let Some((targets, _)) = self.phase_targets.as_mut() {
+            targets.remove(index);
+        }
+        if let Some(assignment) = self.target_assignment.as_mut() {
+            assignment.remove(index);
+        }
+        if let Some(formation) = self.formation.as_mut() {
+            formation.remove(index);
+        }
+        self.agents -= 1;
+
+        self.positions_changed();
+
+        Ok(())
+    }
+
+    /// Returns the standard deviation of the instantaneous frequencies (`delta_phases`)
+    /// from the last step. Drops toward zero as the system frequency-locks.
+    pub fn frequency_spread(&self) -> f64 {
+        if self.agents == 0 {
+            return 0.0;
+        }
+
+        let n = self.agents as f64;
+        let mean = self.delta_phases.iter().sum::<f64>() / n;
+        let variance = self
+            .delta_phases
+            .iter()
+            .map(|w| (w - mean).powi(2))
+            .sum::<f64>()
+            / n;
+
+        variance.sqrt()
+    }
+
+    /// Returns, for each agent, the circular mean of the phases of all agents within
+    /// `radius` of it (including itself), in `[0, 2π)`.
+    ///
+    /// Useful for colouring agents by the local phase field rather than their own
+    /// phase. If the neighbourhood phases cancel out the agent keeps its own phase.
+    pub fn smoothed_phases(&self, radius: f64) -> Vec<f64> {
+        (0..self.agents)
+            .map(|i| {
+                let mut sum_cos = 0.0;
+                let mut sum_sin = 0.0;
+                self.for_each_within(self.position(&self.positions, i), radius, |j| {
+                    sum_cos += cos(self.phases[j]);
+                    sum_sin += sin(self.phases[j]);
+                });
+
+                if sum_cos.abs() < 1e-12 && sum_sin.abs() < 1e-12 {
+                    self.phases[i]
+                } else {
+                    sum_sin.atan2(sum_cos).rem_euclid(2.0 * PI)
+                }
+            })
+            .collect()
+    }
+
+    /// Starts (or restarts) time-averaging the order parameters.
+    ///
+    /// After every subsequent `update` the phase coherence `R` and the magnitudes of
+    /// `S+` and `S-` are accumulated into running means and variances. Start averaging
+    /// only once transients have decayed, otherwise they bias the averages.
+    pub fn start_averaging(&mut self) {
+        self.averaging = Some([RunningStats::default(); 3]);
+    }
+
+    /// Returns the time-averaged order parameters and their standard errors as
+    /// `[R, R_err, S+, S+_err, S-, S-_err]`.
+    ///
+    /// The standard errors treat successive steps as independent samples, so they
+    /// underestimate the true error when the order parameters are correlated in time.
+    /// Values are `NaN` if averaging was never started or too few steps were taken.
+    pub fn averaged_order(&self) -> Vec<f64> {
+        match self.averaging.as_ref() {
+            Some(averaging) => averaging
+                .iter()
+                .flat_map(|stats| [stats.mean(), stats.std_error()])
+                .collect(),
+            None => vec![f64::NAN; 6],
+        }
+    }
+
+    /// Returns the susceptibility `N * Var(R)` of the phase coherence `R`, from the
+    /// statistics gathered since `start_averaging`.
+    ///
+    /// Peaks at the synchronization transition when scanning `K`. The variance is
+    /// only meaningful over an averaging window much longer than the correlation
+    /// time of `R`. Returns `NaN` if averaging was never started or too few steps
+    /// were taken.
+    pub fn order_susceptibility(&self) -> f64 {
+        match self.averaging.as_ref() {
+            Some(averaging) => self.agents as f64 * averaging[0].variance(),
+            None => f64::NAN,
+        }
+    }
+
+    /// Returns the rate of energy dissipation `Σ |v_i|²` from the last step.
+    ///
+    /// In the overdamped dynamics velocity equals force, so this is the power
+    /// dissipated. It decays to zero as the system relaxes to a static state.
+    pub fn dissipation_rate(&self) -> f64 {
+        self.velocities.iter().map(|v| v * v).sum()
+    }
+
+    /// Returns the rainbow order parameters `[S+, S-]`, the magnitudes of
+    /// `W± = Σ e^{i(θ_j ± φ_j)} / N` where `θ_j = atan2(y_j, x_j)` is the angular
+    /// position of agent `j` and `φ_j` its phase. In 3D `θ_j` is the azimuthal angle.
+    pub fn order_parameters(&self) -> Vec<f64> {
+        let (s_plus, s_minus) = self.rainbow_order();
+        vec![s_plus, s_minus]
+    }
+
+    /// Returns the order parameters used to classify the state of the system.
+    ///
+    /// The mean radius is measured from the centroid with plain (non-periodic)
+    /// distances, so it is only meaningful without periodic boundaries.
+    pub fn diagnostics(&self) -> Diagnostics {
+        let (s_plus, s_minus) = self.rainbow_order();
+
+        let (mean_radius, mean_speed) = if self.agents == 0 {
+            (0.0, 0.0)
+        } else {
+            let center = self.center_of_mass();
+            let n = self.agents as f64;
+            let mut radius_sum = 0.0;
+            let mut speed_sum = 0.0;
+            for i in 0..self.agents {
+                let mut offset = [0.0; 3];
+                for (k, x) in self.position(&self.positions, i).iter().enumerate() {
+                    offset[k] = x - center[k];
+                }
+                let mut velocity = [0.0; 3];
+                velocity[..self.dim].copy_from_slice(self.position(&self.velocities, i));
+
+                radius_sum += norm(&offset);
+                speed_sum += norm(&velocity);
+            }
+            (radius_sum / n, speed_sum / n)
+        };
+
+        Diagnostics {
+            phase_coherence: self.phase_coherence(),
+            s_plus,
+            s_minus,
+            phase_space_correlation: s_plus.max(s_minus),
+            mean_radius,
+            mean_speed,
+        }
+    }
+
+    /// Returns the two-cluster order parameter `|Σ e^{i2φ_j}| / N`.
+    ///
+    /// Close to 1 when the agents form two anti-phase clusters (as favoured by
+    /// second-harmonic coupling), a state in which the ordinary phase coherence is
+    /// close to 0.
+    pub fn two_cluster_order(&self) -> f64 {
+        self.harmonic_coherence(2.0)
+    }
+
+    /// Returns the centroid `[mean_x, mean_y]` (with `mean_z` in 3D) of the agents,
+    /// or the origin if there are none.
+    pub fn centroid(&self) -> Vec<f64> {
+        self.center_of_mass()[..self.dim].to_vec()
+    }
+
+    /// Returns the axis-aligned bounding box `[min_x, min_y, max_x, max_y]` of the
+    /// agents, or all zeros if there are none. In 3D it is
+    /// `[min_x, min_y, min_z, max_x, max_y, max_z]`.
+    pub fn bounding_box(&self) -> Vec<f64> {
+        if self.agents == 0 {
+            return vec![0.0; 2 * self.dim];
+        }
+
+        let mut bounds = vec![f64::INFINITY; self.dim];
+        bounds.extend(vec![f64::NEG_INFINITY; self.dim]);
+        for i in 0..self.agents {
+            for (k, &x) in self.position(&self.positions, i).iter().enumerate() {
+                bounds[k] = bounds[k].min(x);
+                bounds[self.dim + k] = bounds[self.dim + k].max(x);
+            }
+        }
+
+        bounds
+    }
+
+    /// Returns the polarization `|Σ v_i / |v_i|| / N`, the directional alignment of
+    /// the agents' motion, in `[0, 1]`.
+    ///
+    /// Agents with a speed below `1e-12` have no well-defined heading; they add
+    /// nothing to the sum but still count toward `N`, so a static swarm has a
+    /// polarization of 0.
+    pub fn polarization(&self) -> f64 {
+        if self.agents == 0 {
+            return 0.0;
+        }
+
+        let mut sum = [0.0; 3];
+        for i in 0..self.agents {
+            let v = self.position(&self.velocities, i);
+            let speed = v.iter().map(|v| v * v).sum::<f64>().sqrt();
+            if speed > 1e-12 {
+                for k in 0..self.dim {
+                    sum[k] += v[k] / speed;
+                }
+            }
+        }
+
+        sum.iter().map(|s| s * s).sum::<f64>().sqrt() / self.agents as f64
+    }
+
+    /// Returns the distribution of DBSCAN cluster sizes: index `k` holds the number
+    /// of clusters containing exactly `k` agents.
+    ///
+    /// Agents are neighbours when they are within `eps` of each other, and a cluster
+    /// grows from agents with at least `min_pts` neighbours (counting themselves).
+    /// Noise agents belong to no cluster and are not counted.
+    pub fn cluster_size_distribution(&self, eps: f64, min_pts: usize) -> Vec<u32> {
+        let labels = cluster::dbscan(self.agents, min_pts, |i, found| {
+            self.for_each_within(self.position(&self.positions, i), eps, |j| found.push(j))
+        });
+
+        let clusters = labels.iter().flatten().map(|&c| c + 1).max().unwrap_or(0);
+        let mut sizes = vec![0usize; clusters];
+        for &c in labels.iter().flatten() {
+            sizes[c] += 1;
+        }
+
+        let largest = sizes.iter().copied().max().unwrap_or(0);
+        let mut distribution = vec![0; largest + 1];
+        for size in sizes {
+            distribution[size] += 1;
+        }
+
+        distribution
+    }
+
+    /// Returns the number of agents in the largest connected component of the graph
+    /// linking every pair of agents within `radius` of each other.
+    ///
+    /// Equal to `agents` when the swarm is a single connected group and smaller when
+    /// it has fragmented.
+    pub fn largest_component_size(&self, radius: f64) -> usize {
+        let mut components = cluster::UnionFind::new(self.agents);
+        for i in 0..self.agents {
+            self.for_each_within(self.position(&self.positions, i), radius, |j| {
+                if j > i {
+                    components.union(i, j);
+                }
+            });
+        }
+
+        components.largest()
+    }
+
+    /// Returns a histogram of the pairwise phase differences `φ_i - φ_j` over all
+    /// unordered pairs, binned into `bins` equal bins across `[0, 2π)`.
+    ///
+    /// A sharp peak at 0 means full synchrony, a flat histogram means incoherence and
+    /// several peaks reveal cluster states. This is O(N²) so is meant for on-demand
+    /// analysis rather than every frame.
+    pub fn phase_difference_histogram(&self, bins: usize) -> Vec<u32> {
+        let mut histogram = vec![0; bins];
+        if bins == 0 {
+            return histogram;
+        }
+
+        for i in 0..self.agents {
+            for j in (i + 1)..self.agents {
+                let diff = (self.phases[i] - self.phases[j]).rem_euclid(2.0 * PI);
+                let bin = ((diff / (2.0 * PI)) * bins as f64) as usize;
+                histogram[bin.min(bins - 1)] += 1;
+            }
+        }
+
+        histogram
+    }
+
+    /// Displaces and phase-shifts every agent within `radius` of `(cx, cy)`, leaving
+    /// all other agents untouched. In 3D the centre lies in the plane `z = 0` and
+    /// agents aren't displaced along z.
+    /// # Arguments
+    /// - `cx`, `cy`: Centre of the region.
+    /// - `radius`: Radius of the region.
+    /// - `dx`, `dy`: Displacement applied to agents in the region.
+    /// - `dphase`: Phase shift applied to agents in the region.
+    /// # Errors
+    /// Returns an error if the displacement or phase shift is not finite.
+    pub fn kick_region(
+        &mut self,
+        cx: f64,
+        cy: f64,
+        radius: f64,
+        dx: f64,
+        dy: f64,
+        dphase: f64,
+    ) -> Result<(), Error> {
+        check_finite("Displacement", dx)?;
+        check_finite("Displacement", dy)?;
+        check_finite("Phase shift", dphase)?;
+
+        let mut kicked = Vec::new();
+        self.for_each_within(&[cx, cy, 0.0][..self.dim], radius, |i| kicked.push(i));
+
+        for i in kicked {
+            self.positions[i * self.dim] += dx;
+            self.positions[i * self.dim + 1] += dy;
+
+            self.phases[i] = (self.phases[i] + dphase).rem_euclid(2.0 * PI);
+        }
+
+        self.enforce_boundary();
+        self.positions_changed();
+
+        Ok(())
+    }
+
+    /// Applies a pointer force around `(x, y)` during every following step.
+    ///
+    /// Agents within `radius` of the pointer are attracted, repelled or phase-kicked
+    /// as described in `PointerMode`, most strongly at the pointer and not at all at
+    /// the edge of the radius. The force stays on, replacing any earlier one, until
+    /// `clear_pointer_force` is called, so forward pointer moves here and clear it on
+    /// release. It isn't saved with the state. In 3D the pointer lies in the plane
+    /// `z = 0`.
+    /// # Arguments
+    /// - `x`, `y`: Position of the pointer.
+    /// - `strength`: Speed, or phase velocity, given to an agent right at the pointer.
+    /// - `radius`: Radius of the region the pointer acts on.
+    /// - `mode`: What the pointer does to the agents.
+    /// # Errors
+    /// Returns an error if `radius` is not positive, or any argument is not finite.
+    pub fn apply_pointer_force(
+        &mut self,
+        x: f64,
+        y: f64,
+        strength: f64,
+        radius: f64,
+        mode: PointerMode,
+    ) -> Result<(), Error> {
+        check_finite("Pointer position", x)?;
+        check_finite("Pointer position", y)?;
+        check_finite("Pointer strength", strength)?;
+        if !radius.is_finite() || radius <= 0.0 {
+            return Err(Error::new("Pointer radius must be positive and finite"));
+        }
+
+        self.pointer = Some(Pointer {
+            center: [x, y, 0.0],
+            strength,
+            radius,
+            mode,
+        });
+
+        Ok(())
+    }
+
+    /// Stops the pointer force from `apply_pointer_force`.
+    pub fn clear_pointer_force(&mut self) {
+        self.pointer = None;
+    }
+
+    /// Update the target position.
+    ///
+    /// While a target is set, each agent's `J` is rescaled by how far it is from
+    /// the target relative to the nearest and furthest agents, from zero for the
+    /// nearest to the gain from `set_target_gain` for the furthest. If all agents
+    /// are equally far from the target (including when there is a single agent) the
+    /// scalar `J` is used for everyone instead. Replaces any targets from
+    /// `set_targets`.
+    /// # Arguments
+    /// - `target`: New target position.
+    /// # Errors
+    /// Returns an error if the length of `target` is not equal to `dim`, or any
+    /// coordinate is not finite.
+    pub fn set_target(&mut self, target: Vec<f64>) -> Result<(), Error> {
+        if target.len() != self.dim {
+            return Err(Error::new(&format!(
+                "Target array must have {} elements",
+                self.dim
+            )));
+        }
+
+        check_all_finite("Target", &target)?;
+
+        self.target = Some(target);
+        self.target_assignment = None;
+
+        Ok(())
+    }
+
+    /// Set the gain of the `J` rescaling while a target is set.
+    ///
+    /// The agent furthest from its target gets this `J`, and the nearest gets zero.
+    /// Defaults to 1.
+    /// # Arguments
+    /// - `gain`: New target gain.
+    /// # Errors
+    /// Returns an error if `gain` is not finite.
+    pub fn set_target_gain(&mut self, gain: f64) -> Result<(), Error> {
+        check_finite("Target gain", gain)?;
+
+        self.target_gain = gain;
+
+        Ok(())
+    }
+
+    /// Returns the gain of the `J` rescaling while a target is set.
+    pub fn target_gain(&self) -> f64 {
+        self.target_gain
+    }
+
+    /// Set several targets, each chased by a subset of the agents.
+    ///
+    /// Agents chasing the same target form a group, and the `J` rescaling and
+    /// phase entrainment described in `set_target` and `set_phase_target` are
+    /// applied within each group, relative to the nearest and furthest agents of
+    /// that group. Without an assignment every agent chases whichever target is
+    /// nearest to it at the start of each step, so groups can change over time.
+    /// Agents added later chase their nearest target.
+    /// # Arguments
+    /// - `targets`: Target positions, `dim` components each.
+    /// - `assignment`: Index of the target each agent chases, or `None` for the nearest.
+    /// # Errors
+    /// Returns an error if `targets` is empty, its length is not a multiple of
+    /// `dim` or any coordinate is not finite, if the length of `assignment` is not
+    /// equal to the number of agents, or if any assigned index is not less than the
+    /// number of targets.
+    pub fn set_targets(
+        &mut self,
+        targets: Vec<f64>,
+        assignment: Option<Vec<usize>>,
+    ) -> Result<(), Error> {
+        if targets.is_empty() || !targets.len().is_multiple_of(self.dim) {
+            return Err(Error::new(&format!(
+                "Targets array must have a non-zero multiple of {} elements",
+                self.dim
+            )));
+        }
+
+        check_all_finite("Targets", &targets)?;
+
+        if let Some(assignment) = assignment.as_ref() {
+            if assignment.len() != self.agents {
+                return Err(Error::new(
+                    "Target assignment array must have agents elements",
+                ));
+            }
+            if assignment.iter().any(|&t| t >= targets.len() / self.dim) {
+                return Err(Error::new(
+                    "Target assignment index must be less than the number of targets",
+                ));
+            }
+        }
+
+        self.target = Some(targets);
+        self.target_assignment = assignment;
+
+        Ok(())
+    }
+
+    /// Set a shape for the swarm to form, such as the outline of a letter or logo.
+    ///
+    /// Each agent is assigned its own point of the shape, as described in
+    /// `AssignmentMethod`, and gains a velocity `strength * (p_i - x_i)` toward its
+    /// point `p_i` on top of the swarmalator dynamics, which keep running so the
+    /// phases still sync or wave across the shape. The assignment is made now from
+    /// the current positions and kept until `reassign_target_shape` is called, or
+    /// redone on the next step after agents are added or removed. Independent of `set_target` and `set_targets`.
+    /// To follow an SVG path, sample it in JS, e.g. with `getPointAtLength`.
+    /// # Arguments
+    /// - `points`: Points of the shape, `dim` components each.
+    /// - `strength`: Stiffness of the pull toward the assigned points.
+    /// - `method`: How agents are matched to points.
+    /// # Errors
+    /// Returns an error if `points` is empty, its length is not a multiple of `dim` or
+    /// any coordinate is not finite, if `strength` is not finite, or if `method` is
+    /// `Optimal` and there are more than 1000 agents.
+    pub fn set_target_shape(
+        &mut self,
+        points: Vec<f64>,
+        strength: f64,
+        method: AssignmentMethod,
+    ) -> Result<(), Error> {
+        if points.is_empty() || !points.len().is_multiple_of(self.dim) {
+            return Err(Error::new(&format!(
+                "Target shape array must have a non-zero multiple of {} elements",
+                self.dim
+            )));
+        }
+        check_all_finite("Target shape", &points)?;
+        check_finite("Target shape strength", strength)?;
+        if method == AssignmentMethod::Optimal && self.agents > MAX_OPTIMAL_AGENTS {
+            return Err(Error::new(&format!(
+                "Optimal assignment is limited to {} agents, use Greedy instead",
+                MAX_OPTIMAL_AGENTS
+            )));
+        }
+
+        self.formation = Some(Formation::new(points, strength, method));
+        self.assign_formation();
+
+        Ok(())
+    }
+
+    /// Matches the agents to the points of the target shape again from their current
+    /// positions, e.g. after the swarm has been scattered. Does nothing without a
+    /// target shape.
+    pub fn reassign_target_shape(&mut self) {
+        self.assign_formation();
+    }
+
+    /// Removes the target shape.
+    pub fn clear_target_shape(&mut self) {
+        self.formation = None;
+    }
+
+    /// Returns the index of the target shape point each agent is assigned to, or an
+    /// empty array without a target shape.
+    pub fn target_shape_assignment(&self) -> Vec<usize> {
+        self.formation
+            .as_ref()
+            .map_or_else(Vec::new, |formation| formation.assignment().to_vec())
+    }
+
+    /// Set a phase that agents are entrained to as they approach the target.
+    ///
+    /// While a target is set, each agent's phase velocity gains the term
+    /// `strength * p_i * sin(phase - φ_i)`, where the proximity `p_i` goes from 1 for
+    /// the agent closest to its target to 0 for the furthest chasing the same
+    /// target. A strength of `0`
+    /// disables the term.
+    /// # Arguments
+    /// - `phase`: Target phase.
+    /// - `strength`: Strength of the entrainment.
+    /// # Errors
+    /// Returns an error if `phase` or `strength` is not finite.
+    pub fn set_phase_target(&mut self, phase: f64, strength: f64) -> Result<(), Error> {
+        check_finite("Target phase", phase)?;
+        check_finite("Strength", strength)?;
+
+        self.phase_target = (phase, strength);
+
+        Ok(())
+    }
+
+    /// Set how strongly the short-range repulsion depends on phase.
+    ///
+    /// The repulsion term `B * (x_j - x_i) / |x_j - x_i|²` in `update` is scaled by
+    /// `1 + gain * cos(φ_j - φ_i)`. A gain of `0` leaves the repulsion unchanged.
+    /// # Arguments
+    /// - `gain`: Phase dependence of the repulsion.
+    /// # Errors
+    /// Returns an error if `gain` is not finite.
+    pub fn set_phase_repulsion_coupling(&mut self, gain: f64) -> Result<(), Error> {
+        check_finite("Gain", gain)?;
+
+        self.phase_repulsion_coupling = gain;
+
+        Ok(())
+    }
+
+    /// Returns the gain of the phase repulsion.
+    pub fn phase_repulsion_coupling(&self) -> f64 {
+        self.phase_repulsion_coupling
+    }
+
+    /// Set the harmonic of the phase coupling.
+    ///
+    /// The phase coupling term in `update` becomes `sin(n * (φ_j - φ_i))`. The default
+    /// `n = 1` drives the agents toward a single synchronized cluster, while higher
+    /// harmonics favour `n` phase clusters spaced `2π / n` apart (e.g. two anti-phase
+    /// clusters for `n = 2`).
+    /// # Arguments
+    /// - `n`: Harmonic of the coupling.
+    /// # Errors
+    /// Returns an error if `n` is 0.
+    pub fn set_phase_harmonic(&mut self, n: u32) -> Result<(), Error> {
+        if n == 0 {
+            return Err(Error::new("Phase harmonic must be at least 1"));
+        }
+
+        self.phase_harmonic = n;
+
+        Ok(())
+    }
+
+    /// Returns the harmonic of the phase coupling.
+    pub fn phase_harmonic(&self) -> u32 {
+        self.phase_harmonic
+    }
+
+    /// Set per-agent target phases.
+    ///
+    /// Each step adds `strength * sin(target_i - φ_i)` to every agent's phase
+    /// velocity, a proportional controller that drives the system toward the
+    /// prescribed phase pattern. Targets are wrapped into `[0, 2π)`.
+    /// # Arguments
+    /// - `targets`: Target phase of each agent, or `None` to remove the targets.
+    /// - `strength`: Strength of the pull toward the targets.
+    /// # Errors
+    /// Returns an error if the length of `targets` is not equal to the number of agents,
+    /// or any value is not finite.
+    pub fn set_phase_targets(
+        &mut self,
+        targets: Option<Vec<f64>>,
+        strength: f64,
+    ) -> Result<(), Error> {
+        check_finite("Strength", strength)?;
+        if let Some(targets) = targets.as_ref() {
+            if targets.len() != self.agents {
+                return Err(Error::new("Phase targets array must have agents elements"));
+            }
+            check_all_finite("Phase targets", targets)?;
+        }
+
+        self.phase_targets = targets.map(|targets| {
+            let targets = targets.iter().map(|t| t.rem_euclid(2.0 * PI)).collect();
+            (targets, strength)
+        });
+
+        Ok(())
+    }
+
+    /// Set the integrator.
+    /// # Arguments
+    /// - `kind`: New integrator.
+    pub fn set_integrator(&mut self, kind: IntegratorKind) {
+        self.integrator = kind;
+    }
+
+    /// Returns the integrator used by `update`.
+    pub fn integrator(&self) -> IntegratorKind {
+        self.integrator
+    }
+
+    /// Set the error tolerance of the adaptive integrator.
+    ///
+    /// With `IntegratorKind::Rk45`, substeps are shrunk until the estimated local
+    /// error of each coordinate and phase is within `tolerance * (1 + |value|)`, in
+    /// the root-mean-square sense. Smaller tolerances are more accurate but take
+    /// more substeps. Defaults to `1e-6`.
+    /// # Arguments
+    /// - `tolerance`: New error tolerance.
+    /// # Errors
+    /// Returns an error if `tolerance` is not positive.
+    pub fn set_tolerance(&mut self, tolerance: f64) -> Result<(), Error> {
+        if tolerance.is_nan() || tolerance <= 0.0 {
+            return Err(Error::new("Tolerance must be positive"));
+        }
+
+        self.tolerance = tolerance;
+
+        Ok(())
+    }
+
+    /// Returns the error tolerance of the adaptive integrator.
+    pub fn tolerance(&self) -> f64 {
+        self.tolerance
+    }
+
+    /// Set the integration scheme.
+    /// # Arguments
+    /// - `scheme`: New integration scheme.
+    pub fn set_integration_scheme(&mut self, scheme: Scheme) {
+        self.integration_scheme = scheme;
+    }
+
+    /// Returns the integration scheme.
+    pub fn integration_scheme(&self) -> Scheme {
+        self.integration_scheme
+    }
+
+    /// Set the minimum pairwise distance.
+    ///
+    /// Distances between agents are clamped to at least this value in `update`
+    /// before dividing by them, so agents sharing a position produce large but
+    /// finite contributions instead of `NaN`. Defaults to `1e-6`.
+    /// # Arguments
+    /// - `eps`: New minimum distance.
+    /// # Errors
+    /// Returns an error if `eps` is not positive and finite.
+    pub fn set_min_distance(&mut self, eps: f64) -> Result<(), Error> {
+        if !eps.is_finite() || eps <= 0.0 {
+            return Err(Error::new("Minimum distance must be positive and finite"));
+        }
+
+        self.min_distance = eps;
+
+        Ok(())
+    }
+
+    /// Returns the minimum distance used in the interaction kernels.
+    pub fn min_distance(&self) -> f64 {
+        self.min_distance
+    }
+
+    /// Set the softening length.
+    ///
+    /// Pairwise distances become `sqrt(r² + ε²)`, which caps the repulsion between
+    /// close agents smoothly instead of letting it grow like `1 / r` until the
+    /// minimum distance. Interactions at distances well beyond `ε` barely change.
+    /// Defaults to `0`. A nonzero softening runs the pairwise loop without the
+    /// `wasm-simd` or `webgpu` fast paths.
+    /// # Arguments
+    /// - `epsilon`: New softening length.
+    /// # Errors
+    /// Returns an error if `epsilon` is negative or not finite.
+    pub fn set_softening(&mut self, epsilon: f64) -> Result<(), Error> {
+        if !epsilon.is_finite() || epsilon < 0.0 {
+            return Err(Error::new("Softening must be non-negative and finite"));
+        }
+
+        self.softening = epsilon;
+
+        Ok(())
+    }
+
+    /// Returns the softening length.
+    pub fn softening(&self) -> f64 {
+        self.softening
+    }
+
+    /// Set what a step does when it produces non-finite values. Defaults to
+    /// `NanPolicy::Ignore`.
+    ///
+    /// Any other policy copies the agent arrays before every step to recover from.
+    /// # Arguments
+    /// - `policy`: New policy.
+    pub fn set_nan_policy(&mut self, policy: NanPolicy) {
+        self.nan_policy = policy;
+    }
+
+    /// Returns what a step does when it produces non-finite values.
+    pub fn nan_policy(&self) -> NanPolicy {
+        self.nan_policy
+    }
+
+    /// Split steps automatically so that no agent moves more than `max_displacement`
+    /// in one.
+    ///
+    /// Each step of `dt` becomes `ceil(v * dt / max_displacement)` equal substeps,
+    /// up to 64, where `v` is the fastest agent's speed at the start of the step.
+    /// Close encounters then get the small steps they need while calm stretches keep
+    /// the full `dt`, at the cost of one extra evaluation of the interactions per
+    /// step. Set a maximum speed too if even 64 substeps aren't enough. Time
+    /// averages, recordings and `render_positions` still see one step per `update`.
+    /// # Arguments
+    /// - `max_displacement`: Furthest an agent may move per substep, or `None` to
+    ///   always take whole steps.
+    /// # Errors
+    /// Returns an error if `max_displacement` is not positive and finite.
+    pub fn set_auto_timestep(&mut self, max_displacement: Option<f64>) -> Result<(), Error> {
+        if let Some(max_displacement) = max_displacement {
+            if !max_displacement.is_finite() || max_displacement <= 0.0 {
+                return Err(Error::new(
+                    "Maximum displacement must be positive and finite",
+                ));
+            }
+        }
+
+        self.max_displacement = max_displacement;
+
+        Ok(())
+    }
+
+    /// Returns the largest distance an agent may move per substep, if automatic
+    /// substepping is enabled.
+    pub fn auto_timestep(&self) -> Option<f64> {
+        self.max_displacement
+    }
+
+    /// Set the spatial attraction coefficient.
+    /// # Arguments
+    /// - `A`: New value for A
+    /// # Errors
+    /// Returns an error if `A` is not finite.
+    pub fn set_A(&mut self, A: f64) -> Result<(), Error> {
+        check_finite("A", A)?;
+
+        self.A = A;
+
+        Ok(())
+    }
+
+    /// Returns the spatial attraction coefficient.
+    pub fn A(&self) -> f64 {
+        self.A
+    }
+
+    /// Set the short-range repulsion coefficient.
+    /// # Arguments
+    /// - `B`: New value for B
+    /// # Errors
+    /// Returns an error if `B` is not finite.
+    pub fn set_B(&mut self, B: f64) -> Result<(), Error> {
+        check_finite("B", B)?;
+
+        self.B = B;
+
+        Ok(())
+    }
+
+    /// Returns the short-range repulsion coefficient.
+    pub fn B(&self) -> f64 {
+        self.B
+    }
+
+    /// Set periodic (toroidal) boundaries.
+    ///
+    /// With a box of side `size`, pairwise separations and distances to the target
+    /// use the minimum-image convention, wrapping each coordinate difference into
+    /// `[-size / 2, size / 2]`, and positions are wrapped back into `[0, size)` after
+    /// every step. In 3D the box is a cube. Shorthand for `set_boundary` with
+    /// `Boundary::Periodic`, or `Boundary::Open` when `size` is `None`.
+    /// # Arguments
+    /// - `size`: Side length of the box, or `None` for an unbounded domain.
+    /// # Errors
+    /// Returns an error if `size` is not positive and finite.
+    pub fn set_periodic(&mut self, size: Option<f64>) -> Result<(), Error> {
+        match size {
+            Some(size) => self.set_boundary(Boundary::Periodic, size, 0.0),
+            None => self.set_boundary(Boundary::Open, 0.0, 0.0),
+        }
+    }
+
+    /// Set the boundary conditions.
+    ///
+    /// Agents already outside the arena are wrapped or reflected back into it
+    /// straight away, while soft confinement only pulls them back gradually. See
+    /// `Boundary` for how each kind behaves.
+    /// # Arguments
+    /// - `kind`: Kind of boundary.
+    /// - `size`: Side length of the box, or radius of the circle. Ignored for `Open`.
+    /// - `stiffness`: Strength of the confinement. Only used by `SoftCircular`.
+    /// # Errors
+    /// Returns an error if `size` is not positive and finite for a kind other than
+    /// `Open`, or `stiffness` is not finite.
+    pub fn set_boundary(&mut self, kind: Boundary, size: f64, stiffness: f64) -> Result<(), Error> {
+        if kind != Boundary::Open && !(size.is_finite() && size > 0.0) {
+            return Err(Error::new("Arena size must be positive and finite"));
+        }
+        check_finite("Stiffness", stiffness)?;
+
+        self.boundary = kind;
+        self.arena_size = size;
+        self.confinement_stiffness = stiffness;
+        self.enforce_boundary();
+        self.positions_changed();
+
+        Ok(())
+    }
+
+    /// Returns the kind of boundary.
+    pub fn boundary(&self) -> Boundary {
+        self.boundary
+    }
+
+    /// Returns the side length or radius of the arena.
+    pub fn arena_size(&self) -> f64 {
+        self.arena_size
+    }
+
+    /// Returns the strength of the soft confinement.
+    pub fn confinement_stiffness(&self) -> f64 {
+        self.confinement_stiffness
+    }
+
+    /// Add a circular obstacle that agents are repelled from.
+    ///
+    /// Obstacles lie in the xy-plane; in 3D they are cylinders along z. Agents
+    /// within the repulsion range of an obstacle's edge, or inside it, are pushed
+    /// out with a speed that grows linearly from zero at the range to `strength` at
+    /// the edge and keeps growing inside (see `set_obstacle_repulsion`). Obstacles
+    /// use the plain coordinates, without periodic images.
+    /// # Arguments
+    /// - `x`, `y`: Centre of the obstacle.
+    /// - `radius`: Radius of the obstacle.
+    /// # Returns
+    /// The index of the obstacle.
+    /// # Errors
+    /// Returns an error if `radius` is not positive, or any argument is not finite.
+    pub fn add_obstacle(&mut self, x: f64, y: f64, radius: f64) -> Result<usize, Error> {
+        check_finite("Obstacle position", x)?;
+        check_finite("Obstacle position", y)?;
+        check_finite("Obstacle radius", radius)?;
+
+        if radius.is_nan() || radius <= 0.0 {
+            return Err(Error::new("Obstacle radius must be positive"));
+        }
+
+        Ok(self.environment.add_circle(x, y, radius))
+    }
+
+    /// Add a polygonal obstacle that agents are repelled from, such as a wall of a
+    /// maze. Otherwise behaves like `add_obstacle`.
+    /// # Arguments
+    /// - `vertices`: Vertices of the polygon in order, flattened `[x0, y0, x1, y1, ...]`.
+    /// # Returns
+    /// The index of the obstacle.
+    /// # Errors
+    /// Returns an error if `vertices` has an odd length, fewer than 3 vertices or
+    /// any value that is not finite.
+    pub fn add_polygon_obstacle(&mut self, vertices: Vec<f64>) -> Result<usize, Error> {
+        if !vertices.len().is_multiple_of(2) || vertices.len() < 6 {
+            return Err(Error::new(
+                "Polygon obstacles must have at least 3 vertices",
+            ));
+        }
+        check_all_finite("Vertices", &vertices)?;
+
+        Ok(self.environment.add_polygon(&vertices))
+    }
+
+    /// Removes every obstacle.
+    pub fn clear_obstacles(&mut self) {
+        self.environment.clear_obstacles();
+    }
+
+    /// Set how strongly obstacles repel agents. Defaults to a strength of 1 and a
+    /// range of 0.2.
+    /// # Arguments
+    /// - `strength`: Speed an agent is pushed with at the edge of an obstacle.
+    /// - `range`: Distance from the edge at which the push starts.
+    /// # Errors
+    /// Returns an error if `range` is not positive, or either argument is not
+    /// finite.
+    pub fn set_obstacle_repulsion(&mut self, strength: f64, range: f64) -> Result<(), Error> {
+        check_finite("Obstacle strength", strength)?;
+        check_finite("Obstacle range", range)?;
+
+        if range.is_nan() || range <= 0.0 {
+            return Err(Error::new("Obstacle range must be positive"));
+        }
+
+        self.environment.set_obstacle_repulsion(strength, range);
+
+        Ok(())
+    }
+
+    /// Set an external flow field, replacing any existing ones.
+    ///
+    /// The flow's velocity is added to every agent's velocity in `update`. See
+    /// `FlowKind` for the fields and their parameters. Use `add_flow_field` to
+    /// combine several.
+    /// # Arguments
+    /// - `kind`: Kind of flow field, or `None` to remove all flow fields.
+    /// - `params`: Parameters of the flow field.
+    /// # Errors
+    /// Returns an error if `params` doesn't have the number of elements `kind` needs,
+    /// or any of them is not finite.
+    pub fn set_flow_field(
+        &mut self,
+        kind: Option<FlowKind>,
+        params: Vec<f64>,
+    ) -> Result<(), Error> {
+        if let Some(kind) = kind {
+            environment::check_flow(kind, &params, self.dim)?;
+        }
+
+        self.environment.clear_flows();
+        if let Some(kind) = kind {
+            self.environment.add_flow(kind, params);
+        }
+
+        Ok(())
+    }
+
+    /// Add an external flow field on top of any existing ones. See `set_flow_field`.
+    /// # Arguments
+    /// - `kind`: Kind of flow field.
+    /// - `params`: Parameters of the flow field.
+    /// # Errors
+    /// Returns an error if `params` doesn't have the number of elements `kind` needs,
+    /// or any of them is not finite.
+    pub fn add_flow_field(&mut self, kind: FlowKind, params: Vec<f64>) -> Result<(), Error> {
+        environment::check_flow(kind, &params, self.dim)?;
+
+        self.environment.add_flow(kind, params);
+
+        Ok(())
+    }
+
+    /// Give the agents bodies that collide.
+    ///
+    /// Each agent is a disk (a ball in 3D) of radius `radius`, and agents closer than
+    /// `2 * radius` respond as described in `CollisionKind`. Collisions act between
+    /// every pair of agents regardless of any cutoff or topology, are found through a
+    /// grid of cells the size of a body, and aren't scaled by the number of agents.
+    /// # Arguments
+    /// - `kind`: How overlapping agents respond, or `None` for point agents.
+    /// - `radius`: Body radius of every agent.
+    /// - `stiffness`: Strength of the soft-core push, ignored for hard cores.
+    /// # Errors
+    /// Returns an error if `radius` is not positive, or if `stiffness` is negative or
+    /// not finite.
+    pub fn set_collisions(
+        &mut self,
+        kind: Option<CollisionKind>,
+        radius: f64,
+        stiffness: f64,
+    ) -> Result<(), Error> {
+        let Some(kind) = kind else {
+            self.collisions = None;
+            return Ok(());
+        };
+
+        if !radius.is_finite() || radius <= 0.0 {
+            return Err(Error::new("Body radius must be positive and finite"));
+        }
+        if !stiffness.is_finite() || stiffness < 0.0 {
+            return Err(Error::new(
+                "Collision stiffness must be non-negative and finite",
+            ));
+        }
+
+        self.collisions = Some(Collisions {
+            kind,
+            radius,
+            stiffness,
+        });
+        if kind == CollisionKind::Hard {
+            self.resolve_overlaps();
+            self.positions_changed();
+        }
+
+        Ok(())
+    }
+
+    /// Set the maximum speed of an agent.
+    ///
+    /// Any agent whose velocity would be faster is slowed to `max_speed` in the same
+    /// direction, after every other contribution, which keeps close encounters from
+    /// flinging agents across the arena.
+    /// # Arguments
+    /// - `max_speed`: Largest allowed speed, or `None` for no limit.
+    /// # Errors
+    /// Returns an error if `max_speed` is negative or not finite.
+    pub fn set_max_speed(&mut self, max_speed: Option<f64>) -> Result<(), Error> {
+        if let Some(max_speed) = max_speed {
+            if !max_speed.is_finite() || max_speed < 0.0 {
+                return Err(Error::new("Maximum speed must be non-negative and finite"));
+            }
+        }
+
+        self.max_speed = max_speed;
+
+        Ok(())
+    }
+
+    /// Returns the speed limit, if one is set.
+    pub fn max_speed(&self) -> Option<f64> {
+        self.max_speed
+    }
+
+    /// Set an interaction cutoff radius.
+    ///
+    /// With a cutoff, `update` buckets the agents into a grid of cells the size of
+    /// the cutoff every step and each agent only interacts with agents within the
+    /// cutoff, making a step roughly O(N) for a local interaction instead of O(N²).
+    /// Contributions are still normalised by the total number of agents, so a
+    /// cutoff larger than the swarm reproduces the all-to-all dynamics (up to
+    /// floating point summation order).
+    /// # Arguments
+    /// - `radius`: Interaction radius, or `None` for all-to-all interaction.
+    /// # Errors
+    /// Returns an error if `radius` is not positive.
+    pub fn set_cutoff(&mut self, radius: Option<f64>) -> Result<(), Error> {
+        if let Some(radius) = radius {
+            if radius.is_nan() || radius <= 0.0 {
+                return Err(Error::new("Cutoff radius must be positive"));
+            }
+        }
+
+        self.cutoff = radius;
+        self.positions_changed();
+
+        Ok(())
+    }
+
+    /// Returns the interaction cutoff radius, if one is set.
+    pub fn cutoff(&self) -> Option<f64> {
+        self.cutoff
+    }
+
+    /// Restrict coupling to the edges of an interaction graph.
+    ///
+    /// Each agent only interacts with the agents it shares an edge with, in both
+    /// directions. Contributions are still normalised by the total number of agents,
+    /// as with a cutoff, which the graph replaces along with the far field.
+    /// # Arguments
+    /// - `edges`: Flattened `[a0, b0, a1, b1, ...]` pairs of agent indices.
+    /// Self-loops and repeated edges are ignored.
+    /// # Errors
+    /// Returns an error if `edges` has an odd length or any index is not less than
+    /// `agents`.
+    pub fn set_topology_edges(&mut self, edges: Vec<u32>) -> Result<(), Error> {
+        self.topology = Some(Topology::from_edges(self.agents, &edges)?);
+
+        Ok(())
+    }
+
+    /// Restrict coupling to each agent's `k` nearest neighbours.
+    ///
+    /// Each agent interacts with the `k` agents nearest to it, which need not count
+    /// it among their own nearest. The neighbours are found by brute force, O(N²),
+    /// at the start of the next step and every `refresh_every` steps after that, and
+    /// whenever agents are added or removed. Contributions are still normalised by
+    /// the total number of agents. Replaces the cutoff and far field like
+    /// `set_topology_edges`.
+    /// # Arguments
+    /// - `k`: Number of neighbours of each agent.
+    /// - `refresh_every`: Steps between recomputing the neighbours.
+    /// # Errors
+    /// Returns an error if `k` or `refresh_every` is zero.
+    pub fn set_knn(&mut self, k: usize, refresh_every: usize) -> Result<(), Error> {
+        if k == 0 {
+            return Err(Error::new("Number of neighbours must be positive"));
+        }
+        if refresh_every == 0 {
+            return Err(Error::new("Refresh interval must be positive"));
+        }
+
+        self.topology = Some(Topology::knn(self.agents, k, refresh_every));
+
+        Ok(())
+    }
+
+    /// Removes the interaction graph so agents couple all-to-all, or within the
+    /// cutoff, again.
+    pub fn clear_topology(&mut self) {
+        self.topology = None;
+    }
+
+    /// Approximate interactions beyond the cutoff instead of dropping them.
+    ///
+    /// Only has an effect while a cutoff is set. The agents are bucketed into the
+    /// cutoff-sized grid as usual, then each cell is summarised by its centroid,
+    /// number of agents and phase sums. An agent interacts exactly with every agent
+    /// in the cells whose members may be within the cutoff of it, and with each
+    /// remaining cell as a single pseudo-agent at its centroid, which approximates
+    /// the long-range attraction and phase coupling of distant agents. The
+    /// approximation ignores the chiral frequency offsets between distant agents.
+    /// A step costs O(N * cells), so this pays off when each cell holds many agents.
+    /// # Arguments
+    /// - `enabled`: Whether to approximate the far field.
+    pub fn set_far_field(&mut self, enabled: bool) {
+        self.far_field = enabled;
+    }
+
+    /// Returns whether distant cells are approximated by their aggregates.
+    pub fn far_field(&self) -> bool {
+        self.far_field
+    }
+
+    /// Approximate interactions beyond the cutoff through a single mean field.
+    ///
+    /// Once per step every agent's position and phase is summed. An agent interacts
+    /// exactly with the agents within the cutoff, and with all the others as a single
+    /// pseudo-agent at their centroid carrying their phase sums, found by taking the
+    /// near agents out of the global sums. Without a cutoff every other agent is in
+    /// the pseudo-agent, a well-stirred approximation. A step costs O(N * neighbours),
+    /// so large systems stay interactive, but the far agents' spread is lost: a far
+    /// group surrounding an agent pulls it much less than the exact sum would.
+    /// Overrides `set_far_field`. Like it, the approximation ignores the chiral
+    /// frequency offsets, doesn't apply with an interaction graph, and falls back to
+    /// exact all-to-all interactions while per-pair coefficients are set.
+    /// # Arguments
+    /// - `enabled`: Whether to use the mean-field approximation.
+    pub fn set_mean_field(&mut self, enabled: bool) {
+        self.mean_field = enabled;
+    }
+
+    /// Returns whether agents beyond the cutoff are approximated by the mean field.
+    pub fn mean_field(&self) -> bool {
+        self.mean_field
+    }
+
+    /// Pin agents in place.
+    ///
+    /// Pinned agents never move or change phase in `update`, but still attract,
+    /// repel and couple to every other agent, acting as fixed anchors. Replaces
+    /// any previously pinned agents.
+    /// # Arguments
+    /// - `indices`: Indices of the agents to pin.
+    /// # Errors
+    /// Returns an error if any index is not less than the number of agents.
+    pub fn set_pinned(&mut self, indices: Vec<usize>) -> Result<(), Error> {
+        let mut pinned = vec![false; self.agents];
+        for i in indices {
+            if i >= self.agents {
+                return Err(Error::new("Pinned index must be less than agents"));
+            }
+            pinned[i] = true;
+        }
+
+        self.pinned = pinned;
+
+        Ok(())
+    }
+
+    /// Pin a single agent, leaving the others as they are. See `set_pinned`.
+    ///
+    /// Together with `set_agent_position` and `set_agent_phase` this lets an agent
+    /// act as a leader driven from outside, e.g. by the mouse, that the rest of the
+    /// swarm responds to.
+    /// # Arguments
+    /// - `index`: Index of the agent to pin.
+    /// # Errors
+    /// Returns an error if `index` is not less than the number of agents.
+    pub fn pin_agent(&mut self, index: usize) -> Result<(), Error> {
+        if index >= self.agents {
+            return Err(Error::new("Agent index must be less than agents"));
+        }
+
+        self.pinned[index] = true;
+
+        Ok(())
+    }
+
+    /// Unpin a single agent so it follows the dynamics again.
+    /// # Arguments
+    /// - `index`: Index of the agent to unpin.
+    /// # Errors
+    /// Returns an error if `index` is not less than the number of agents.
+    pub fn unpin_agent(&mut self, index: usize) -> Result<(), Error> {
+        if index >= self.agents {
+            return Err(Error::new("Agent index must be less than agents"));
+        }
+
+        self.pinned[index] = false;
+
+        Ok(())
+    }
+
+    /// Move a single agent.
+    ///
+    /// Usually used on pinned agents, which stay where they are put. An unpinned
+    /// agent carries on from its new position. The position is wrapped or
+    /// reflected into the arena like any other.
+    /// # Arguments
+    /// - `index`: Index of the agent to move.
+    /// - `x`, `y`: New position of the agent.
+    /// - `z`: New z coordinate in 3D, or `None` to keep the current one. Ignored in 2D.
+    /// # Errors
+    /// Returns an error if `index` is not less than the number of agents, or the
+    /// position is not finite.
+    pub fn set_agent_position(
+        &mut self,
+        index: usize,
+        x: f64,
+        y: f64,
+        z: Option<f64>,
+    ) -> Result<(), Error> {
+        if index >= self.agents {
+            return Err(Error::new("Agent index must be less than agents"));
+        }
+        check_finite("Position", x)?;
+        check_finite("Position", y)?;
+        check_finite("Position", z.unwrap_or_default())?;
+
+        let position = &mut self.positions[index * self.dim..(index + 1) * self.dim];
+        position[0] = x;
+        position[1] = y;
+        if let (Some(z), 3) = (z, self.dim) {
+            position[2] = z;
+        }
+
+        self.enforce_boundary();
+        self.positions_changed();
+
+        Ok(())
+    }
+
+    /// Set the phase of a single agent. Like `set_agent_position`, this is usually
+    /// used on pinned agents, which keep the phase they are given.
+    /// # Arguments
+    /// - `index`: Index of the agent.
+    /// - `phase`: New phase of the agent.
+    /// # Errors
+    /// Returns an error if `index` is not less than the number of agents, or
+    /// `phase` is not finite.
+    pub fn set_agent_phase(&mut self, index: usize, phase: f64) -> Result<(), Error> {
+        if index >= self.agents {
+            return Err(Error::new("Agent index must be less than agents"));
+        }
+        check_finite("Phase", phase)?;
+
+        self.phases[index] = phase;
+
+        Ok(())
+    }
+
+    /// Set the natural frequency of a single agent.
+    /// # Arguments
+    /// - `index`: Index of the agent.
+    /// - `omega`: New natural frequency of the agent.
+    /// # Errors
+    /// Returns an error if `index` is not less than the number of agents, or
+    /// `omega` is not finite.
+    pub fn set_agent_natural_frequency(&mut self, index: usize, omega: f64) -> Result<(), Error> {
+        if index >= self.agents {
+            return Err(Error::new("Agent index must be less than agents"));
+        }
+        check_finite("Natural frequency", omega)?;
+
+        self.natural_frequencies[index] = omega;
+
+        Ok(())
+    }
+
+    /// Set the chiral value of a single agent. If the system isn't chiral yet it
+    /// becomes chiral, with every other agent given a chiral value of zero.
+    /// # Arguments
+    /// - `index`: Index of the agent.
+    /// - `chiral`: New chiral value of the agent.
+    /// # Errors
+    /// Returns an error if `index` is not less than the number of agents, or
+    /// `chiral` is not finite.
+    pub fn set_agent_chiral(&mut self, index: usize, chiral: f64) -> Result<(), Error> {
+        if index >= self.agents {
+            return Err(Error::new("Agent index must be less than agents"));
+        }
+        check_finite("Chiral value", chiral)?;
+
+        let agents = self.agents;
+        self.chiral.get_or_insert_with(|| vec![0.0; agents])[index] = chiral;
+
+        Ok(())
+    }
+
+    /// Returns a snapshot of a single agent, e.g. for a tooltip or inspector.
+    /// # Arguments
+    /// - `index`: Index of the agent.
+    /// # Errors
+    /// Returns an error if `index` is not less than the number of agents.
+    pub fn get_agent(&self, index: usize) -> Result<AgentInfo, Error> {
+        if index >= self.agents {
+            return Err(Error::new("Agent index must be less than agents"));
+        }
+
+        let mut position = [0.0; 3];
+        position[..self.dim].copy_from_slice(self.position(&self.positions, index));
+        let mut velocity = [0.0; 3];
+        velocity[..self.dim].copy_from_slice(self.position(&self.velocities, index));
+
+        Ok(AgentInfo {
+            x: position[0],
+            y: position[1],
+            z: position[2],
+            vx: velocity[0],
+            vy: velocity[1],
+            vz: velocity[2],
+            phase: self.phases[index],
+            omega: self.natural_frequencies[index],
+            chiral: self.chiral.as_ref().map_or(0.0, |chiral| chiral[index]),
+            species: self.species[index],
+            pinned: self.pinned[index],
+        })
+    }
+
+    /// Returns the index of the agent nearest to `(x, y)`, or `None` if there are no
+    /// agents.
+    ///
+    /// Distances are measured in the xy-plane, so in 3D this picks the agent under a
+    /// click on a top-down view. With periodic boundaries the nearest image counts.
+    /// # Arguments
+    /// - `x`, `y`: Point to search from.
+    pub fn find_nearest(&self, x: f64, y: f64) -> Option<usize> {
+        (0..self.agents)
+            .map(|i| {
+                let position = self.position(&self.positions, i);
+                let mut point = [x, y, 0.0];
+                point[2..self.dim].copy_from_slice(&position[2..]);
+                let d = self.displacement(&point[..self.dim], position);
+                (i, d[0] * d[0] + d[1] * d[1])
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(i, _)| i)
+    }
+
+    /// Set stochastic noise on the positions and phases.
+    ///
+    /// Each `update` adds independent Gaussian increments with standard deviations
+    /// `position_sigma * sqrt(dt)` to each coordinate and `phase_sigma * sqrt(dt)` to
+    /// each phase. The increments come from a generator seeded with `seed`, so noisy
+    /// runs are reproducible. With both sigmas at zero the noise is removed and the
+    /// dynamics are deterministic again.
+    /// # Arguments
+    /// - `position_sigma`: Noise strength on the positions.
+    /// - `phase_sigma`: Noise strength on the phases.
+    /// - `seed`: Seed for the noise generator.
+    /// # Errors
+    /// Returns an error if either sigma is not finite.
+    pub fn set_noise(
+        &mut self,
+        position_sigma: f64,
+        phase_sigma: f64,
+        seed: u64,
+    ) -> Result<(), Error> {
+        check_finite("Position sigma", position_sigma)?;
+        check_finite("Phase sigma", phase_sigma)?;
+
+        self.noise = if position_sigma == 0.0 && phase_sigma == 0.0 {
+            None
+        } else {
+            Some(Noise {
+                position_sigma,
+                phase_sigma,
+                seed,
+                rng: ChaCha12Rng::seed_from_u64(seed),
+            })
+        };
+
+        Ok(())
+    }
+
+    /// Set stochastic noise from diffusion coefficients.
+    ///
+    /// The usual parametrisation in the swarmalator literature, where the Langevin
+    /// terms `sqrt(2 D) ξ(t)` are white noise. Equivalent to `set_noise` with
+    /// `position_sigma = sqrt(2 * D_pos)` and `phase_sigma = sqrt(2 * D_phase)`.
+    /// # Arguments
+    /// - `D_pos`: Translational diffusion coefficient.
+    /// - `D_phase`: Phase diffusion coefficient.
+    /// - `seed`: Seed for the noise generator.
+    /// # Errors
+    /// Returns an error if either coefficient is negative or not finite.
+    pub fn set_diffusion(&mut self, D_pos: f64, D_phase: f64, seed: u64) -> Result<(), Error> {
+        if !(D_pos.is_finite() && D_pos >= 0.0 && D_phase.is_finite() && D_phase >= 0.0) {
+            return Err(Error::new(
+                "Diffusion coefficients must be finite and not negative",
+            ));
+        }
+
+        self.set_noise((2.0 * D_pos).sqrt(), (2.0 * D_phase).sqrt(), seed)
+    }
+
+    /// Set the exponent of the short-range repulsion.
+    ///
+    /// The repulsion term in `update` becomes `B * (x_j - x_i) / |x_j - x_i|^p`, so
+    /// its magnitude falls off as `1 / r^(p - 1)` against the attraction, whose
+    /// magnitude `A` doesn't depend on distance with the default attraction
+    /// exponent. For a pair the two balance at
+    /// `r = (B / A)^(1 / (p - 1))`, so `p` must be greater than 1 for the repulsion
+    /// to win at short range, and larger `p` gives a harder core. The default `p = 2`
+    /// is the standard model. For large `p` the minimum distance should be raised,
+    /// since `r^p` underflows and the repulsion becomes enormous.
+    /// # Arguments
+    /// - `p`: New repulsion exponent.
+    /// # Errors
+    /// Returns an error if `p` is not finite.
+    pub fn set_repulsion_exponent(&mut self, p: f64) -> Result<(), Error> {
+        check_finite("Repulsion exponent", p)?;
+
+        self.repulsion_exponent = p;
+
+        Ok(())
+    }
+
+    /// Returns the exponent of the short-range repulsion.
+    pub fn repulsion_exponent(&self) -> f64 {
+        self.repulsion_exponent
+    }
+
+    /// Set the exponent of the spatial attraction.
+    ///
+    /// The attraction term in `update` becomes
+    /// `(A + J cos(φ_j - φ_i)) * (x_j - x_i) / |x_j - x_i|^p`, so its magnitude
+    /// falls off as `1 / r^(p - 1)`. The default `p = 1` is the standard model, with
+    /// an attraction that doesn't weaken with distance. The repulsion must still
+    /// fall off faster, i.e. its exponent must be larger, for agents to keep apart.
+    /// # Arguments
+    /// - `p`: New attraction exponent.
+    /// # Errors
+    /// Returns an error if `p` is not finite.
+    pub fn set_attraction_exponent(&mut self, p: f64) -> Result<(), Error> {
+        check_finite("Attraction exponent", p)?;
+
+        self.attraction_exponent = p;
+
+        Ok(())
+    }
+
+    /// Returns the exponent of the attraction.
+    pub fn attraction_exponent(&self) -> f64 {
+        self.attraction_exponent
+    }
+
+    /// Set the exponent of the phase coupling kernel.
+    ///
+    /// The phase coupling term in `update` becomes `K sin(φ_j - φ_i) / |x_j - x_i|^p`.
+    /// The default `p = 1` is the standard model and `p = 0` gives the global
+    /// Kuramoto coupling, independent of distance.
+    /// # Arguments
+    /// - `p`: New phase coupling exponent.
+    /// # Errors
+    /// Returns an error if `p` is not finite.
+    pub fn set_phase_coupling_exponent(&mut self, p: f64) -> Result<(), Error> {
+        check_finite("Phase coupling exponent", p)?;
+
+        self.phase_coupling_exponent = p;
+
+        Ok(())
+    }
+
+    /// Returns the exponent of the phase coupling.
+    pub fn phase_coupling_exponent(&self) -> f64 {
+        self.phase_coupling_exponent
+    }
+
+    /// Set the Sakaguchi phase lag.
+    ///
+    /// The phase coupling term in `update` becomes `sin(n(φ_j - φ_i) - α)`. A lag
+    /// frustrates synchronization: agents lock with a phase offset and, for `α`
+    /// near `π / 2`, coherence breaks down. The default `α = 0` has no lag.
+    /// # Arguments
+    /// - `alpha`: Phase lag in radians.
+    /// # Errors
+    /// Returns an error if `alpha` is not finite.
+    pub fn set_phase_lag(&mut self, alpha: f64) -> Result<(), Error> {
+        check_finite("Phase lag", alpha)?;
+
+        self.phase_lag = alpha;
+
+        Ok(())
+    }
+
+    /// Returns the phase lag.
+    pub fn phase_lag(&self) -> f64 {
+        self.phase_lag
+    }
+
+    /// Drives a scalar parameter from keyframes as the simulation runs.
+    ///
+    /// The parameter is set to the scheduled value at the start of every step, using
+    /// the simulation time before the step, replacing any earlier schedule for the
+    /// same parameter. Before the first keyframe and after the last one the
+    /// parameter holds that keyframe's value. Setting the parameter directly has no
+    /// lasting effect while it is scheduled.
+    ///
+    /// # Arguments
+    /// - `parameter`: Parameter to drive.
+    /// - `keyframes`: Flattened `[t0, v0, t1, v1, ...]` pairs of simulation time and
+    ///   value, in increasing time.
+    /// - `interpolation`: How the value moves between keyframes.
+    ///
+    /// # Errors
+    /// Returns an error if `keyframes` is empty or has an odd length, its times are
+    /// not strictly increasing, or any value is not finite.
+    pub fn schedule_parameter(
+        &mut self,
+        parameter: ScheduledParameter,
+        keyframes: Vec<f64>,
+        interpolation: Interpolation,
+    ) -> Result<(), Error> {
+        let schedule = Schedule::new(parameter, &keyframes, interpolation)?;
+
+        self.schedules.retain(|s| s.parameter != parameter);
+        self.schedules.push(schedule);
+
+        Ok(())
+    }
+
+    /// Stops driving `parameter`, leaving it at its last scheduled value.
+    pub fn clear_schedule(&mut self, parameter: ScheduledParameter) {
+        self.schedules.retain(|s| s.parameter != parameter);
+    }
+
+    /// Stops driving every scheduled parameter.
+    pub fn clear_schedules(&mut self) {
+        self.schedules.clear();
+    }
+
+    /// Set the phase coupling coefficient.
+    ///
+    /// Applies to every agent, clearing any per-agent or per-pair values from
+    /// `set_K_vec` and `set_K_matrix`.
+    /// # Arguments
+    /// - `K`: New value for K
+    /// # Errors
+    /// Returns an error if `K` is not finite.
+    pub fn set_K(&mut self, K: f64) -> Result<(), Error> {
+        check_finite("K", K)?;
+
+        self.K = K;
+        self.K_vec = None;
+        self.K_matrix = None;
+
+        Ok(())
+    }
+
+    /// Returns the scalar phase coupling coefficient.
+    ///
+    /// Per-agent, per-pair and species couplings take precedence over it where set.
+    pub fn K(&self) -> f64 {
+        self.K
+    }
+
+    /// Set the spatial-phase interaction coefficient.
+    ///
+    /// Applies to every agent, clearing any per-agent or per-pair values from
+    /// `set_J_vec` and `set_J_matrix`.
+    /// # Arguments
+    /// - `J`: New value for J
+    /// # Errors
+    /// Returns an error if `J` is not finite.
+    pub fn set_J(&mut self, J: f64) -> Result<(), Error> {
+        check_finite("J", J)?;
+
+        self.J = J;
+        self.J_vec = None;
+        self.J_matrix = None;
+
+        Ok(())
+    }
+
+    /// Returns the scalar spatial-phase interaction coefficient.
+    ///
+    /// Per-agent, per-pair and species couplings take precedence over it where set.
+    pub fn J(&self) -> f64 {
+        self.J
+    }
+
+    /// Set per-agent phase coupling coefficients.
+    ///
+    /// Agent `i`'s phase velocity uses `K_vec[i]` in place of `K`.
+    /// # Arguments
+    /// - `K_vec`: Coefficient for each agent, or `None` to use `K` for all of them.
+    /// # Errors
+    /// Returns an error if the length of `K_vec` is not equal to the number of agents, or
+    /// any value is not finite.
+    pub fn set_K_vec(&mut self, K_vec: Option<Vec<f64>>) -> Result<(), Error> {
+        if K_vec
+            .as_ref()
+            .is_some_and(|K_vec| K_vec.len() != self.agents)
+        {
+            return Err(Error::new("K array must have agents elements"));
+        }
+        check_all_finite("K", K_vec.as_deref().unwrap_or_default())?;
+
+        self.K_vec = K_vec;
+
+        Ok(())
+    }
+
+    /// Set per-agent spatial-phase interaction coefficients.
+    ///
+    /// Agent `i`'s velocity uses `J_vec[i]` in place of `J`. While a target is set
+    /// the target-based rescaling still takes precedence.
+    /// # Arguments
+    /// - `J_vec`: Coefficient for each agent, or `None` to use `J` for all of them.
+    /// # Errors
+    /// Returns an error if the length of `J_vec` is not equal to the number of agents, or
+    /// any value is not finite.
+    pub fn set_J_vec(&mut self, J_vec: Option<Vec<f64>>) -> Result<(), Error> {
+        if J_vec
+            .as_ref()
+            .is_some_and(|J_vec| J_vec.len() != self.agents)
+        {
+            return Err(Error::new("J array must have agents elements"));
+        }
+        check_all_finite("J", J_vec.as_deref().unwrap_or_default())?;
+
+        self.J_vec = J_vec;
+
+        Ok(())
+    }
+
+    /// Set per-pair phase coupling coefficients.
+    ///
+    /// The phase coupling of agent `i` to agent `j` uses `K_matrix[i * agents + j]`,
+    /// taking precedence over `K_vec` and `K`. The matrix needn't be symmetric. It
+    /// takes O(N²) memory, and while it is set the far-field approximation is
+    /// replaced by exact interactions.
+    /// # Arguments
+    /// - `K_matrix`: Row-major coefficient matrix, or `None` to remove it.
+    /// # Errors
+    /// Returns an error if the length of `K_matrix` is not equal to the number of agents
+    /// squared, or any value is not finite.
+    pub fn set_K_matrix(&mut self, K_matrix: Option<Vec<f64>>) -> Result<(), Error> {
+        if K_matrix
+            .as_ref()
+            .is_some_and(|K_matrix| K_matrix.len() != self.agents * self.agents)
+        {
+            return Err(Error::new("K matrix must have agents * agents elements"));
+        }
+        check_all_finite("K matrix", K_matrix.as_deref().unwrap_or_default())?;
+
+        self.K_matrix = K_matrix;
+
+        Ok(())
+    }
+
+    /// Set per-pair spatial-phase interaction coefficients.
+    ///
+    /// The velocity of agent `i` due to agent `j` uses `J_matrix[i * agents + j]`,
+    /// taking precedence over `J_vec` and `J`. While a target is set the
+    /// target-based rescaling still takes precedence. Like `set_K_matrix`, this
+    /// disables the far-field approximation.
+    /// # Arguments
+    /// - `J_matrix`: Row-major coefficient matrix, or `None` to remove it.
+    /// # Errors
+    /// Returns an error if the length of `J_matrix` is not equal to the number of agents
+    /// squared, or any value is not finite.
+    pub fn set_J_matrix(&mut self, J_matrix: Option<Vec<f64>>) -> Result<(), Error> {
+        if J_matrix
+            .as_ref()
+            .is_some_and(|J_matrix| J_matrix.len() != self.agents * self.agents)
+        {
+            return Err(Error::new("J matrix must have agents * agents elements"));
+        }
+        check_all_finite("J matrix", J_matrix.as_deref().unwrap_or_default())?;
+
+        self.J_matrix = J_matrix;
+
+        Ok(())
+    }
+
+    /// Set the species of every agent.
+    ///
+    /// Species only matter once coupling constants are set with
+    /// `set_species_coupling`. All agents start as species 0.
+    /// # Arguments
+    /// - `species`: Species id of each agent.
+    /// # Errors
+    /// Returns an error if the length of `species` is not equal to the number of agents, or
+    /// if species coupling is set and any id is not less than the number of species.
+    pub fn set_species(&mut self, species: Vec<u32>) -> Result<(), Error> {
+        if species.len() != self.agents {
+            return Err(Error::new("Species array must have agents elements"));
+        }
+
+        if let Some(coupling) = self.species_coupling.as_ref() {
+            if species.iter().any(|&s| s as usize >= coupling.count) {
+                return Err(Error::new(
+                    "Species id must be less than the number of species",
+                ));
+            }
+        }
+
+        self.species = species;
+
+        Ok(())
+    }
+
+    /// Returns the species id of every agent.
+    pub fn species(&self) -> Vec<u32> {
+        self.species.clone()
+    }
+
+    /// Set the coupling constants between species.
+    ///
+    /// Each argument is a row-major `S × S` matrix for `S` species, whose entry
+    /// `[a * S + b]` is used when an agent of species `a` interacts with one of
+    /// species `b`, in place of the scalar `A`, `B`, `J` and `K` and any per-agent
+    /// values. Asymmetric matrices give non-reciprocal interactions such as
+    /// predators chasing prey that flee. Per-pair matrices from `set_K_matrix` and
+    /// `set_J_matrix` still take precedence, as does the target-based `J`
+    /// rescaling. Like them, species coupling disables the far-field approximation.
+    /// # Arguments
+    /// - `A`: Attraction coefficients.
+    /// - `B`: Repulsion coefficients.
+    /// - `J`: Spatial-phase interaction coefficients.
+    /// - `K`: Phase coupling coefficients.
+    /// # Errors
+    /// Returns an error if the matrices are not all the same square length or hold
+    /// a value that is not finite, or if any agent's species id is not less than `S`.
+    pub fn set_species_coupling(
+        &mut self,
+        A: Vec<f64>,
+        B: Vec<f64>,
+        J: Vec<f64>,
+        K: Vec<f64>,
+    ) -> Result<(), Error> {
+        let count = (A.len() as f64).sqrt().round() as usize;
+        if count * count != A.len() || [&B, &J, &K].iter().any(|m| m.len() != A.len()) {
+            return Err(Error::new(
+                "Species coupling matrices must all have species * species elements",
+            ));
+        }
+
+        for (name, matrix) in [("A", &A), ("B", &B), ("J", &J), ("K", &K)] {
+            check_all_finite(name, matrix)?;
+        }
+
+        if self.species.iter().any(|&s| s as usize >= count) {
+            return Err(Error::new(
+                "Species id must be less than the number of species",
+            ));
+        }
+
+        self.species_coupling = Some(SpeciesCoupling { count, A, B, J, K });
+
+        Ok(())
+    }
+
+    /// Removes the species coupling so every agent uses the scalar and per-agent
+    /// coefficients again. The species ids are kept.
+    pub fn clear_species_coupling(&mut self) {
+        self.species_coupling = None;
+    }
+
+    /// Set the chiral values.
+    ///
+    /// Agent `i` gains the velocity `chiral_i * (cos(φ_i + π/2), sin(φ_i + π/2))`,
+    /// which in 3D acts in the xy-plane.
+    /// # Arguments
+    /// - `chiral`: New chiral values, or `None` to remove them.
+    /// # Errors
+    /// Returns an error if the length of `chiral` is not equal to the number of
+    /// agents, or any value is not finite.
+    pub fn set_chiral(&mut self, chiral: Option<Vec<f64>>) -> Result<(), Error> {
+        if let Some(chiral) = chiral.as_ref() {
+            if chiral.len() != self.agents {
+                return Err(Error::new("Chiral array must have agents elements"));
+            }
+            check_all_finite("Chiral", chiral)?;
+        }
+
+        self.chiral = chiral;
+
+        Ok(())
+    }
+
+    /// Returns the chiral value of each agent, if set.
+    pub fn chiral(&self) -> Option<Vec<f64>> {
+        self.chiral.clone()
+    }
+
+    /// Set the chiral values by drawing them from a distribution.
+    ///
+    /// Each agent turns counter-clockwise (a positive chiral value) with probability
+    /// `fraction_ccw` and clockwise otherwise, with a magnitude of `|m|` for `m`
+    /// drawn from a normal distribution. Natural frequencies are left alone; in the
+    /// frequency-difference term agents pair up by the sign of their natural
+    /// frequency, or of their chiral value where the frequency is zero.
+    /// # Arguments
+    /// - `fraction_ccw`: Probability of each agent turning counter-clockwise.
+    /// - `magnitude_mean`: Mean of the chiral magnitudes.
+    /// - `magnitude_std`: Standard deviation of the chiral magnitudes.
+    /// - `seed`: Seed for the random number generator.
+    /// # Errors
+    /// Returns an error if `fraction_ccw` is not in `[0, 1]`, `magnitude_mean` is not
+    /// finite, or `magnitude_std` is negative or not finite.
+    pub fn set_chirality_distribution(
+        &mut self,
+        fraction_ccw: f64,
+        magnitude_mean: f64,
+        magnitude_std: f64,
+        seed: u64,
+    ) -> Result<(), Error> {
+        if !(0.0..=1.0).contains(&fraction_ccw) {
+            return Err(Error::new("Counter-clockwise fraction must be in [0, 1]"));
+        }
+        check_finite("Magnitude mean", magnitude_mean)?;
+        if !magnitude_std.is_finite() || magnitude_std < 0.0 {
+            return Err(Error::new(
+                "Magnitude standard deviation must be non-negative and finite",
+            ));
+        }
+
+        let mut rng = ChaCha12Rng::seed_from_u64(seed);
+        let chiral = (0..self.agents)
+            .map(|_| {
+                let z: f64 = rng.sample(StandardNormal);
+                let magnitude = (magnitude_mean + magnitude_std * z).abs();
+                if rng.gen_bool(fraction_ccw) {
+                    magnitude
+                } else {
+                    -magnitude
+                }
+            })
+            .collect();
+        self.chiral = Some(chiral);
+
+        Ok(())
+    }
+
+    /// Set the natural frequencies.
+    /// # Arguments
+    /// - `natural_frequencies`: New natural frequencies.
+    /// # Errors
+    /// Returns an error if the length of `natural_frequencies` is not equal to the
+    /// number of agents, or any value is not finite.
+    pub fn set_natural_frequencies(&mut self, natural_frequencies: Vec<f64>) -> Result<(), Error> {
+        if natural_frequencies.len() != self.agents {
+            return Err(Error::new(
+                "Natural frequencies array must have agents elements",
+            ));
+        }
+        check_all_finite("Natural frequencies", &natural_frequencies)?;
+
+        self.natural_frequencies = natural_frequencies;
+
+        Ok(())
+    }
+
+    /// Set the rate at which natural frequencies adapt.
+    ///
+    /// Each step every natural frequency drifts toward that agent's instantaneous
+    /// frequency: `ω_i += rate * (dφ_i/dt - ω_i) * dt`. A rate of `0` keeps the
+    /// frequencies fixed.
+    /// # Arguments
+    /// - `rate`: Adaptation rate.
+    /// # Errors
+    /// Returns an error if `rate` is not finite.
+    pub fn set_frequency_adaptation(&mut self, rate: f64) -> Result<(), Error> {
+        check_finite("Adaptation rate", rate)?;
+
+        self.frequency_adaptation = rate;
+
+        Ok(())
+    }
+
+    /// Returns the rate at which natural frequencies adapt.
+    pub fn frequency_adaptation(&self) -> f64 {
+        self.frequency_adaptation
+    }
+
+    /// Set a spatial gradient for the natural frequencies.
+    ///
+    /// The effective natural frequency of agent `i` in `update` becomes
+    /// `natural_frequency_i + gx * x_i + gy * y_i`, using the position at the start
+    /// of the step. The chiral frequency-difference term still uses the unshifted
+    /// natural frequencies. A zero gradient leaves the dynamics unchanged. In 3D the
+    /// frequencies don't vary along z.
+    /// # Arguments
+    /// - `gx`: Frequency change per unit x.
+    /// - `gy`: Frequency change per unit y.
+    /// # Errors
+    /// Returns an error if `gx` or `gy` is not finite.
+    pub fn set_frequency_gradient(&mut self, gx: f64, gy: f64) -> Result<(), Error> {
+        check_finite("Frequency gradient", gx)?;
+        check_finite("Frequency gradient", gy)?;
+
+        self.frequency_gradient = (gx, gy);
+
+        Ok(())
+    }
+
+    /// Returns the spatial gradient of the natural frequencies as `[gx, gy]`.
+    pub fn frequency_gradient(&self) -> Vec<f64> {
+        vec![self.frequency_gradient.0, self.frequency_gradient.1]
+    }
+
+    /// Hold the centre of mass fixed.
+    ///
+    /// When enabled, `update` subtracts the net displacement of the centre of mass
+    /// from every agent after integrating, so the swarm never drifts. The internal
+    /// dynamics are translation invariant so they are unaffected. Pinned agents
+    /// stay put, so the unpinned agents are shifted by enough to hold the centre of
+    /// mass of the whole swarm. This is a no-op under periodic boundaries, where the
+    /// centre of mass is not well defined.
+    /// # Arguments
+    /// - `fix`: Whether to pin the centre of mass.
+    pub fn set_fix_center_of_mass(&mut self, fix: bool) {
+        self.fix_center_of_mass = fix;
+    }
+
+    /// Returns whether the centre of mass is held fixed.
+    pub fn fix_center_of_mass(&self) -> bool {
+        self.fix_center_of_mass
+    }
+
+    /// Set the phases
+    /// # Arguments
+    /// - `phases`: New phases.
+    ///
+    /// # Errors
+    /// Returns an error if the length of `phases` is not equal to the number of agents, or
+    /// any phase is not finite.
+    pub fn set_phases(&mut self, phases: Vec<f64>) -> Result<(), Error> {
+        if phases.len() != self.agents {
+            return Err(Error::new("Phases array must have agents elements"));
+        }
+        check_all_finite("Phases", &phases)?;
+
+        self.phases = phases;
+
+        Ok(())
+    }
+}
+
+impl Swarmalator {
+    /// Creates a new Swarmalator instance in `dim` dimensions.
+    ///
+    /// # Errors
+    /// Returns an error if `dim` is not 2 or 3, the length of `positions` is not
+    /// equal to `dim * agents`, the other arrays don't match the number of agents,
+    /// or any value is not finite.
+    #[allow(clippy::too_many_arguments)]
+    fn with_dimension(
+        dim: usize,
+        agents: usize,
+        positions: Vec<f64>,
+        phases: Vec<f64>,
+        natural_frequencies: Vec<f64>,
+        K: f64,
+        J: f64,
+        chiral: Option<Vec<f64>>,
+        target: Option<Vec<f64>>,
+    ) -> Result<Swarmalator, Error> {
+        utils::set_panic_hook();
+
+        if dim != 2 && dim != 3 {
+            return Err(Error::new("Dimension must be 2 or 3"));
+        }
+
+        // Check the length of the arrays
+        if positions.len() != agents * dim {
+            return Err(Error::new(&format!(
+                "Positions array must have {} * agents elements",
+                dim
+            )));
+        }
+
+        if phases.len() != agents {
+            return Err(Error::new("Phases array must have agents elements"));
+        }
+
+        if natural_frequencies.len() != agents {
+            return Err(Error::new(
+                "Natural frequencies array must have agents elements",
+            ));
+        }
+
+        if chiral.as_ref().is_some_and(|chiral| chiral.len() != agents) {
+            return Err(Error::new("Chiral array must have agents elements"));
+        }
+
+        if target.as_ref().is_some_and(|target| target.len() != dim) {
+            return Err(Error::new(&format!(
+                "Target array must have {} elements",
+                dim
+            )));
+        }
+
+        // A single bad value would spread to every agent within a step
+        check_all_finite("Positions", &positions)?;
+        check_all_finite("Phases", &phases)?;
+        check_all_finite("Natural frequencies", &natural_frequencies)?;
+        check_all_finite("Chiral", chiral.as_deref().unwrap_or_default())?;
+        check_all_finite("Target", target.as_deref().unwrap_or_default())?;
+        check_finite("K", K)?;
+        check_finite("J", J)?;
+
+        // All agents start stationary
+        let velocities: Vec<f64> = vec![0.0; agents * dim];
+
+        // We store delta_phase so we get the dt from update
+        let delta_phases: Vec<f64> = vec![0.0; agents];
+
+        Ok(Swarmalator {
+            agents,
+            dim,
+            A: 1.0,
+            B: 1.0,
+            K,
+            J,
+            K_vec: None,
+            J_vec: None,
+            K_matrix: None,
+            J_matrix: None,
+            species: vec![0; agents],
+            species_coupling: None,
+            chiral,
+            target,
+            target_gain: 1.0,
+            target_assignment: None,
+            natural_frequencies,
+            velocities,
+            phases,
+            delta_phases,
+            positions: positions.clone(),
+            grid: OnceLock::new(),
+            grid_enabled: false,
+            frequency_adaptation: 0.0,
+            frequency_gradient: (0.0, 0.0),
+            fix_center_of_mass: false,
+            phase_target: (0.0, 0.0),
+            phase_targets: None,
+            phase_repulsion_coupling: 0.0,
+            phase_harmonic: 1,
+            integration_scheme: Scheme::Explicit,
+            min_distance: 1e-6,
+            integrator: IntegratorKind::Euler,
+            boundary: Boundary::Open,
+            arena_size: 0.0,
+            confinement_stiffness: 0.0,
+            cutoff: None,
+            pinned: vec![false; agents],
+            noise: None,
+            repulsion_exponent: 2.0,
+            attraction_exponent: 1.0,
+            phase_coupling_exponent: 1.0,
+            phase_lag: 0.0,
+            far_field: false,
+            tolerance: 1e-6,
+            environment: Environment::default(),
+            recording: None,
+            time: 0.0,
+            schedules: Vec::new(),
+            topology: None,
+            collisions: None,
+            max_speed: None,
+            formation: None,
+            pointer: None,
+            watches: Vec::new(),
+            #[cfg(feature = "webgpu")]
+            pairwise: None,
+            fixed_timestep: None,
+            accumulator: 0.0,
+            previous_positions: Vec::new(),
+            mean_field: false,
+            softening: 0.0,
+            nan_policy: NanPolicy::Ignore,
+            max_displacement: None,
+            averaging: None,
+        })
+    }
+
+    /// Creates a Swarmalator in `dim` dimensions with randomly initialised agents
+    /// from a seed, as described in `random`.
+    fn random_with_dimension(
+        dim: usize,
+        agents: usize,
+        seed: u64,
+        K: f64,
+        J: f64,
+        frequency_spread: f64,
+    ) -> Result<Swarmalator, Error> {
+        check_finite("Frequency spread", frequency_spread)?;
+
+        let mut rng = ChaCha12Rng::seed_from_u64(seed);
+
+        let positions: Vec<f64> = (0..agents * dim)
+            .map(|_| rng.gen_range(-1.0..1.0))
+            .collect();
+        let phases: Vec<f64> = (0..agents).map(|_| rng.gen_range(0.0..2.0 * PI)).collect();
+        let spread = frequency_spread.abs();
+        let natural_frequencies: Vec<f64> = (0..agents)
+            .map(|_| rng.gen_range(-spread..=spread))
+            .collect();
+
+        Swarmalator::with_dimension(
+            dim,
+            agents,
+            positions,
+            phases,
+            natural_frequencies,
+            K,
+            J,
+            None,
+            None,
+        )
+    }
+}
+
+/// Boundary fields of a saved state, to tell states saved with only a periodic box
+/// size apart from those with an open boundary.
+#[derive(Deserialize)]
+struct LegacyBoundary {
+    #[serde(default)]
+    boundary: Option<Boundary>,
+    #[serde(default)]
+    periodic: Option<f64>,
+}
+
+/// Number of spatial dimensions of states saved before 3D support.
+fn default_dim() -> usize {
+    2
+}
+
+/// Repulsion exponent of states saved before it was configurable.
+fn default_repulsion_exponent() -> f64 {
+    2.0
+}
+
+/// Attraction and phase coupling exponents of states saved before they were
+/// configurable.
+fn default_unit_exponent() -> f64 {
+    1.0
+}
+
+/// Tolerance of states saved before the adaptive integrator.
+fn default_tolerance() -> f64 {
+    1e-6
+}
+
+/// Target gain of states saved before it was separate from `A`.
+fn default_target_gain() -> f64 {
+    1.0
+}
+
+/// Returns an error naming `name` unless `value` is finite.
+fn check_finite(name: &str, value: f64) -> Result<(), Error> {
+    if !value.is_finite() {
+        return Err(Error::new(&format!("{} must be finite", name)));
+    }
+
+    Ok(())
+}
+
+/// Returns an error naming `name` unless every element of `values` is finite.
+fn check_all_finite(name: &str, values: &[f64]) -> Result<(), Error> {
+    if values.iter().any(|value| !value.is_finite()) {
+        return Err(Error::new(&format!(
+            "{} array must only contain finite values",
+            name
+        )));
+    }
+
+    Ok(())
+}
+
+/// Grows the row-major `n × n` `matrix` by `extra` rows and columns filled with `fill`.
+fn grow_matrix(matrix: &mut Vec<f64>, n: usize, extra: usize, fill: f64) {
+    let size = n + extra;
+    let mut grown = vec![fill; size * size];
+    for row in 0..n {
+        grown[row * size..row * size + n].copy_from_slice(&matrix[row * n..(row + 1) * n]);
+    }
+
+    *matrix = grown;
+}
+
+/// Removes row and column `index` from the row-major `n × n` `matrix`.
+fn shrink_matrix(matrix: &mut Vec<f64>, n: usize, index: usize) {
+    *matrix = (0..n * n)
+        .filter(|k| k / n != index && k % n != index)
+        .map(|k| matrix[k])
+        .collect();
 }
@@ -0,0 +1,81 @@
+// All-to-all pairwise sums of the swarmalator model, one invocation per agent.
+//
+// Each agent is `(x, y, z, phase)`. For agent `i` the sums over every other agent
+// `j`, with `d = x_j - x_i`, `r = max(|d|, min_distance)` and `Δ = φ_j - φ_i`, are
+// written as three vec4s:
+//
+// - `(Σ d / r, Σ sin(nΔ) / r)`
+// - `(Σ cos(Δ) d / r, Σ cos(nΔ) / r)`
+// - `(Σ d / r², 0)`
+//
+// The coefficients A, B, J and K are applied on the CPU, so parameter changes
+// never need a new upload.
+
+struct Params {
+    agents: u32,
+    harmonic: f32,
+    min_distance: f32,
+    _padding: f32,
+}
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<storage, read> agents: array<vec4<f32>>;
+@group(0) @binding(2) var<storage, read_write> sums: array<vec4<f32>>;
+
+const TILE: u32 = 64u;
+
+// Agents are staged through workgroup memory a tile at a time
+var<workgroup> tile: array<vec4<f32>, TILE>;
+
+@compute @workgroup_size(64)
+fn main(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(local_invocation_id) local_id: vec3<u32>,
+) {
+    let i = global_id.x;
+    let in_range = i < params.agents;
+
+    var own = vec4<f32>(0.0);
+    if in_range {
+        own = agents[i];
+    }
+
+    var attraction = vec3<f32>(0.0);
+    var aligned = vec3<f32>(0.0);
+    var repulsion = vec3<f32>(0.0);
+    var sin_harmonic = 0.0;
+    var cos_harmonic = 0.0;
+
+    for (var start = 0u; start < params.agents; start += TILE) {
+        let j = start + local_id.x;
+        if j < params.agents {
+            tile[local_id.x] = agents[j];
+        }
+        workgroupBarrier();
+
+        let count = min(TILE, params.agents - start);
+        for (var k = 0u; k < count; k++) {
+            if start + k == i {
+                continue;
+            }
+
+            let other = tile[k];
+            let d = other.xyz - own.xyz;
+            let inv = 1.0 / max(length(d), params.min_distance);
+            let diff = other.w - own.w;
+
+            attraction += d * inv;
+            aligned += d * (cos(diff) * inv);
+            repulsion += d * (inv * inv);
+            sin_harmonic += sin(params.harmonic * diff) * inv;
+            cos_harmonic += cos(params.harmonic * diff) * inv;
+        }
+        workgroupBarrier();
+    }
+
+    if in_range {
+        sums[3u * i] = vec4<f32>(attraction, sin_harmonic);
+        sums[3u * i + 1u] = vec4<f32>(aligned, cos_harmonic);
+        sums[3u * i + 2u] = vec4<f32>(repulsion, 0.0);
+    }
+}
@@ -0,0 +1,144 @@
+use wasm_bindgen::prelude::*;
+#[cfg(target_arch = "wasm32")]
+use web_sys::js_sys::{Object, Reflect};
+
+use crate::{Error, Swarmalator};
+
+type Getter = fn(&Swarmalator) -> f64;
+type Setter = fn(&mut Swarmalator, f64) -> Result<(), Error>;
+
+/// Every scalar coefficient `set_param` can change, by the name of its getter.
+/// Setting one goes through its typed setter, so the same checks apply.
+const PARAMS: [(&str, Getter, Setter); 16] = [
+    ("A", Swarmalator::A, Swarmalator::set_A),
+    ("B", Swarmalator::B, Swarmalator::set_B),
+    ("K", Swarmalator::K, Swarmalator::set_K),
+    ("J", Swarmalator::J, Swarmalator::set_J),
+    (
+        "target_gain",
+        Swarmalator::target_gain,
+        Swarmalator::set_target_gain,
+    ),
+    (
+        "phase_lag",
+        Swarmalator::phase_lag,
+        Swarmalator::set_phase_lag,
+    ),
+    (
+        "phase_repulsion_coupling",
+        Swarmalator::phase_repulsion_coupling,
+        Swarmalator::set_phase_repulsion_coupling,
+    ),
+    (
+        "repulsion_exponent",
+        Swarmalator::repulsion_exponent,
+        Swarmalator::set_repulsion_exponent,
+    ),
+    (
+        "attraction_exponent",
+        Swarmalator::attraction_exponent,
+        Swarmalator::set_attraction_exponent,
+    ),
+    (
+        "phase_coupling_exponent",
+        Swarmalator::phase_coupling_exponent,
+        Swarmalator::set_phase_coupling_exponent,
+    ),
+    (
+        "min_distance",
+        Swarmalator::min_distance,
+        Swarmalator::set_min_distance,
+    ),
+    (
+        "softening",
+        Swarmalator::softening,
+        Swarmalator::set_softening,
+    ),
+    (
+        "frequency_adaptation",
+        Swarmalator::frequency_adaptation,
+        Swarmalator::set_frequency_adaptation,
+    ),
+    (
+        "tolerance",
+        Swarmalator::tolerance,
+        Swarmalator::set_tolerance,
+    ),
+    ("arena_size", Swarmalator::arena_size, |s, size| {
+        s.set_boundary(s.boundary, size, s.confinement_stiffness)
+    }),
+    (
+        "confinement_stiffness",
+        Swarmalator::confinement_stiffness,
+        |s, stiffness| s.set_boundary(s.boundary, s.arena_size, stiffness),
+    ),
+];
+
+/// Returns the getter and setter of the parameter called `name`.
+fn lookup(name: &str) -> Result<(Getter, Setter), Error> {
+    PARAMS
+        .iter()
+        .find(|(param, _, _)| *param == name)
+        .map(|&(_, get, set)| (get, set))
+        .ok_or_else(|| Error::new(&format!("Unknown parameter '{}'", name)))
+}
+
+impl Swarmalator {
+    /// Returns every scalar parameter `set_param` accepts, paired with its value.
+    pub fn params(&self) -> Vec<(&'static str, f64)> {
+        PARAMS
+            .iter()
+            .map(|(name, get, _)| (*name, get(self)))
+            .collect()
+    }
+}
+
+#[wasm_bindgen]
+impl Swarmalator {
+    /// Returns the names of the parameters `set_param` and `get_param` accept.
+    ///
+    /// Each name is also that of the parameter's getter, with `set_` in front for
+    /// its setter, so a UI can build one slider per name without a binding for each.
+    pub fn param_names() -> Vec<String> {
+        PARAMS.iter().map(|(name, _, _)| name.to_string()).collect()
+    }
+
+    /// Set a scalar parameter by name.
+    ///
+    /// Equivalent to calling the setter of the same name, e.g. `set_param("K", 1.0)`
+    /// is `set_K(1.0)`, with the same effects and checks.
+    /// # Arguments
+    /// - `name`: Name of the parameter, one of `param_names`.
+    /// - `value`: New value.
+    /// # Errors
+    /// Returns an error if `name` is not a parameter, or `value` is out of range for
+    /// it.
+    pub fn set_param(&mut self, name: &str, value: f64) -> Result<(), Error> {
+        let (_, set) = lookup(name)?;
+        set(self, value)
+    }
+
+    /// Returns a scalar parameter by name.
+    /// # Arguments
+    /// - `name`: Name of the parameter, one of `param_names`.
+    /// # Errors
+    /// Returns an error if `name` is not a parameter.
+    pub fn get_param(&self, name: &str) -> Result<f64, Error> {
+        let (get, _) = lookup(name)?;
+        Ok(get(self))
+    }
+
+    /// Returns every scalar parameter as an object from name to value.
+    ///
+    /// Only available on the web, since it builds a JS object. Native code can use
+    /// `params` instead.
+    #[cfg(target_arch = "wasm32")]
+    pub fn get_params(&self) -> Object {
+        let params = Object::new();
+        for (name, value) in self.params() {
+            Reflect::set(&params, &name.into(), &value.into())
+                .expect("Setting a property on a plain object can't fail");
+        }
+        params
+    }
+}
@@ -0,0 +1,37 @@
+use wasm_bindgen::prelude::*;
+
+/// A canonical state of the swarmalator model, passed to `Swarmalator::preset`.
+///
+/// Each preset uses identical agents (`A = B = 1`, no natural frequencies) and the
+/// couplings of O'Keeffe, Hong & Strogatz, "Oscillators that sync and swarm"
+/// (Nat. Commun. 8, 1504, 2017), which settle from random initial conditions into:
+///
+/// - `StaticSync`: `J = 0.1`, `K = 1`. A uniform disk with every phase equal.
+/// - `StaticAsync`: `J = 0.1`, `K = -1`. A uniform disk with phases scrambled.
+/// - `StaticPhaseWave`: `J = 1`, `K = 0`. An annulus with phase locked to angle.
+/// - `SplinteredPhaseWave`: `J = 1`, `K = -0.1`. The annulus breaks into clusters
+///   that jiggle in place.
+/// - `ActivePhaseWave`: `J = 1`, `K = -0.75`. The clusters dissolve into an annulus
+///   whose agents circulate.
+#[wasm_bindgen]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Preset {
+    StaticSync,
+    StaticAsync,
+    StaticPhaseWave,
+    SplinteredPhaseWave,
+    ActivePhaseWave,
+}
+
+impl Preset {
+    /// Returns the couplings `(K, J)` that produce the state.
+    pub fn couplings(self) -> (f64, f64) {
+        match self {
+            Preset::StaticSync => (1.0, 0.1),
+            Preset::StaticAsync => (-1.0, 0.1),
+            Preset::StaticPhaseWave => (0.0, 1.0),
+            Preset::SplinteredPhaseWave => (-0.1, 1.0),
+            Preset::ActivePhaseWave => (-0.75, 1.0),
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use std::collections::VecDeque;
+use std::fmt::Write;
+
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+/// Format of an exported recording, passed to `Swarmalator::export_recording`.
+///
+/// - `Binary`: little-endian `f64`s, loadable with
+///   `numpy.frombuffer(blob, dtype="<f8")`. The blob starts with `[dim, frames]`,
+///   followed by each frame as `[time, agents, positions..., phases...]` where the
+///   positions are `agents * dim` values with stride `dim`.
+/// - `Csv`: UTF-8 text with a `frame,time,agent,x,y,phase` header (`x,y,z` in 3D)
+///   and one row per agent per frame.
+/// - `Json`: UTF-8 text of the form
+///   `{"dim": 2, "frames": [{"time": 0.0, "positions": [...], "phases": [...]}]}`.
+#[wasm_bindgen]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RecordingFormat {
+    Binary,
+    Csv,
+    Json,
+}
+
+/// Positions and phases of the agents at one instant.
+#[derive(Clone, Serialize)]
+struct Frame {
+    time: f64,
+    positions: Vec<f64>,
+    phases: Vec<f64>,
+}
+
+/// A ring buffer of snapshots taken every `interval` steps, keeping at most
+/// `capacity` of the latest frames.
+#[derive(Clone, Serialize)]
+pub struct Recording {
+    #[serde(skip)]
+    interval: usize,
+    #[serde(skip)]
+    capacity: usize,
+    #[serde(skip)]
+    steps: usize,
+    #[serde(skip)]
+    time: f64,
+    #[serde(skip)]
+    active: bool,
+    dim: usize,
+    frames: VecDeque<Frame>,
+}
+
+impl Recording {
+    /// Starts an empty recording in `dim` dimensions.
+    pub fn new(dim: usize, interval: usize, capacity: usize) -> Recording {
+        Recording {
+            interval,
+            capacity,
+            steps: 0,
+            time: 0.0,
+            active: true,
+            dim,
+            frames: VecDeque::with_capacity(capacity.min(1024)),
+        }
+    }
+
+    /// Whether steps are still being captured.
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Stops capturing steps, keeping the frames so far.
+    pub fn stop(&mut self) {
+        self.active = false;
+    }
+
+    /// Number of frames held.
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Counts a step of length `dt`, capturing the state if it falls on the
+    /// interval. The oldest frame is dropped once the buffer is full.
+    pub fn observe(&mut self, dt: f64, positions: &[f64], phases: &[f64]) {
+        if !self.active {
+            return;
+        }
+
+        self.time += dt;
+        self.steps += 1;
+        if !self.steps.is_multiple_of(self.interval) {
+            return;
+        }
+
+        if self.frames.len() == self.capacity {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(Frame {
+            time: self.time,
+            positions: positions.to_vec(),
+            phases: phases.to_vec(),
+        });
+    }
+
+    /// Encodes the frames in `format`.
+    pub fn export(&self, format: RecordingFormat) -> Vec<u8> {
+        match format {
+            RecordingFormat::Binary => {
+                let mut values = vec![self.dim as f64, self.frames.len() as f64];
+                for frame in &self.frames {
+                    values.push(frame.time);
+                    values.push(frame.phases.len() as f64);
+                    values.extend_from_slice(&frame.positions);
+                    values.extend_from_slice(&frame.phases);
+                }
+
+                values.iter().flat_map(|v| v.to_le_bytes()).collect()
+            }
+            RecordingFormat::Csv => {
+                let axes = ["x", "y", "z"][..self.dim].join(",");
+                let mut csv = format!("frame,time,agent,{},phase\n", axes);
+                for (f, frame) in self.frames.iter().enumerate() {
+                    for (i, phase) in frame.phases.iter().enumerate() {
+                        let position = &frame.positions[i * self.dim..(i + 1) * self.dim];
+                        let coordinates: Vec<String> =
+                            position.iter().map(|x| x.to_string()).collect();
+                        let _ = writeln!(
+                            csv,
+                            "{},{},{},{},{}",
+                            f,
+                            frame.time,
+                            i,
+                            coordinates.join(","),
+                            phase
+                        );
+                    }
+                }
+
+                csv.into_bytes()
+            }
+            RecordingFormat::Json => serde_json::to_vec(self).unwrap(),
+        }
+    }
+}
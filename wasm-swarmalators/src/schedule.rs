@@ -0,0 +1,176 @@
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+use crate::cos;
+
+/// A scalar parameter that can follow a schedule, passed to
+/// `Swarmalator::schedule_parameter`.
+///
+/// Schedules drive the scalar coefficients only, so per-agent, per-pair and species
+/// couplings still take precedence over a scheduled `K` or `J`.
+#[wasm_bindgen]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum ScheduledParameter {
+    A,
+    B,
+    K,
+    J,
+    PhaseLag,
+}
+
+/// How a schedule moves between consecutive keyframes.
+///
+/// - `Linear`: straight-line interpolation.
+/// - `Step`: holds each keyframe's value until the next keyframe.
+/// - `Sinusoidal`: half a cosine wave, easing out of one keyframe and into the next
+///   with zero slope at both.
+#[wasm_bindgen]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Interpolation {
+    Linear,
+    Step,
+    Sinusoidal,
+}
+
+/// Keyframes `(time, value)` of one parameter, in increasing time.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Schedule {
+    pub parameter: ScheduledParameter,
+    interpolation: Interpolation,
+    keyframes: Vec<(f64, f64)>,
+}
+
+impl Schedule {
+    /// Builds a schedule from flattened `[t0, v0, t1, v1, ...]` keyframes.
+    pub fn new(
+        parameter: ScheduledParameter,
+        keyframes: &[f64],
+        interpolation: Interpolation,
+    ) -> Result<Schedule, String> {
+        if !keyframes.len().is_multiple_of(2) {
+            return Err("Keyframes must be non-empty [time, value] pairs".to_string());
+        }
+        let schedule = Schedule {
+            parameter,
+            interpolation,
+            keyframes: keyframes.chunks(2).map(|k| (k[0], k[1])).collect(),
+        };
+        schedule.validate()?;
+
+        Ok(schedule)
+    }
+
+    /// Checks there is at least one keyframe, every value is finite and the times
+    /// are strictly increasing.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.keyframes.is_empty() {
+            return Err("Keyframes must be non-empty [time, value] pairs".to_string());
+        }
+        if self
+            .keyframes
+            .iter()
+            .any(|(t, v)| !t.is_finite() || !v.is_finite())
+        {
+            return Err("Keyframes array must only contain finite values".to_string());
+        }
+        if self.keyframes.windows(2).any(|pair| pair[1].0 <= pair[0].0) {
+            return Err("Keyframe times must be strictly increasing".to_string());
+        }
+
+        Ok(())
+    }
+
+    /// Returns the value at `time`, holding the first and last values outside the
+    /// keyframes.
+    pub fn value(&self, time: f64) -> f64 {
+        let next = self.keyframes.partition_point(|&(t, _)| t <= time);
+        if next == 0 {
+            return self.keyframes[0].1;
+        }
+        if next == self.keyframes.len() {
+            return self.keyframes[next - 1].1;
+        }
+
+        let (t0, v0) = self.keyframes[next - 1];
+        let (t1, v1) = self.keyframes[next];
+        let s = (time - t0) / (t1 - t0);
+        let weight = match self.interpolation {
+            Interpolation::Linear => s,
+            Interpolation::Step => 0.0,
+            Interpolation::Sinusoidal => 0.5 * (1.0 - cos(std::f64::consts::PI * s)),
+        };
+
+        v0 + weight * (v1 - v0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn odd_keyframes_are_an_error() {
+        let schedule = Schedule::new(
+            ScheduledParameter::K,
+            &[0.0, 1.0, 2.0],
+            Interpolation::Linear,
+        );
+        assert!(schedule.is_err());
+    }
+
+    #[test]
+    fn keyframes_must_be_finite_and_increasing() {
+        for keyframes in [
+            &[][..],
+            &[0.0, f64::NAN],
+            &[f64::INFINITY, 1.0],
+            &[1.0, 0.0, 1.0, 1.0],
+            &[1.0, 0.0, 0.5, 1.0],
+        ] {
+            let schedule = Schedule::new(ScheduledParameter::J, keyframes, Interpolation::Step);
+            assert!(schedule.is_err(), "{:?}", keyframes);
+        }
+    }
+
+    #[test]
+    fn values_are_held_outside_the_keyframes() {
+        let keyframes = [1.0, -1.0, 3.0, 1.0];
+        for interpolation in [
+            Interpolation::Linear,
+            Interpolation::Step,
+            Interpolation::Sinusoidal,
+        ] {
+            let schedule = Schedule::new(ScheduledParameter::K, &keyframes, interpolation).unwrap();
+            assert_eq!(schedule.value(-5.0), -1.0);
+            assert_eq!(schedule.value(1.0), -1.0);
+            assert_eq!(schedule.value(3.0), 1.0);
+            assert_eq!(schedule.value(10.0), 1.0);
+        }
+
+        let single = Schedule::new(ScheduledParameter::A, &[2.0, 0.5], Interpolation::Linear);
+        assert_eq!(single.unwrap().value(0.0), 0.5);
+    }
+
+    #[test]
+    fn interpolation_shapes_the_value_between_keyframes() {
+        let keyframes = [0.0, 0.0, 2.0, 4.0, 3.0, 1.0];
+        let value = |interpolation, time| {
+            Schedule::new(ScheduledParameter::K, &keyframes, interpolation)
+                .unwrap()
+                .value(time)
+        };
+
+        assert!((value(Interpolation::Linear, 0.5) - 1.0).abs() < 1e-12);
+        assert!((value(Interpolation::Linear, 2.5) - 2.5).abs() < 1e-12);
+
+        assert_eq!(value(Interpolation::Step, 1.9), 0.0);
+        assert_eq!(value(Interpolation::Step, 2.0), 4.0);
+        assert_eq!(value(Interpolation::Step, 2.9), 4.0);
+
+        // Halfway through is halfway up, and a quarter through is (1 - cos(π/4)) / 2
+        // of the way
+        assert!((value(Interpolation::Sinusoidal, 1.0) - 2.0).abs() < 1e-12);
+        let quarter = 4.0 * 0.5 * (1.0 - std::f64::consts::FRAC_1_SQRT_2);
+        assert!((value(Interpolation::Sinusoidal, 0.5) - quarter).abs() < 1e-12);
+    }
+}
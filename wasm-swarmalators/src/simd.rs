@@ -0,0 +1,203 @@
+use std::ops::Range;
+
+use crate::{cos, sin};
+
+/// The agents' state laid out as structure-of-arrays for the vectorised pairwise
+/// loop.
+///
+/// Phases are stored as their cosines and sines so the loop needs no
+/// trigonometry: `cos(φ_j - φ_i)` and `sin(n(φ_j - φ_i))` are expanded with the
+/// angle-difference identities. In 2D the z coordinates are all zero.
+pub struct PairwiseState {
+    x: Vec<f64>,
+    y: Vec<f64>,
+    z: Vec<f64>,
+    cos: Vec<f64>,
+    sin: Vec<f64>,
+    cos_harmonic: Vec<f64>,
+    sin_harmonic: Vec<f64>,
+}
+
+/// Coefficients of the interaction between agent `i` and every other agent.
+pub struct Coefficients {
+    pub A: f64,
+    pub B: f64,
+    pub J: f64,
+    pub min_distance: f64,
+}
+
+/// Sums over `j` of the pairwise terms for one agent.
+///
+/// - `velocity`: `Σ (A + J cos(φ_j - φ_i)) d / r - B d / r²`.
+/// - `sin_harmonic`, `cos_harmonic`: `Σ sin(n(φ_j - φ_i)) / r` and
+///   `Σ cos(n(φ_j - φ_i)) / r`, from which any phase lag can be applied.
+#[derive(Default)]
+pub struct Sums {
+    pub velocity: [f64; 3],
+    pub sin_harmonic: f64,
+    pub cos_harmonic: f64,
+}
+
+impl PairwiseState {
+    /// Splits `positions` (stride `dim`) and `phases` into separate arrays.
+    pub fn new(positions: &[f64], phases: &[f64], dim: usize, harmonic: f64) -> PairwiseState {
+        let axis = |k: usize| -> Vec<f64> {
+            if k < dim {
+                positions.iter().skip(k).step_by(dim).copied().collect()
+            } else {
+                vec![0.0; phases.len()]
+            }
+        };
+
+        PairwiseState {
+            x: axis(0),
+            y: axis(1),
+            z: axis(2),
+            cos: phases.iter().map(|&phase| cos(phase)).collect(),
+            sin: phases.iter().map(|&phase| sin(phase)).collect(),
+            cos_harmonic: phases.iter().map(|&phase| cos(harmonic * phase)).collect(),
+            sin_harmonic: phases.iter().map(|&phase| sin(harmonic * phase)).collect(),
+        }
+    }
+
+    /// Sums the pairwise terms of agent `i` over every other agent.
+    pub fn accumulate(&self, i: usize, coefficients: &Coefficients) -> Sums {
+        let mut sums = Sums::default();
+        self.accumulate_range(i, 0..i, coefficients, &mut sums);
+        self.accumulate_range(i, i + 1..self.x.len(), coefficients, &mut sums);
+        sums
+    }
+
+    /// Adds the pairwise terms of agent `i` with each agent in `range` to `sums`,
+    /// two agents at a time using 128-bit SIMD.
+    #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+    fn accumulate_range(
+        &self,
+        i: usize,
+        range: Range<usize>,
+        coefficients: &Coefficients,
+        sums: &mut Sums,
+    ) {
+        use core::arch::wasm32::*;
+
+        let load = |values: &[f64], j: usize| -> v128 {
+            let lanes = &values[j..j + 2];
+            // SAFETY: `lanes` holds two contiguous f64s and v128_load is unaligned
+            unsafe { v128_load(lanes.as_ptr() as *const v128) }
+        };
+
+        let (xi, yi, zi) = (
+            f64x2_splat(self.x[i]),
+            f64x2_splat(self.y[i]),
+            f64x2_splat(self.z[i]),
+        );
+        let (cos_i, sin_i) = (f64x2_splat(self.cos[i]), f64x2_splat(self.sin[i]));
+        let (cos_harmonic_i, sin_harmonic_i) = (
+            f64x2_splat(self.cos_harmonic[i]),
+            f64x2_splat(self.sin_harmonic[i]),
+        );
+        let A = f64x2_splat(coefficients.A);
+        let B = f64x2_splat(coefficients.B);
+        let J = f64x2_splat(coefficients.J);
+        let min_distance = f64x2_splat(coefficients.min_distance);
+        let one = f64x2_splat(1.0);
+
+        let zero = f64x2_splat(0.0);
+        let (mut vx, mut vy, mut vz) = (zero, zero, zero);
+        let (mut sum_sin, mut sum_cos) = (zero, zero);
+
+        let paired_end = range.start + (range.len() / 2) * 2;
+        for j in (range.start..paired_end).step_by(2) {
+            let dx = f64x2_sub(load(&self.x, j), xi);
+            let dy = f64x2_sub(load(&self.y, j), yi);
+            let dz = f64x2_sub(load(&self.z, j), zi);
+
+            let dist_sq = f64x2_add(
+                f64x2_add(f64x2_mul(dx, dx), f64x2_mul(dy, dy)),
+                f64x2_mul(dz, dz),
+            );
+            let inv = f64x2_div(one, f64x2_pmax(f64x2_sqrt(dist_sq), min_distance));
+
+            let (cos_j, sin_j) = (load(&self.cos, j), load(&self.sin, j));
+            let cos_diff = f64x2_add(f64x2_mul(cos_j, cos_i), f64x2_mul(sin_j, sin_i));
+
+            // (A + J cos(φ_j - φ_i)) / r - B / r²
+            let coefficient = f64x2_sub(
+                f64x2_mul(f64x2_add(A, f64x2_mul(J, cos_diff)), inv),
+                f64x2_mul(B, f64x2_mul(inv, inv)),
+            );
+            vx = f64x2_add(vx, f64x2_mul(dx, coefficient));
+            vy = f64x2_add(vy, f64x2_mul(dy, coefficient));
+            vz = f64x2_add(vz, f64x2_mul(dz, coefficient));
+
+            let (cos_harmonic_j, sin_harmonic_j) =
+                (load(&self.cos_harmonic, j), load(&self.sin_harmonic, j));
+            let sin_harmonic_diff = f64x2_sub(
+                f64x2_mul(sin_harmonic_j, cos_harmonic_i),
+                f64x2_mul(cos_harmonic_j, sin_harmonic_i),
+            );
+            let cos_harmonic_diff = f64x2_add(
+                f64x2_mul(cos_harmonic_j, cos_harmonic_i),
+                f64x2_mul(sin_harmonic_j, sin_harmonic_i),
+            );
+            sum_sin = f64x2_add(sum_sin, f64x2_mul(sin_harmonic_diff, inv));
+            sum_cos = f64x2_add(sum_cos, f64x2_mul(cos_harmonic_diff, inv));
+        }
+
+        let total = |v: v128| f64x2_extract_lane::<0>(v) + f64x2_extract_lane::<1>(v);
+        sums.velocity[0] += total(vx);
+        sums.velocity[1] += total(vy);
+        sums.velocity[2] += total(vz);
+        sums.sin_harmonic += total(sum_sin);
+        sums.cos_harmonic += total(sum_cos);
+
+        // An odd agent out is handled on its own
+        for j in paired_end..range.end {
+            self.accumulate_pair(i, j, coefficients, sums);
+        }
+    }
+
+    /// Adds the pairwise terms of agent `i` with each agent in `range` to `sums`.
+    ///
+    /// Used where WASM SIMD isn't available. The flat loop over separate arrays
+    /// still leaves the compiler free to vectorise it for the target.
+    #[cfg(not(all(target_arch = "wasm32", target_feature = "simd128")))]
+    fn accumulate_range(
+        &self,
+        i: usize,
+        range: Range<usize>,
+        coefficients: &Coefficients,
+        sums: &mut Sums,
+    ) {
+        for j in range {
+            self.accumulate_pair(i, j, coefficients, sums);
+        }
+    }
+
+    /// Adds the pairwise terms of agents `i` and `j` to `sums`.
+    fn accumulate_pair(&self, i: usize, j: usize, coefficients: &Coefficients, sums: &mut Sums) {
+        let d = [
+            self.x[j] - self.x[i],
+            self.y[j] - self.y[i],
+            self.z[j] - self.z[i],
+        ];
+        let dist = (d[0] * d[0] + d[1] * d[1] + d[2] * d[2])
+            .sqrt()
+            .max(coefficients.min_distance);
+        let inv = 1.0 / dist;
+
+        let cos_diff = self.cos[j] * self.cos[i] + self.sin[j] * self.sin[i];
+        let coefficient =
+            (coefficients.A + coefficients.J * cos_diff) * inv - coefficients.B * inv * inv;
+        for (velocity, d) in sums.velocity.iter_mut().zip(d) {
+            *velocity += d * coefficient;
+        }
+
+        let sin_harmonic_diff = self.sin_harmonic[j] * self.cos_harmonic[i]
+            - self.cos_harmonic[j] * self.sin_harmonic[i];
+        let cos_harmonic_diff = self.cos_harmonic[j] * self.cos_harmonic[i]
+            + self.sin_harmonic[j] * self.sin_harmonic[i];
+        sums.sin_harmonic += sin_harmonic_diff * inv;
+        sums.cos_harmonic += cos_harmonic_diff * inv;
+    }
+}
@@ -0,0 +1,111 @@
+use serde::{Deserialize, Serialize};
+
+/// Refresh settings of a k-nearest-neighbour topology.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+struct Knn {
+    k: usize,
+    refresh_every: usize,
+}
+
+/// The interaction graph restricting which agents couple, as an adjacency list.
+///
+/// Either a fixed graph from an edge list or the `k` nearest neighbours of each
+/// agent, recomputed every `refresh_every` steps.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Topology {
+    knn: Option<Knn>,
+    age: usize,
+    neighbours: Vec<Vec<usize>>,
+}
+
+impl Topology {
+    /// Builds an undirected graph over `agents` agents from flattened
+    /// `[a0, b0, a1, b1, ...]` edges.
+    pub fn from_edges(agents: usize, edges: &[u32]) -> Result<Topology, String> {
+        if !edges.len().is_multiple_of(2) {
+            return Err("Edges must be [from, to] pairs".to_string());
+        }
+        if edges.iter().any(|&a| a as usize >= agents) {
+            return Err("Edge endpoints must be less than agents".to_string());
+        }
+
+        let mut neighbours = vec![Vec::new(); agents];
+        for edge in edges.chunks(2) {
+            let (a, b) = (edge[0] as usize, edge[1] as usize);
+            if a != b && !neighbours[a].contains(&b) {
+                neighbours[a].push(b);
+                neighbours[b].push(a);
+            }
+        }
+
+        Ok(Topology {
+            knn: None,
+            age: 0,
+            neighbours,
+        })
+    }
+
+    /// Starts a k-nearest-neighbour graph over `agents` agents, computed on the next
+    /// refresh.
+    pub fn knn(agents: usize, k: usize, refresh_every: usize) -> Topology {
+        Topology {
+            knn: Some(Knn { k, refresh_every }),
+            age: 0,
+            neighbours: vec![Vec::new(); agents],
+        }
+    }
+
+    /// Agents that agent `i` couples to.
+    pub fn neighbours(&self, i: usize) -> &[usize] {
+        &self.neighbours[i]
+    }
+
+    /// Counts a step, returning `k` if the nearest neighbours are due to be
+    /// recomputed before it.
+    pub fn tick(&mut self) -> Option<usize> {
+        let knn = self.knn?;
+        let due = self.age == 0;
+        self.age = (self.age + 1) % knn.refresh_every;
+
+        due.then_some(knn.k)
+    }
+
+    /// Replaces the adjacency list with freshly computed nearest neighbours.
+    pub fn set_neighbours(&mut self, neighbours: Vec<Vec<usize>>) {
+        self.neighbours = neighbours;
+    }
+
+    /// Adds `extra` agents with no edges. Nearest neighbours are recomputed on the
+    /// next step so the new agents are linked straight away.
+    pub fn grow(&mut self, extra: usize) {
+        self.neighbours.extend(vec![Vec::new(); extra]);
+        self.age = 0;
+    }
+
+    /// Removes agent `index` and its edges, renumbering the agents after it.
+    pub fn remove(&mut self, index: usize) {
+        self.neighbours.remove(index);
+        for neighbours in self.neighbours.iter_mut() {
+            neighbours.retain(|&j| j != index);
+            for j in neighbours.iter_mut().filter(|j| **j > index) {
+                *j -= 1;
+            }
+        }
+        self.age = 0;
+    }
+
+    /// Checks the graph covers exactly `agents` agents.
+    pub fn validate(&self, agents: usize) -> Result<(), String> {
+        if let Some(knn) = self.knn {
+            if knn.k == 0 || knn.refresh_every == 0 {
+                return Err("Nearest neighbour k and refresh interval must be positive".to_string());
+            }
+        }
+        if self.neighbours.len() != agents || self.neighbours.iter().flatten().any(|&j| j >= agents)
+        {
+            return Err("Topology must link agents less than agents".to_string());
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,67 @@
+//! Measurements of the state: order parameters and extents of the swarm.
+
+use std::f64::consts::PI;
+
+use wasm_swarmalators::Swarmalator;
+
+#[test]
+fn phase_locked_to_the_angle_gives_full_rainbow_order() {
+    let agents = 12;
+    let mut positions = Vec::new();
+    let mut phases = Vec::new();
+    for j in 0..agents {
+        let angle = 2.0 * PI * j as f64 / agents as f64;
+        positions.extend([angle.cos(), angle.sin()]);
+        // θ + φ is the same for every agent
+        phases.push((0.3 - angle).rem_euclid(2.0 * PI));
+    }
+    let system = Swarmalator::new(
+        agents,
+        positions,
+        phases,
+        vec![0.0; agents],
+        0.0,
+        1.0,
+        None,
+        None,
+    )
+    .unwrap();
+
+    let order = system.order_parameters();
+    assert!((order[0] - 1.0).abs() < 1e-12, "{:?}", order);
+    assert!(order[1] < 1e-12, "{:?}", order);
+}
+
+#[test]
+fn centroid_and_bounding_box_of_a_known_configuration() {
+    let positions = vec![1.0, 2.0, -3.0, 0.5, 2.0, -1.5, 0.0, 3.0];
+    let system = Swarmalator::new(
+        4,
+        positions,
+        vec![0.0; 4],
+        vec![0.0; 4],
+        1.0,
+        1.0,
+        None,
+        None,
+    )
+    .unwrap();
+
+    assert_eq!(system.centroid(), vec![0.0, 1.0]);
+    assert_eq!(system.bounding_box(), vec![-3.0, -1.5, 2.0, 3.0]);
+
+    let single = Swarmalator::new(
+        1,
+        vec![0.25, -0.75],
+        vec![0.0],
+        vec![0.0],
+        1.0,
+        1.0,
+        None,
+        None,
+    )
+    .unwrap();
+
+    assert_eq!(single.centroid(), vec![0.25, -0.75]);
+    assert_eq!(single.bounding_box(), vec![0.25, -0.75, 0.25, -0.75]);
+}
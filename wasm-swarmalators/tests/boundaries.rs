@@ -0,0 +1,112 @@
+//! Boundary conditions: periodic, reflective and soft arenas.
+
+use wasm_swarmalators::{Boundary, Swarmalator};
+
+#[test]
+fn agents_attract_across_a_periodic_edge() {
+    // 1.0 apart across the edge of the box, 3.0 apart through it
+    let mut system = Swarmalator::new(
+        2,
+        vec![0.5, 2.0, 3.5, 2.0],
+        vec![0.0, 0.0],
+        vec![0.0, 0.0],
+        0.0,
+        0.5,
+        None,
+        None,
+    )
+    .unwrap();
+    system.set_periodic(Some(4.0)).unwrap();
+
+    system.update(0.01).unwrap();
+
+    let velocities = system.velocities_vec();
+    assert!(velocities[0] < 0.0, "{:?}", velocities);
+    assert!(velocities[2] > 0.0, "{:?}", velocities);
+
+    system.step_many(200, 0.01).unwrap();
+    let positions = system.positions_vec();
+    let gap = (positions[0] - positions[2]).rem_euclid(4.0);
+    assert!(gap.min(4.0 - gap) < 0.9, "{:?}", positions);
+}
+
+#[test]
+fn merged_agents_are_brought_into_the_arena() {
+    for kind in [Boundary::Periodic, Boundary::Reflective] {
+        let mut system = Swarmalator::random(5, 1, 1.0, 0.5, 0.1).unwrap();
+        system.set_boundary(kind, 4.0, 0.0).unwrap();
+        let outside = Swarmalator::new(
+            2,
+            vec![10.0, -1.0, -5.5, 4.5],
+            vec![0.0; 2],
+            vec![0.0; 2],
+            1.0,
+            0.5,
+            None,
+            None,
+        )
+        .unwrap();
+
+        system.merge(&outside).unwrap();
+
+        let positions = system.positions_vec();
+        assert!(
+            positions.iter().all(|x| (0.0..=4.0).contains(x)),
+            "{:?}",
+            positions
+        );
+    }
+}
+
+#[test]
+fn reflective_walls_hold_a_spreading_swarm() {
+    let mut system = Swarmalator::random(50, 2, 0.0, 0.0, 0.0).unwrap();
+    system.set_boundary(Boundary::Reflective, 1.0, 0.0).unwrap();
+    assert!(system
+        .positions_vec()
+        .iter()
+        .all(|x| (0.0..=1.0).contains(x)));
+
+    // Repulsion alone drives the agents apart and into the walls
+    system.set_A(0.0).unwrap();
+    for _ in 0..100 {
+        system.update(0.05).unwrap();
+        let positions = system.positions_vec();
+        assert!(
+            positions.iter().all(|x| (0.0..=1.0).contains(x)),
+            "{:?}",
+            positions
+        );
+    }
+}
+
+#[test]
+fn soft_confinement_pulls_agents_back_to_the_circle() {
+    let mut system = Swarmalator::new(
+        2,
+        vec![3.0, 0.0, 0.0, 0.5],
+        vec![0.0; 2],
+        vec![0.0; 2],
+        0.0,
+        0.0,
+        None,
+        None,
+    )
+    .unwrap();
+    system.set_A(0.0).unwrap();
+    system.set_B(0.0).unwrap();
+    system
+        .set_boundary(Boundary::SoftCircular, 1.0, 1.0)
+        .unwrap();
+    // Only pulled back gradually
+    assert_eq!(system.positions_vec()[0], 3.0);
+
+    system.step_many(500, 0.01).unwrap();
+
+    // dx/dt = 1 - x from x = 3, so x = 1 + 2 exp(-t)
+    let positions = system.positions_vec();
+    let expected = 1.0 + 2.0 * (-5.0f64).exp();
+    assert!((positions[0] - expected).abs() < 1e-3, "{:?}", positions);
+    // Agents inside the circle feel nothing
+    assert_eq!(&positions[2..], &[0.0, 0.5]);
+}
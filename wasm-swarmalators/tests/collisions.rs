@@ -0,0 +1,109 @@
+//! Finite-size agents and the speed limit.
+
+use wasm_swarmalators::{CollisionKind, Swarmalator};
+
+/// Smallest distance between any two agents.
+fn min_separation(positions: &[f64]) -> f64 {
+    let agents = positions.len() / 2;
+    (0..agents)
+        .flat_map(|i| (i + 1..agents).map(move |j| (i, j)))
+        .map(|(i, j)| {
+            (positions[2 * i] - positions[2 * j]).hypot(positions[2 * i + 1] - positions[2 * j + 1])
+        })
+        .fold(f64::INFINITY, f64::min)
+}
+
+/// Two agents `gap` apart on the x-axis that only interact through collisions.
+fn pair(gap: f64) -> Swarmalator {
+    let mut system = Swarmalator::new(
+        2,
+        vec![0.0, 0.0, gap, 0.0],
+        vec![0.0; 2],
+        vec![0.0; 2],
+        0.0,
+        0.0,
+        None,
+        None,
+    )
+    .unwrap();
+    system.set_A(0.0).unwrap();
+    system.set_B(0.0).unwrap();
+    system
+}
+
+#[test]
+fn hard_cores_keep_a_crowded_swarm_apart() {
+    let mut points = Swarmalator::random(40, 19, 1.0, 0.5, 0.1).unwrap();
+    points.step_many(200, 0.02).unwrap();
+    assert!(min_separation(&points.positions_vec()) < 0.4);
+
+    // The relaxation sweeps leave the bodies overlapping by a sliver at most
+    let mut system = Swarmalator::random(40, 19, 1.0, 0.5, 0.1).unwrap();
+    system
+        .set_collisions(Some(CollisionKind::Hard), 0.2, 0.0)
+        .unwrap();
+    assert!(min_separation(&system.positions_vec()) > 0.398);
+    for _ in 0..20 {
+        system.step_many(10, 0.02).unwrap();
+        let separation = min_separation(&system.positions_vec());
+        assert!(separation > 0.398, "{}", separation);
+    }
+}
+
+#[test]
+fn soft_cores_push_overlapping_agents_apart() {
+    let mut system = pair(0.1);
+    system
+        .set_collisions(Some(CollisionKind::Soft), 0.25, 2.0)
+        .unwrap();
+    // Only pushed apart gradually
+    assert_eq!(system.positions_vec()[2], 0.1);
+
+    system.step_many(500, 0.01).unwrap();
+
+    let positions = system.positions_vec();
+    let gap = positions[2] - positions[0];
+    assert!((gap - 0.5).abs() < 1e-3, "{:?}", positions);
+    // Symmetrically about where they started
+    assert!(
+        (positions[0] + positions[2] - 0.1).abs() < 1e-9,
+        "{:?}",
+        positions
+    );
+}
+
+#[test]
+fn pinned_agents_push_the_other_the_whole_way() {
+    let mut system = pair(0.1);
+    system.pin_agent(0).unwrap();
+    system
+        .set_collisions(Some(CollisionKind::Hard), 0.25, 0.0)
+        .unwrap();
+
+    let positions = system.positions_vec();
+    assert_eq!(&positions[..2], &[0.0, 0.0]);
+    assert!((positions[2] - 0.5).abs() < 1e-9, "{:?}", positions);
+}
+
+#[test]
+fn the_speed_limit_caps_every_agent() {
+    let mut system = Swarmalator::random(30, 20, 1.0, 0.5, 0.5).unwrap();
+    system.set_max_speed(Some(0.05)).unwrap();
+
+    for _ in 0..20 {
+        system.update(0.05).unwrap();
+        let velocities = system.velocities_vec();
+        for velocity in velocities.chunks(2) {
+            assert!(
+                velocity[0].hypot(velocity[1]) <= 0.05 + 1e-12,
+                "{:?}",
+                velocity
+            );
+        }
+    }
+
+    assert!(system.set_max_speed(Some(-1.0)).is_err());
+    assert!(system
+        .set_collisions(Some(CollisionKind::Soft), 0.0, 1.0)
+        .is_err());
+}
@@ -0,0 +1,270 @@
+//! Coupling coefficients and the power laws of the pairwise interaction.
+
+use std::f64::consts::PI;
+
+use wasm_swarmalators::{Interpolation, ScheduledParameter, Swarmalator};
+
+/// Distance between the centroids of the first and second half of the agents,
+/// after running with the first half at phase 0 and `J = j_a`, and the second half at
+/// phase π and `J = j_b`.
+fn gap_between_subgroups(j_a: f64, j_b: f64) -> f64 {
+    let agents = 40;
+    let positions = Swarmalator::random(agents, 3, 0.0, 0.0, 0.0)
+        .unwrap()
+        .positions_vec();
+    let phases = (0..agents)
+        .map(|i| if i < agents / 2 { 0.0 } else { PI })
+        .collect();
+    let mut system = Swarmalator::new(
+        agents,
+        positions,
+        phases,
+        vec![0.0; agents],
+        0.0,
+        0.0,
+        None,
+        None,
+    )
+    .unwrap();
+    let j_vec = (0..agents)
+        .map(|i| if i < agents / 2 { j_a } else { j_b })
+        .collect();
+    system.set_J_vec(Some(j_vec)).unwrap();
+    system.step_many(400, 0.05).unwrap();
+
+    let positions = system.positions_vec();
+    let centroid = |group: std::ops::Range<usize>| {
+        let n = group.len() as f64;
+        group.fold([0.0, 0.0], |[x, y], i| {
+            [x + positions[2 * i] / n, y + positions[2 * i + 1] / n]
+        })
+    };
+    let (a, b) = (centroid(0..agents / 2), centroid(agents / 2..agents));
+    (a[0] - b[0]).hypot(a[1] - b[1])
+}
+
+#[test]
+fn subgroups_with_differing_coupling_separate_or_chase() {
+    // Both prefer their own phase, so the anti-phase groups move apart
+    assert!(gap_between_subgroups(0.8, 0.8) > 2.0);
+    // The second prefers the opposite phase and chases the first, so they mix
+    assert!(gap_between_subgroups(0.8, -0.8) < 1.0);
+}
+
+/// Mean distance from each agent to its nearest neighbour.
+fn mean_nearest_neighbour_distance(positions: &[f64]) -> f64 {
+    let agents = positions.len() / 2;
+    (0..agents)
+        .map(|i| {
+            (0..agents)
+                .filter(|&j| j != i)
+                .map(|j| {
+                    (positions[2 * i] - positions[2 * j])
+                        .hypot(positions[2 * i + 1] - positions[2 * j + 1])
+                })
+                .fold(f64::INFINITY, f64::min)
+        })
+        .sum::<f64>()
+        / agents as f64
+}
+
+#[test]
+fn repulsion_exponent_sets_the_spacing() {
+    let mut default = Swarmalator::random(30, 6, 1.0, 0.5, 0.2).unwrap();
+    let mut squared = Swarmalator::random(30, 6, 1.0, 0.5, 0.2).unwrap();
+    squared.set_repulsion_exponent(2.0).unwrap();
+    default.step_many(20, 0.05).unwrap();
+    squared.step_many(20, 0.05).unwrap();
+    assert_eq!(default.to_bytes(), squared.to_bytes());
+
+    // A steeper repulsion holds the agents of the static crystal further apart
+    let spacings: Vec<f64> = [1.5, 2.0, 3.0, 4.0]
+        .iter()
+        .map(|&p| {
+            let mut system = Swarmalator::random(30, 6, 0.0, 0.0, 0.0).unwrap();
+            system.set_repulsion_exponent(p).unwrap();
+            system.step_many(1500, 0.02).unwrap();
+            mean_nearest_neighbour_distance(&system.positions_vec())
+        })
+        .collect();
+    assert!(spacings.windows(2).all(|w| w[0] < w[1]), "{:?}", spacings);
+}
+
+/// Largest difference between the phases of two systems.
+fn max_phase_difference(a: &Swarmalator, b: &Swarmalator) -> f64 {
+    a.phases_vec()
+        .iter()
+        .zip(b.phases_vec())
+        .map(|(x, y)| (x - y).abs())
+        .fold(0.0, f64::max)
+}
+
+#[test]
+fn uniform_coupling_arrays_match_the_scalars() {
+    let agents = 20;
+    let mut scalar = Swarmalator::random(agents, 8, 1.0, 0.5, 0.3).unwrap();
+    let mut vectors = Swarmalator::random(agents, 8, 1.0, 0.5, 0.3).unwrap();
+    vectors.set_K_vec(Some(vec![1.0; agents])).unwrap();
+    vectors.set_J_vec(Some(vec![0.5; agents])).unwrap();
+    let mut matrices = Swarmalator::random(agents, 8, 1.0, 0.5, 0.3).unwrap();
+    matrices
+        .set_K_matrix(Some(vec![1.0; agents * agents]))
+        .unwrap();
+    matrices
+        .set_J_matrix(Some(vec![0.5; agents * agents]))
+        .unwrap();
+
+    for system in [&mut scalar, &mut vectors, &mut matrices] {
+        system.step_many(50, 0.05).unwrap();
+    }
+
+    assert!(max_phase_difference(&scalar, &vectors) < 1e-9);
+    assert!(max_phase_difference(&scalar, &matrices) < 1e-9);
+}
+
+#[test]
+fn a_zero_row_decouples_an_agent() {
+    let agents = 10;
+    let mut system = Swarmalator::random(agents, 9, 1.0, 0.0, 0.0).unwrap();
+    let initial = system.phases_vec();
+    let mut k_matrix = vec![1.0; agents * agents];
+    k_matrix[..agents].fill(0.0);
+    system.set_K_matrix(Some(k_matrix)).unwrap();
+
+    system.step_many(100, 0.05).unwrap();
+
+    // Without natural frequencies, phases only move by coupling
+    let phases = system.phases_vec();
+    assert!((phases[0] - initial[0]).abs() < 1e-12, "{:?}", phases);
+    assert!((phases[1] - initial[1]).abs() > 1e-3, "{:?}", phases);
+}
+
+#[test]
+fn contrarian_agents_oppose_the_mean_phase() {
+    let agents = 20;
+    let mut system = Swarmalator::random(agents, 10, 0.0, 0.0, 0.0).unwrap();
+    let k_vec = (0..agents)
+        .map(|i| if i < agents / 4 { -1.0 } else { 1.0 })
+        .collect();
+    system.set_K_vec(Some(k_vec)).unwrap();
+
+    system.step_many(400, 0.05).unwrap();
+
+    // Conformists lock together, and contrarians settle opposite them
+    let phases = system.phases_vec();
+    let mean = |group: std::ops::Range<usize>| {
+        let (s, c) = group.fold((0.0, 0.0), |(s, c), i| {
+            (s + phases[i].sin(), c + phases[i].cos())
+        });
+        s.atan2(c)
+    };
+    let gap = (mean(0..agents / 4) - mean(agents / 4..agents)).rem_euclid(2.0 * PI);
+    assert!((gap - PI).abs() < 0.5, "{:?}", phases);
+}
+
+#[test]
+fn coupling_arrays_must_match_the_agents() {
+    let mut system = Swarmalator::random(5, 11, 1.0, 0.5, 0.0).unwrap();
+    assert!(system.set_K_vec(Some(vec![1.0; 4])).is_err());
+    assert!(system.set_J_vec(Some(vec![1.0; 6])).is_err());
+    assert!(system.set_K_matrix(Some(vec![1.0; 5])).is_err());
+    assert!(system.set_J_matrix(Some(vec![f64::NAN; 25])).is_err());
+}
+
+#[test]
+fn a_single_species_matches_the_scalars() {
+    let mut scalar = Swarmalator::random(20, 12, 1.0, 0.5, 0.3).unwrap();
+    let mut species = Swarmalator::random(20, 12, 1.0, 0.5, 0.3).unwrap();
+    species
+        .set_species_coupling(vec![1.0], vec![1.0], vec![0.5], vec![1.0])
+        .unwrap();
+
+    scalar.step_many(50, 0.05).unwrap();
+    species.step_many(50, 0.05).unwrap();
+
+    assert!(max_phase_difference(&scalar, &species) < 1e-9);
+}
+
+/// Distance between the centroids of two species of equal size, after running
+/// with attraction `cross` between them and 1 within each.
+fn gap_between_species(cross: f64) -> f64 {
+    let agents = 40;
+    let mut system = Swarmalator::random(agents, 13, 0.0, 0.0, 0.0).unwrap();
+    system
+        .set_species((0..agents as u32).map(|i| i % 2).collect())
+        .unwrap();
+    system
+        .set_species_coupling(
+            vec![1.0, cross, cross, 1.0],
+            vec![1.0; 4],
+            vec![0.0; 4],
+            vec![0.0; 4],
+        )
+        .unwrap();
+    system.step_many(400, 0.05).unwrap();
+
+    let positions = system.positions_vec();
+    let centroid = |parity: usize| {
+        let n = (agents / 2) as f64;
+        (parity..agents).step_by(2).fold([0.0, 0.0], |[x, y], i| {
+            [x + positions[2 * i] / n, y + positions[2 * i + 1] / n]
+        })
+    };
+    let (a, b) = (centroid(0), centroid(1));
+    (a[0] - b[0]).hypot(a[1] - b[1])
+}
+
+#[test]
+fn species_without_mutual_attraction_segregate() {
+    let mixed = gap_between_species(1.0);
+    let segregated = gap_between_species(0.0);
+    assert!(mixed < 0.5, "{}", mixed);
+    assert!(segregated > 2.0, "{}", segregated);
+}
+
+#[test]
+fn species_ids_must_be_below_the_species_count() {
+    let mut system = Swarmalator::random(4, 14, 1.0, 0.5, 0.0).unwrap();
+    system.set_species(vec![0, 1, 2, 1]).unwrap();
+    let coupling = || vec![1.0; 4];
+    assert!(system
+        .set_species_coupling(coupling(), coupling(), coupling(), coupling())
+        .is_err());
+
+    system.set_species(vec![0, 1, 0, 1]).unwrap();
+    system
+        .set_species_coupling(coupling(), coupling(), coupling(), coupling())
+        .unwrap();
+    assert!(system.set_species(vec![0, 1, 2, 1]).is_err());
+    assert!(system.set_species(vec![0, 1]).is_err());
+}
+
+#[test]
+fn scheduled_coupling_follows_the_simulation_time() {
+    let mut system = Swarmalator::random(10, 15, 0.0, 0.5, 0.1).unwrap();
+    system
+        .schedule_parameter(
+            ScheduledParameter::K,
+            vec![0.0, -1.0, 1.0, 1.0],
+            Interpolation::Linear,
+        )
+        .unwrap();
+
+    // Each step uses the value at its start
+    system.step_many(10, 0.05).unwrap();
+    assert!((system.time() - 0.5).abs() < 1e-12);
+    assert!((system.K() + 0.1).abs() < 1e-12, "{}", system.K());
+
+    // Setting it directly only lasts until the next step
+    system.set_K(5.0).unwrap();
+    system.update(0.05).unwrap();
+    assert!((system.K() - 0.0).abs() < 1e-12, "{}", system.K());
+
+    system.step_many(20, 0.05).unwrap();
+    assert_eq!(system.K(), 1.0);
+
+    system.clear_schedule(ScheduledParameter::K);
+    system.set_K(5.0).unwrap();
+    system.update(0.05).unwrap();
+    assert_eq!(system.K(), 5.0);
+}
@@ -0,0 +1,139 @@
+//! Obstacles and external flow fields.
+
+use std::f64::consts::PI;
+
+use wasm_swarmalators::{FlowKind, IntegratorKind, Swarmalator};
+
+/// A lone stationary agent at `(x, y)`, which only moves with the environment.
+fn lone_agent(x: f64, y: f64) -> Swarmalator {
+    let mut system =
+        Swarmalator::new(1, vec![x, y], vec![0.0], vec![0.0], 0.0, 0.0, None, None).unwrap();
+    system.set_integrator(IntegratorKind::Rk4);
+    system
+}
+
+#[test]
+fn flow_fields_carry_agents_along() {
+    let mut wind = lone_agent(0.0, 0.0);
+    wind.set_flow_field(Some(FlowKind::Uniform), vec![1.0, -0.5])
+        .unwrap();
+    wind.step_many(100, 0.01).unwrap();
+    let positions = wind.positions_vec();
+    assert!((positions[0] - 1.0).abs() < 1e-9, "{:?}", positions);
+    assert!((positions[1] + 0.5).abs() < 1e-9, "{:?}", positions);
+
+    // dx/dt = -x from x = 2, so x = 2 exp(-t)
+    let mut radial = lone_agent(2.0, 0.0);
+    radial
+        .set_flow_field(Some(FlowKind::Radial), vec![0.0, 0.0, 1.0])
+        .unwrap();
+    radial.step_many(100, 0.01).unwrap();
+    let positions = radial.positions_vec();
+    assert!(
+        (positions[0] - 2.0 * (-1.0f64).exp()).abs() < 1e-6,
+        "{:?}",
+        positions
+    );
+
+    // Without a core, a unit circle is swept at angular speed `strength`
+    let mut vortex = lone_agent(1.0, 0.0);
+    vortex
+        .set_flow_field(Some(FlowKind::Vortex), vec![0.0, 0.0, 1.0, 0.0])
+        .unwrap();
+    vortex.step_many(100, PI / 200.0).unwrap();
+    let positions = vortex.positions_vec();
+    assert!(positions[0].abs() < 1e-6, "{:?}", positions);
+    assert!((positions[1] - 1.0).abs() < 1e-6, "{:?}", positions);
+}
+
+#[test]
+fn flow_fields_add_up() {
+    let mut system = lone_agent(0.0, 0.0);
+    system
+        .set_flow_field(Some(FlowKind::Uniform), vec![1.0, 0.0])
+        .unwrap();
+    system
+        .add_flow_field(FlowKind::Uniform, vec![0.0, 2.0])
+        .unwrap();
+    system.update(0.5).unwrap();
+    assert_eq!(system.positions_vec(), vec![0.5, 1.0]);
+
+    system.set_flow_field(None, vec![]).unwrap();
+    system.update(0.5).unwrap();
+    assert_eq!(system.positions_vec(), vec![0.5, 1.0]);
+
+    assert!(system
+        .set_flow_field(Some(FlowKind::Vortex), vec![0.0, 0.0, 1.0])
+        .is_err());
+    assert!(system
+        .add_flow_field(FlowKind::Uniform, vec![f64::NAN, 0.0])
+        .is_err());
+}
+
+/// Agents evenly spaced on a circle of `radius` about the origin, without any
+/// pairwise interaction.
+fn ring(agents: usize, radius: f64) -> Swarmalator {
+    let positions = (0..agents)
+        .flat_map(|i| {
+            let angle = 2.0 * PI * i as f64 / agents as f64;
+            [radius * angle.cos(), radius * angle.sin()]
+        })
+        .collect();
+    let mut system = Swarmalator::new(
+        agents,
+        positions,
+        vec![0.0; agents],
+        vec![0.0; agents],
+        0.0,
+        0.0,
+        None,
+        None,
+    )
+    .unwrap();
+    system.set_A(0.0).unwrap();
+    system.set_B(0.0).unwrap();
+    system
+}
+
+#[test]
+fn agents_drawn_to_an_obstacle_stay_outside_it() {
+    let mut system = ring(12, 2.0);
+    system
+        .set_flow_field(Some(FlowKind::Radial), vec![0.0, 0.0, 1.0])
+        .unwrap();
+    system.add_obstacle(0.0, 0.0, 0.5).unwrap();
+    system.set_obstacle_repulsion(5.0, 0.2).unwrap();
+
+    system.step_many(500, 0.01).unwrap();
+
+    let positions = system.positions_vec();
+    for agent in positions.chunks(2) {
+        let distance = agent[0].hypot(agent[1]);
+        assert!(distance > 0.5 && distance < 0.7, "{:?}", positions);
+    }
+}
+
+#[test]
+fn agents_drawn_to_a_polygon_stay_outside_it() {
+    let mut system = ring(12, 2.0);
+    system
+        .set_flow_field(Some(FlowKind::Radial), vec![0.0, 0.0, 1.0])
+        .unwrap();
+    system
+        .add_polygon_obstacle(vec![-0.5, -0.5, 0.5, -0.5, 0.5, 0.5, -0.5, 0.5])
+        .unwrap();
+    system.set_obstacle_repulsion(5.0, 0.2).unwrap();
+
+    system.step_many(500, 0.01).unwrap();
+
+    let positions = system.positions_vec();
+    for agent in positions.chunks(2) {
+        assert!(agent[0].abs().max(agent[1].abs()) > 0.5, "{:?}", positions);
+    }
+
+    // Without the obstacle they gather at the centre
+    system.clear_obstacles();
+    system.step_many(500, 0.01).unwrap();
+    let positions = system.positions_vec();
+    assert!(positions.iter().all(|x| x.abs() < 0.1), "{:?}", positions);
+}
@@ -0,0 +1,229 @@
+//! Spatial grid: cached neighbour queries and the cutoff interaction.
+
+use std::f64::consts::PI;
+
+use wasm_swarmalators::{CollisionKind, Swarmalator};
+
+/// `agents` agents spread evenly over `[-1, 1]²` with spread-out phases.
+fn scattered(agents: usize) -> Swarmalator {
+    let positions = (0..agents)
+        .flat_map(|i| {
+            let i = i as f64;
+            [
+                2.0 * (0.754_877_666 * i).fract() - 1.0,
+                2.0 * (0.569_840_291 * i).fract() - 1.0,
+            ]
+        })
+        .collect();
+    let phases = (0..agents)
+        .map(|i| 2.0 * PI * (0.618_033_989 * i as f64).fract())
+        .collect();
+    Swarmalator::new(
+        agents,
+        positions,
+        phases,
+        vec![0.0; agents],
+        1.0,
+        0.5,
+        None,
+        None,
+    )
+    .unwrap()
+}
+
+/// Indices of the agents within `radius` of `(x, y)`, by checking every agent.
+fn brute_force_within(positions: &[f64], x: f64, y: f64, radius: f64) -> Vec<u32> {
+    (0..positions.len() / 2)
+        .filter(|&i| {
+            (positions[2 * i] - x).powi(2) + (positions[2 * i + 1] - y).powi(2) <= radius * radius
+        })
+        .map(|i| i as u32)
+        .collect()
+}
+
+#[test]
+fn cached_queries_follow_the_positions() {
+    let mut rebuilt = scattered(200);
+    rebuilt.rebuild_grid();
+    let mut with_cutoff = scattered(200);
+    with_cutoff.set_cutoff(Some(0.3)).unwrap();
+
+    for _ in 0..5 {
+        for system in [&rebuilt, &with_cutoff] {
+            for (x, y) in [(0.0, 0.0), (0.4, -0.2), (-0.7, 0.5)] {
+                let mut found = system.agents_within(x, y, 0.35);
+                found.sort_unstable();
+                assert_eq!(
+                    found,
+                    brute_force_within(&system.positions_vec(), x, y, 0.35)
+                );
+            }
+        }
+        rebuilt.update(0.1).unwrap();
+        with_cutoff.update(0.1).unwrap();
+    }
+}
+
+#[test]
+fn a_far_outlier_does_not_blow_up_the_grid() {
+    let positions = vec![0.0, 0.0, 0.05, 0.0, 1e5, 1e5];
+    let mut system = Swarmalator::new(
+        3,
+        positions,
+        vec![0.0; 3],
+        vec![0.0; 3],
+        1.0,
+        0.5,
+        None,
+        None,
+    )
+    .unwrap();
+    system.set_cutoff(Some(0.1)).unwrap();
+    system
+        .set_collisions(Some(CollisionKind::Soft), 0.01, 1.0)
+        .unwrap();
+
+    system.update(0.01).unwrap();
+    system
+        .set_collisions(Some(CollisionKind::Hard), 0.01, 1.0)
+        .unwrap();
+    system.update(0.01).unwrap();
+
+    assert!(system.positions_vec().iter().all(|x| x.is_finite()));
+    let mut found = system.agents_within(0.0, 0.0, 1.0);
+    found.sort_unstable();
+    assert_eq!(found, vec![0, 1]);
+    assert_eq!(system.agents_within(1e5, 1e5, 1.0), vec![2]);
+
+    // A query spanning far more cells than are occupied
+    let mut found = system.agents_within(0.0, 0.0, 1e20);
+    found.sort_unstable();
+    assert_eq!(found, vec![0, 1, 2]);
+}
+
+#[test]
+fn a_far_outlier_in_3d_does_not_overflow_the_query() {
+    let positions = vec![0.0, 0.0, 0.0, 1e13, 1e13, 1e13];
+    let mut system = Swarmalator::new_3d(
+        2,
+        positions,
+        vec![0.0; 2],
+        vec![0.0; 2],
+        1.0,
+        0.5,
+        None,
+        None,
+    )
+    .unwrap();
+    system.set_cutoff(Some(1e-3)).unwrap();
+
+    let mut found = system.agents_within(0.0, 0.0, 1e20);
+    found.sort_unstable();
+    assert_eq!(found, vec![0, 1]);
+    assert_eq!(system.agents_within(0.0, 0.0, 1.0), vec![0]);
+
+    system.update(0.01).unwrap();
+    assert!(system.positions_vec().iter().all(|x| x.is_finite()));
+}
+
+#[test]
+fn a_cutoff_wider_than_the_swarm_matches_all_pairs() {
+    let mut all_pairs = scattered(100);
+    let mut with_grid = scattered(100);
+    with_grid.set_cutoff(Some(10.0)).unwrap();
+
+    for _ in 0..10 {
+        all_pairs.update(0.05).unwrap();
+        with_grid.update(0.05).unwrap();
+    }
+
+    for (a, b) in all_pairs
+        .positions_vec()
+        .iter()
+        .zip(with_grid.positions_vec())
+    {
+        assert!((a - b).abs() < 1e-9, "{} vs {}", a, b);
+    }
+    for (a, b) in all_pairs.phases_vec().iter().zip(with_grid.phases_vec()) {
+        assert!((a - b).abs() < 1e-9, "{} vs {}", a, b);
+    }
+}
+
+/// Root mean square difference between the velocities after one step of `system`
+/// and of an all-to-all copy.
+fn velocity_error(mut system: Swarmalator) -> f64 {
+    let mut exact = Swarmalator::from_bytes(system.to_bytes()).unwrap();
+    exact.set_cutoff(None).unwrap();
+    exact.set_far_field(false);
+    exact.set_mean_field(false);
+    exact.update(0.01).unwrap();
+    system.update(0.01).unwrap();
+
+    let velocities = exact.velocities_vec();
+    let squares: f64 = velocities
+        .iter()
+        .zip(system.velocities_vec())
+        .map(|(a, b)| (a - b).powi(2))
+        .sum();
+    let scale: f64 = velocities.iter().map(|v| v * v).sum();
+    (squares / scale).sqrt()
+}
+
+#[test]
+fn the_far_field_approximates_distant_agents() {
+    let mut truncated = Swarmalator::random(400, 7, 1.0, 0.5, 0.1).unwrap();
+    truncated.set_cutoff(Some(0.2)).unwrap();
+    let mut far_field = Swarmalator::random(400, 7, 1.0, 0.5, 0.1).unwrap();
+    far_field.set_cutoff(Some(0.2)).unwrap();
+    far_field.set_far_field(true);
+
+    let (truncated, far_field) = (velocity_error(truncated), velocity_error(far_field));
+    assert!(far_field < 0.05, "{}", far_field);
+    assert!(
+        far_field < truncated / 10.0,
+        "{} vs {}",
+        far_field,
+        truncated
+    );
+}
+
+/// Two clusters of radius 0.3 with 100 agents each, 4 apart.
+fn two_clusters() -> Swarmalator {
+    let cluster = Swarmalator::random(100, 8, 1.0, 0.5, 0.1).unwrap();
+    let positions = cluster.positions_vec();
+    let positions = [-2.0, 2.0]
+        .iter()
+        .flat_map(|offset| {
+            positions
+                .chunks(2)
+                .flat_map(move |p| [0.3 * p[0] + offset, 0.3 * p[1]])
+        })
+        .collect();
+    let mut phases = cluster.phases_vec();
+    phases.extend(phases.iter().map(|phase| phase + 1.0).collect::<Vec<_>>());
+    Swarmalator::new(200, positions, phases, vec![0.1; 200], 1.0, 0.5, None, None).unwrap()
+}
+
+#[test]
+fn the_mean_field_approximates_a_distant_cluster() {
+    let mut truncated = two_clusters();
+    truncated.set_cutoff(Some(1.0)).unwrap();
+    let mut mean_field = two_clusters();
+    mean_field.set_cutoff(Some(1.0)).unwrap();
+    mean_field.set_mean_field(true);
+
+    let (truncated, mean_field) = (velocity_error(truncated), velocity_error(mean_field));
+    assert!(mean_field < 0.05, "{}", mean_field);
+    assert!(
+        mean_field < truncated / 10.0,
+        "{} vs {}",
+        mean_field,
+        truncated
+    );
+
+    // With every agent near, nothing is approximated
+    let mut near = two_clusters();
+    near.set_cutoff(Some(10.0)).unwrap();
+    near.set_mean_field(true);
+    assert!(velocity_error(near) < 1e-9);
+}
@@ -0,0 +1,446 @@
+//! Behaviour of the integration step: pinning, noise, integrators and drift.
+
+use std::f64::consts::PI;
+
+use wasm_swarmalators::{
+    IntegratorKind, Interpolation, NanPolicy, ScheduledParameter, Swarmalator,
+};
+
+/// `agents` agents spread evenly over `[-1, 1]²` with spread-out phases.
+fn scattered(agents: usize) -> Swarmalator {
+    let positions = (0..agents)
+        .flat_map(|i| {
+            let i = i as f64;
+            [
+                2.0 * (0.754_877_666 * i).fract() - 1.0,
+                2.0 * (0.569_840_291 * i).fract() - 1.0,
+            ]
+        })
+        .collect();
+    let phases = (0..agents)
+        .map(|i| 2.0 * PI * (0.618_033_989 * i as f64).fract())
+        .collect();
+    Swarmalator::new(
+        agents,
+        positions,
+        phases,
+        vec![0.0; agents],
+        1.0,
+        0.5,
+        None,
+        None,
+    )
+    .unwrap()
+}
+
+/// Largest change in either coordinate of the centroid.
+fn centroid_shift(before: &[f64], after: &[f64]) -> f64 {
+    (after[0] - before[0])
+        .abs()
+        .max((after[1] - before[1]).abs())
+}
+
+#[test]
+fn fixed_center_of_mass_holds() {
+    // A target makes the coupling uneven, so the swarm drifts on its own
+    let mut drifting = scattered(20);
+    drifting.set_target(vec![2.0, 1.0]).unwrap();
+    let mut fixed = scattered(20);
+    fixed.set_target(vec![2.0, 1.0]).unwrap();
+    fixed.set_fix_center_of_mass(true);
+
+    let before = fixed.centroid();
+    drifting.step_many(50, 0.05).unwrap();
+    fixed.step_many(50, 0.05).unwrap();
+
+    assert!(centroid_shift(&before, &drifting.centroid()) > 1e-6);
+    let after = fixed.centroid();
+    assert!(
+        centroid_shift(&before, &after) < 1e-9,
+        "{:?} vs {:?}",
+        after,
+        before
+    );
+}
+
+#[test]
+fn fixed_center_of_mass_holds_with_pinned_agents() {
+    let mut system = scattered(20);
+    system.set_target(vec![2.0, 1.0]).unwrap();
+    system.set_pinned(vec![0, 1]).unwrap();
+    system.set_fix_center_of_mass(true);
+
+    let before = system.centroid();
+    system.step_many(50, 0.05).unwrap();
+    let after = system.centroid();
+
+    assert!(
+        centroid_shift(&before, &after) < 1e-9,
+        "{:?} vs {:?}",
+        after,
+        before
+    );
+}
+
+#[test]
+fn coincident_agents_stay_finite() {
+    let positions = vec![0.3, -0.2, 0.3, -0.2, 0.8, 0.5];
+    let mut system = Swarmalator::new(
+        3,
+        positions,
+        vec![0.0, 1.0, 2.0],
+        vec![0.0; 3],
+        1.0,
+        1.0,
+        None,
+        None,
+    )
+    .unwrap();
+
+    system.update(0.01).unwrap();
+
+    assert!(system.positions_vec().iter().all(|x| x.is_finite()));
+    assert!(system.phases_vec().iter().all(|x| x.is_finite()));
+}
+
+#[test]
+fn the_minimum_distance_must_be_positive() {
+    let mut system = scattered(2);
+    assert!(system.set_min_distance(0.0).is_err());
+    assert!(system.set_min_distance(-1e-6).is_err());
+    assert!(system.set_min_distance(f64::NAN).is_err());
+}
+
+#[test]
+fn agents_equally_far_from_the_target_stay_finite() {
+    // On a circle around the target, so every distance to it is exactly 0.5
+    let positions = vec![1.5, 1.0, 1.0, 1.5, 0.5, 1.0, 1.0, 0.5];
+    let mut system = Swarmalator::new(
+        4,
+        positions,
+        vec![0.0, 1.0, 2.0, 3.0],
+        vec![0.0; 4],
+        1.0,
+        1.0,
+        None,
+        Some(vec![1.0, 1.0]),
+    )
+    .unwrap();
+    system.update(0.01).unwrap();
+    assert!(system.positions_vec().iter().all(|x| x.is_finite()));
+    assert!(system.phases_vec().iter().all(|x| x.is_finite()));
+
+    let mut single = Swarmalator::new(
+        1,
+        vec![0.5, 0.5],
+        vec![0.0],
+        vec![0.0],
+        1.0,
+        1.0,
+        None,
+        Some(vec![0.0, 0.0]),
+    )
+    .unwrap();
+    single.update(0.01).unwrap();
+    assert!(single.positions_vec().iter().all(|x| x.is_finite()));
+}
+
+#[test]
+fn step_many_matches_repeated_updates() {
+    let mut stepped = scattered(50);
+    let mut updated = scattered(50);
+    stepped.set_target(vec![0.2, -0.1]).unwrap();
+    updated.set_target(vec![0.2, -0.1]).unwrap();
+
+    stepped.step_many(10, 0.05).unwrap();
+    for _ in 0..10 {
+        updated.update(0.05).unwrap();
+    }
+
+    assert_eq!(stepped.positions_vec(), updated.positions_vec());
+    assert_eq!(stepped.phases_vec(), updated.phases_vec());
+    assert_eq!(stepped.velocities_vec(), updated.velocities_vec());
+}
+
+/// Largest difference between the positions of two systems.
+fn max_position_error(a: &Swarmalator, b: &Swarmalator) -> f64 {
+    a.positions_vec()
+        .iter()
+        .zip(b.positions_vec())
+        .map(|(x, y)| (x - y).abs())
+        .fold(0.0, f64::max)
+}
+
+#[test]
+fn rk4_is_more_accurate_than_euler_at_large_steps() {
+    let mut reference = scattered(5);
+    reference.set_integrator(IntegratorKind::Rk4);
+    reference.step_many(400, 0.005).unwrap();
+
+    let mut euler = scattered(5);
+    euler.step_many(10, 0.2).unwrap();
+
+    let mut rk4 = scattered(5);
+    rk4.set_integrator(IntegratorKind::Rk4);
+    rk4.step_many(10, 0.2).unwrap();
+
+    let euler_error = max_position_error(&euler, &reference);
+    let rk4_error = max_position_error(&rk4, &reference);
+    assert!(
+        rk4_error < euler_error / 100.0,
+        "RK4 error {} vs Euler error {}",
+        rk4_error,
+        euler_error
+    );
+}
+
+#[test]
+fn pinned_agents_stay_put_while_neighbours_gather() {
+    let positions = vec![0.0, 0.0, 2.0, 0.0, -2.0, 0.5, 0.5, 2.0, 0.0, -2.0];
+    let mut system = Swarmalator::new(
+        5,
+        positions,
+        vec![0.0; 5],
+        vec![1.0, 0.0, 0.0, 0.0, 0.0],
+        1.0,
+        0.5,
+        None,
+        None,
+    )
+    .unwrap();
+    system.set_pinned(vec![0]).unwrap();
+
+    let distance_to_anchor = |positions: &[f64]| -> f64 {
+        (1..5)
+            .map(|i| positions[2 * i].hypot(positions[2 * i + 1]))
+            .sum()
+    };
+    let before = system.positions_vec();
+    system.step_many(200, 0.05).unwrap();
+    let after = system.positions_vec();
+
+    assert_eq!(after[0].to_bits(), 0.0f64.to_bits());
+    assert_eq!(after[1].to_bits(), 0.0f64.to_bits());
+    assert_eq!(system.phases_vec()[0].to_bits(), 0.0f64.to_bits());
+    assert!(distance_to_anchor(&after) < distance_to_anchor(&before) / 2.0);
+}
+
+#[test]
+fn noise_is_seeded_and_vanishes_at_zero_sigma() {
+    let noisy = |position_sigma: f64, phase_sigma: f64, seed: u64| {
+        let mut system = Swarmalator::random(30, 4, 1.0, 0.5, 0.2).unwrap();
+        system.set_noise(position_sigma, phase_sigma, seed).unwrap();
+        system.step_many(20, 0.05).unwrap();
+        system
+    };
+    let mut deterministic = Swarmalator::random(30, 4, 1.0, 0.5, 0.2).unwrap();
+    deterministic.step_many(20, 0.05).unwrap();
+
+    assert_eq!(noisy(0.0, 0.0, 1).to_bytes(), deterministic.to_bytes());
+    assert_eq!(
+        noisy(0.2, 0.2, 1).positions_vec(),
+        noisy(0.2, 0.2, 1).positions_vec()
+    );
+
+    // Different seeds give different paths, each spread away from the deterministic one
+    let first = noisy(0.2, 0.2, 1).positions_vec();
+    let second = noisy(0.2, 0.2, 2).positions_vec();
+    assert_ne!(first, second);
+    for run in [first, second] {
+        let variance = run
+            .iter()
+            .zip(deterministic.positions_vec())
+            .map(|(x, y)| (x - y).powi(2))
+            .sum::<f64>()
+            / run.len() as f64;
+        assert!(variance > 1e-4, "{}", variance);
+    }
+}
+
+#[test]
+fn a_3d_octahedron_collapses_symmetrically() {
+    let mut positions = Vec::new();
+    for axis in 0..3 {
+        for sign in [2.0, -2.0] {
+            let mut corner = [0.0; 3];
+            corner[axis] = sign;
+            positions.extend(corner);
+        }
+    }
+    let mut system = Swarmalator::new_3d(
+        6,
+        positions,
+        vec![0.0; 6],
+        vec![0.0; 6],
+        0.0,
+        0.5,
+        None,
+        None,
+    )
+    .unwrap();
+
+    system.step_many(100, 0.05).unwrap();
+
+    let positions = system.positions_vec();
+    let radii: Vec<f64> = positions
+        .chunks(3)
+        .map(|p| p.iter().map(|x| x * x).sum::<f64>().sqrt())
+        .collect();
+    assert!(radii[0] < 1.0, "{:?}", radii);
+    assert!(
+        radii.iter().all(|r| (r - radii[0]).abs() < 1e-12),
+        "{:?}",
+        radii
+    );
+    assert!(system.centroid().iter().all(|x| x.abs() < 1e-12));
+}
+
+#[test]
+fn step_reports_flag_divergence() {
+    let mut stable = Swarmalator::random(20, 7, 1.0, 0.5, 0.2).unwrap();
+    for _ in 0..20 {
+        let report = stable.update_with_report(0.05).unwrap();
+        assert!(!report.diverged);
+        assert!(report.max_velocity.is_finite());
+    }
+
+    // Phases advance by about `omega * dt`, which overflows
+    let mut unstable = Swarmalator::random(20, 7, 1.0, 0.5, 5.0).unwrap();
+    assert!(unstable.update_with_report(1e308).unwrap().diverged);
+}
+
+#[test]
+fn rk45_error_shrinks_with_the_tolerance() {
+    let system = || Swarmalator::random(5, 5, 1.0, 0.5, 0.2).unwrap();
+
+    let mut reference = system();
+    reference.set_integrator(IntegratorKind::Rk4);
+    reference.step_many(400, 0.005).unwrap();
+
+    let errors: Vec<f64> = [1e-3, 1e-6, 1e-9]
+        .iter()
+        .map(|&tolerance| {
+            let mut rk45 = system();
+            rk45.set_integrator(IntegratorKind::Rk45);
+            rk45.set_tolerance(tolerance).unwrap();
+            rk45.step_many(2, 1.0).unwrap();
+            max_position_error(&rk45, &reference)
+        })
+        .collect();
+
+    assert!(errors.windows(2).all(|w| w[1] < w[0]), "{:?}", errors);
+    assert!(errors[2] < 1e-7, "{:?}", errors);
+}
+
+#[test]
+fn rk45_handles_zero_and_negative_steps() {
+    let mut system = Swarmalator::random(10, 5, 1.0, 0.5, 0.2).unwrap();
+    system.set_integrator(IntegratorKind::Rk45);
+    system.set_tolerance(1e-9).unwrap();
+    let start = system.positions_vec();
+
+    system.update(0.0).unwrap();
+    assert_eq!(system.positions_vec(), start);
+    assert!(system.velocities_vec().iter().all(|v| v.is_finite()));
+
+    // Stepping back undoes the step up to the tolerance
+    system.update(0.5).unwrap();
+    assert!(system.positions_vec() != start);
+    system.update(-0.5).unwrap();
+    for (x, y) in system.positions_vec().iter().zip(&start) {
+        assert!((x - y).abs() < 1e-6, "{} vs {}", x, y);
+    }
+}
+
+#[test]
+fn a_diverging_step_is_undone_under_the_error_policy() {
+    let mut system = Swarmalator::random(20, 7, 2.0, 0.5, 5.0).unwrap();
+    system.set_nan_policy(NanPolicy::Error);
+    system.set_noise(0.1, 0.1, 9).unwrap();
+    system.set_knn(3, 2).unwrap();
+    system
+        .schedule_parameter(
+            ScheduledParameter::K,
+            vec![0.0, 1.0, 1.0, 3.0],
+            Interpolation::Linear,
+        )
+        .unwrap();
+    system.update(0.01).unwrap();
+    let before = system.to_bytes();
+    let rendered = system.render_positions(0.0).unwrap();
+
+    // Phases advance by about `omega * dt`, which overflows
+    assert!(system.update(1e308).is_err());
+    assert_eq!(system.to_bytes(), before);
+    assert_eq!(system.render_positions(0.0).unwrap(), rendered);
+
+    // So a retry goes exactly as if the failed step had never been tried
+    let mut untouched = Swarmalator::from_bytes(before).unwrap();
+    system.update(0.01).unwrap();
+    untouched.update(0.01).unwrap();
+    assert_eq!(system.to_bytes(), untouched.to_bytes());
+}
+
+#[test]
+fn the_reset_policy_puts_diverging_agents_back() {
+    let mut system = Swarmalator::random(20, 7, 1.0, 0.5, 5.0).unwrap();
+    system.set_nan_policy(NanPolicy::Reset);
+
+    system.update(1e308).unwrap();
+
+    assert!(system.positions_vec().iter().all(|x| x.is_finite()));
+    assert!(system.phases_vec().iter().all(|x| x.is_finite()));
+}
+
+#[test]
+fn substeps_count_as_a_single_update() {
+    let mut whole = Swarmalator::random(20, 7, 1.0, 0.5, 0.2).unwrap();
+    let mut split = Swarmalator::random(20, 7, 1.0, 0.5, 0.2).unwrap();
+    split.set_auto_timestep(Some(1e-3)).unwrap();
+    split.start_recording(1, None).unwrap();
+    split.start_averaging();
+    let start = split.positions_vec();
+
+    whole.update(0.1).unwrap();
+    split.update(0.1).unwrap();
+
+    assert_ne!(whole.positions_vec(), split.positions_vec());
+    assert_eq!(split.recorded_frames(), 1);
+    assert_eq!(split.render_positions(0.0).unwrap(), start);
+    // A single sample has no variance yet
+    assert!(split.order_susceptibility().is_nan());
+}
+
+#[test]
+fn softening_and_the_speed_limit_tame_close_encounters() {
+    let close = || {
+        Swarmalator::new(
+            2,
+            vec![0.0, 0.0, 1e-3, 0.0],
+            vec![0.0; 2],
+            vec![0.0; 2],
+            1.0,
+            0.5,
+            None,
+            None,
+        )
+        .unwrap()
+    };
+
+    let mut plain = close();
+    let mut softened = close();
+    softened.set_softening(0.1).unwrap();
+    let mut limited = close();
+    limited.set_max_speed(Some(0.5)).unwrap();
+
+    let plain_speed = plain.update_with_report(1e-4).unwrap().max_velocity;
+    let softened_speed = softened.update_with_report(1e-4).unwrap().max_velocity;
+    let limited_speed = limited.update_with_report(1e-4).unwrap().max_velocity;
+    assert!(
+        softened_speed < plain_speed / 10.0,
+        "{} vs {}",
+        softened_speed,
+        plain_speed
+    );
+    assert!(limited_speed <= 0.5 + 1e-12, "{}", limited_speed);
+}
@@ -0,0 +1,78 @@
+//! Seeded construction, which must give the same system for the same seed on every
+//! platform and build.
+
+use wasm_swarmalators::{FrequencyDistribution, InitConfig, PositionDistribution, Swarmalator};
+
+#[test]
+fn random_systems_are_pinned_to_their_seed() {
+    let system = Swarmalator::random(3, 42, 1.0, 0.5, 0.3).unwrap();
+
+    assert_eq!(
+        system.positions_vec(),
+        vec![
+            0.053114818005547626,
+            0.08545041980628776,
+            0.27293019828778986,
+            -0.18819648353844665,
+            -0.9313143640900878,
+            -0.1700863076292798
+        ]
+    );
+    assert_eq!(
+        system.phases_vec(),
+        vec![4.633374329433223, 5.3360052031752625, 0.8248495875596836]
+    );
+    assert_ne!(
+        Swarmalator::random(3, 43, 1.0, 0.5, 0.3)
+            .unwrap()
+            .positions_vec(),
+        system.positions_vec()
+    );
+}
+
+#[test]
+fn configured_random_systems_are_pinned_to_their_seed() {
+    let mut config = InitConfig::new();
+    config.dim = 3;
+    config.positions = PositionDistribution::Disk;
+    config.frequencies = FrequencyDistribution::Normal;
+    config.frequency_width = 0.5;
+    config.chirality = 1.0;
+
+    let system = Swarmalator::random_with_config(2, 42, 1.0, 0.5, &config).unwrap();
+
+    assert_eq!(
+        system.positions_vec(),
+        vec![
+            0.053114818005547626,
+            0.08545041980628776,
+            0.27293019828778986,
+            -0.18819648353844665,
+            -0.9313143640900878,
+            -0.1700863076292798
+        ]
+    );
+    assert_eq!(
+        system.phases_vec(),
+        vec![4.633374329433223, 5.3360052031752625]
+    );
+    let state: serde_json::Value = serde_json::from_str(&system.save_state()).unwrap();
+    assert_eq!(state["natural_frequencies"][0], -0.6550199621436116);
+    assert_eq!(state["chiral"][1], -1.0);
+}
+
+#[test]
+fn random_3d_systems_fill_the_cube() {
+    let system = Swarmalator::random_3d(50, 42, 1.0, 0.5, 0.3).unwrap();
+    let positions = system.positions_vec();
+
+    assert_eq!(positions.len(), 150);
+    assert!(positions.iter().all(|x| (-1.0..1.0).contains(x)));
+    assert!(positions.chunks(3).any(|p| p[2].abs() > 0.5));
+    assert_eq!(
+        Swarmalator::random_3d(50, 42, 1.0, 0.5, 0.3)
+            .unwrap()
+            .positions_vec(),
+        positions
+    );
+}
@@ -0,0 +1,251 @@
+//! Saving and restoring states and configurations, and resizing the system.
+
+use wasm_swarmalators::{
+    Boundary, CollisionKind, IntegratorKind, Interpolation, NanPolicy, ScheduledParameter,
+    Swarmalator,
+};
+
+/// Fields of the first saved state format, before any of the later options.
+const FIRST_FORMAT: [&str; 25] = [
+    "agents",
+    "A",
+    "B",
+    "K",
+    "J",
+    "target",
+    "natural_frequencies",
+    "chiral",
+    "velocities",
+    "phases",
+    "delta_phases",
+    "positions",
+    "grid_enabled",
+    "frequency_adaptation",
+    "frequency_gradient",
+    "fix_center_of_mass",
+    "phase_target",
+    "phase_targets",
+    "phase_repulsion_coupling",
+    "phase_harmonic",
+    "integration_scheme",
+    "min_distance",
+    "integrator",
+    "cutoff",
+    "averaging",
+];
+
+#[test]
+fn states_in_the_first_format_still_load() {
+    let mut original = Swarmalator::random(30, 2, 1.0, 0.5, 0.1).unwrap();
+    original.set_periodic(Some(4.0)).unwrap();
+
+    let saved: serde_json::Value = serde_json::from_slice(&original.to_bytes()).unwrap();
+    let mut first = serde_json::Map::new();
+    for field in FIRST_FORMAT {
+        first.insert(field.to_string(), saved[field].clone());
+    }
+    first.insert("periodic".to_string(), 4.0.into());
+
+    let mut restored = Swarmalator::from_bytes(serde_json::to_vec(&first).unwrap()).unwrap();
+    original.update(0.05).unwrap();
+    restored.update(0.05).unwrap();
+
+    assert_eq!(original.positions_vec(), restored.positions_vec());
+    assert_eq!(original.phases_vec(), restored.phases_vec());
+}
+
+#[test]
+fn a_restored_state_steps_like_the_original() {
+    let mut original = Swarmalator::random(40, 8, 1.0, 0.5, 0.3).unwrap();
+    original.set_target(vec![0.3, -0.2]).unwrap();
+    original.step_many(5, 0.05).unwrap();
+
+    let mut restored = Swarmalator::from_bytes(original.to_bytes()).unwrap();
+    assert_eq!(original.to_bytes(), restored.to_bytes());
+
+    original.update(0.05).unwrap();
+    restored.update(0.05).unwrap();
+    assert_eq!(original.to_bytes(), restored.to_bytes());
+}
+
+#[test]
+fn malformed_states_are_rejected() {
+    assert!(Swarmalator::from_bytes(b"not a state".to_vec()).is_err());
+    assert!(Swarmalator::from_bytes(vec![0xff, 0xfe]).is_err());
+    assert!(Swarmalator::from_bytes(b"{\"agents\": 3}".to_vec()).is_err());
+}
+
+#[test]
+fn merged_systems_keep_every_agent() {
+    let mut system = Swarmalator::new(
+        2,
+        vec![0.0, 0.0, 1.0, 0.0],
+        vec![0.0, 1.0],
+        vec![1.0, -1.0],
+        1.0,
+        0.5,
+        Some(vec![0.5, 0.5]),
+        None,
+    )
+    .unwrap();
+    let other = Swarmalator::new(
+        1,
+        vec![0.5, 2.0],
+        vec![3.0],
+        vec![1.0],
+        1.0,
+        0.5,
+        None,
+        None,
+    )
+    .unwrap();
+
+    system.merge(&other).unwrap();
+
+    assert_eq!(system.positions_vec(), vec![0.0, 0.0, 1.0, 0.0, 0.5, 2.0]);
+    assert_eq!(system.phases_vec(), vec![0.0, 1.0, 3.0]);
+    assert_eq!(system.velocities_vec(), vec![0.0; 6]);
+
+    // The merged system steps as one, with the new agent joining in
+    system.update(0.05).unwrap();
+    let positions = system.positions_vec();
+    assert!(positions.iter().all(|x| x.is_finite()));
+    assert_ne!(&positions[4..], &[0.5, 2.0]);
+}
+
+#[test]
+fn config_lists_the_parameters_in_order() {
+    let mut system = Swarmalator::new(
+        3,
+        vec![0.0; 6],
+        vec![0.0; 3],
+        vec![0.0; 3],
+        1.0,
+        0.5,
+        None,
+        None,
+    )
+    .unwrap();
+    system.set_K(1.5).unwrap();
+    system.set_J(-0.5).unwrap();
+    system.set_target(vec![0.0, 0.0]).unwrap();
+    system.set_phase_lag(0.25).unwrap();
+    system.set_softening(0.01).unwrap();
+
+    assert_eq!(
+        system.config(),
+        vec![3.0, 1.0, 1.0, 1.5, -0.5, 0.0, 1.0, 1e-6, 2.0, 0.25, 0.01]
+    );
+}
+
+#[test]
+fn agents_can_be_added_and_removed_between_steps() {
+    let mut system = Swarmalator::random(5, 3, 1.0, 0.5, 0.1).unwrap();
+    system.set_target(vec![0.1, 0.1]).unwrap();
+    system.add_agent(0.5, 0.5, 1.0, 0.2).unwrap();
+    system.add_agent(-0.5, 0.25, 2.0, -0.1).unwrap();
+    system.add_agent(0.0, -0.75, 3.0, 0.0).unwrap();
+    system.remove_agent(6).unwrap();
+
+    assert_eq!(system.positions_vec().len(), 14);
+    assert_eq!(system.positions_vec()[12..], [0.0, -0.75]);
+    assert_eq!(system.phases_vec()[6], 3.0);
+
+    system.step_many(10, 0.05).unwrap();
+    assert_eq!(system.positions_vec().len(), 14);
+    assert_eq!(system.velocities_vec().len(), 14);
+    assert!(system.positions_vec().iter().all(|x| x.is_finite()));
+}
+
+#[test]
+fn removing_a_missing_agent_fails() {
+    let mut system = Swarmalator::random(5, 3, 1.0, 0.5, 0.1).unwrap();
+    assert!(system.remove_agent(5).is_err());
+    assert_eq!(system.agents(), 5);
+}
+
+/// A system with most of its parameters away from their defaults.
+fn configured() -> Swarmalator {
+    let mut system = Swarmalator::random(20, 9, 0.8, -0.3, 0.2).unwrap();
+    system.set_A(1.2).unwrap();
+    system.set_B(0.7).unwrap();
+    system.set_phase_lag(0.2).unwrap();
+    system.set_boundary(Boundary::Reflective, 3.0, 0.0).unwrap();
+    system.set_noise(0.01, 0.02, 5).unwrap();
+    system.set_integrator(IntegratorKind::Rk45);
+    system.set_tolerance(1e-5).unwrap();
+    system.set_cutoff(Some(1.5)).unwrap();
+    system.set_far_field(true);
+    system
+        .set_collisions(Some(CollisionKind::Soft), 0.05, 2.0)
+        .unwrap();
+    system.set_max_speed(Some(2.0)).unwrap();
+    system.set_nan_policy(NanPolicy::Reset);
+    system
+        .schedule_parameter(
+            ScheduledParameter::K,
+            vec![0.0, -1.0, 1.0, 1.0],
+            Interpolation::Sinusoidal,
+        )
+        .unwrap();
+    system
+}
+
+#[test]
+fn configurations_round_trip_through_json() {
+    let mut original = configured();
+    let json = original.to_config_json();
+
+    // Applied to the same agents, the copy runs exactly like the original
+    let mut copy = Swarmalator::random(20, 9, 0.0, 0.0, 0.2).unwrap();
+    copy.apply_config_json(&json).unwrap();
+    assert_eq!(copy.to_config_json(), json);
+    original.step_many(20, 0.05).unwrap();
+    copy.step_many(20, 0.05).unwrap();
+    assert_eq!(original.positions_vec(), copy.positions_vec());
+    assert_eq!(original.phases_vec(), copy.phases_vec());
+
+    let created = Swarmalator::from_config_json(&json, 3, 0.1).unwrap();
+    assert_eq!(created.to_config_json(), json);
+}
+
+#[test]
+fn unreadable_configurations_are_rejected() {
+    let mut system = Swarmalator::random(20, 9, 1.0, 0.5, 0.1).unwrap();
+    let original = system.to_config_json();
+    let config: serde_json::Value = serde_json::from_str(&original).unwrap();
+    let edited = |edit: &dyn Fn(&mut serde_json::Map<String, serde_json::Value>)| {
+        let mut config = config.clone();
+        edit(config.as_object_mut().unwrap());
+        config.to_string()
+    };
+
+    let rejected = [
+        edited(&|c| {
+            c.insert("version".into(), 2.into());
+        }),
+        edited(&|c| {
+            c.insert("version".into(), 0.into());
+        }),
+        edited(&|c| {
+            c.remove("version");
+        }),
+        edited(&|c| {
+            c.remove("A");
+        }),
+        edited(&|c| {
+            c.insert("coupling".into(), 1.0.into());
+        }),
+        edited(&|c| {
+            c.insert("agents".into(), 21.into());
+        }),
+        "not json".to_string(),
+    ];
+    for json in &rejected {
+        assert!(system.apply_config_json(json).is_err(), "{}", json);
+        assert_eq!(system.to_config_json(), original);
+    }
+    // A newer version is rejected before anything else
+    let newer = Swarmalator::from_config_json(&rejected[0], 1, 0.1);
+    assert!(newer.is_err_and(|e| e.to_string().contains("version 2")));
+}
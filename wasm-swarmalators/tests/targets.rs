@@ -0,0 +1,150 @@
+//! Targets, target shapes and how agents are assigned to them.
+
+use std::f64::consts::PI;
+
+use wasm_swarmalators::{AssignmentMethod, Swarmalator};
+
+/// Points evenly spaced around a circle of `radius`.
+fn circle(points: usize, radius: f64) -> Vec<f64> {
+    (0..points)
+        .flat_map(|p| {
+            let angle = 2.0 * PI * p as f64 / points as f64;
+            [radius * angle.cos(), radius * angle.sin()]
+        })
+        .collect()
+}
+
+/// Total squared distance from the agents to their assigned points.
+fn assignment_cost(system: &Swarmalator, shape: &[f64]) -> f64 {
+    let positions = system.positions_vec();
+    system
+        .target_shape_assignment()
+        .iter()
+        .enumerate()
+        .map(|(i, &p)| {
+            (positions[2 * i] - shape[2 * p]).powi(2)
+                + (positions[2 * i + 1] - shape[2 * p + 1]).powi(2)
+        })
+        .sum()
+}
+
+#[test]
+fn agents_already_on_the_shape_keep_their_points() {
+    let shape = circle(8, 1.0);
+    // Agent i sits on point (3i + 1) % 8
+    let positions = (0..8)
+        .flat_map(|i| {
+            let p = (3 * i + 1) % 8;
+            [shape[2 * p], shape[2 * p + 1]]
+        })
+        .collect();
+    let mut system = Swarmalator::new(
+        8,
+        positions,
+        vec![0.0; 8],
+        vec![0.0; 8],
+        0.0,
+        0.0,
+        None,
+        None,
+    )
+    .unwrap();
+
+    for method in [AssignmentMethod::Greedy, AssignmentMethod::Optimal] {
+        system.set_target_shape(shape.clone(), 1.0, method).unwrap();
+        let expected: Vec<usize> = (0..8).map(|i| (3 * i + 1) % 8).collect();
+        assert_eq!(system.target_shape_assignment(), expected);
+    }
+}
+
+#[test]
+fn the_optimal_assignment_is_never_worse_than_greedy() {
+    let shape = circle(40, 1.5);
+    let mut greedy = Swarmalator::random(40, 3, 1.0, 0.5, 0.1).unwrap();
+    let mut optimal = Swarmalator::random(40, 3, 1.0, 0.5, 0.1).unwrap();
+    greedy
+        .set_target_shape(shape.clone(), 1.0, AssignmentMethod::Greedy)
+        .unwrap();
+    optimal
+        .set_target_shape(shape.clone(), 1.0, AssignmentMethod::Optimal)
+        .unwrap();
+
+    for system in [&greedy, &optimal] {
+        let mut points = system.target_shape_assignment();
+        points.sort_unstable();
+        assert_eq!(points, (0..40).collect::<Vec<_>>());
+    }
+    assert!(assignment_cost(&optimal, &shape) <= assignment_cost(&greedy, &shape) + 1e-12);
+}
+
+#[test]
+fn points_are_shared_evenly_between_extra_agents() {
+    for method in [AssignmentMethod::Greedy, AssignmentMethod::Optimal] {
+        let mut system = Swarmalator::random(10, 4, 1.0, 0.5, 0.1).unwrap();
+        system
+            .set_target_shape(circle(3, 1.0), 1.0, method)
+            .unwrap();
+
+        let mut counts = [0; 3];
+        for p in system.target_shape_assignment() {
+            counts[p] += 1;
+        }
+        assert!(counts.iter().all(|&c| c == 3 || c == 4), "{:?}", counts);
+    }
+}
+
+#[test]
+fn the_optimal_assignment_is_limited_in_size() {
+    let mut system = Swarmalator::random(1001, 4, 1.0, 0.5, 0.1).unwrap();
+    let shape = circle(100, 1.0);
+    assert!(system
+        .set_target_shape(shape.clone(), 1.0, AssignmentMethod::Optimal)
+        .is_err());
+    system
+        .set_target_shape(shape, 1.0, AssignmentMethod::Greedy)
+        .unwrap();
+    assert_eq!(system.target_shape_assignment().len(), 1001);
+}
+
+#[test]
+fn added_agents_wait_for_the_next_step_to_be_reassigned() {
+    let shape = circle(12, 1.0);
+    let mut system = Swarmalator::random(11, 5, 1.0, 0.5, 0.1).unwrap();
+    system
+        .set_target_shape(shape.clone(), 2.0, AssignmentMethod::Optimal)
+        .unwrap();
+    let before = system.target_shape_assignment();
+
+    // Right next to point 3
+    system
+        .add_agent(shape[6] * 1.01, shape[7] * 1.01, 0.0, 0.0)
+        .unwrap();
+    let patched = system.target_shape_assignment();
+    assert_eq!(patched[..11], before[..]);
+    assert_eq!(patched[11], 3);
+
+    let mut reassigned = Swarmalator::from_bytes(system.to_bytes()).unwrap();
+    reassigned.reassign_target_shape();
+    system.update(0.01).unwrap();
+    assert_eq!(
+        system.target_shape_assignment(),
+        reassigned.target_shape_assignment()
+    );
+
+    system.remove_agent(0).unwrap();
+    assert_eq!(system.target_shape_assignment().len(), 11);
+    system.update(0.01).unwrap();
+}
+
+#[test]
+fn agents_are_pulled_onto_their_points() {
+    let shape = circle(20, 1.5);
+    let mut system = Swarmalator::random(20, 6, 0.0, 0.0, 0.0).unwrap();
+    system
+        .set_target_shape(shape.clone(), 5.0, AssignmentMethod::Greedy)
+        .unwrap();
+
+    let before = assignment_cost(&system, &shape);
+    system.step_many(100, 0.01).unwrap();
+    assert!(assignment_cost(&system, &shape) < before / 10.0);
+}
@@ -0,0 +1,74 @@
+//! Coupling restricted to an interaction graph.
+
+use wasm_swarmalators::Swarmalator;
+
+/// Largest difference between the positions of two systems.
+fn max_position_difference(a: &Swarmalator, b: &Swarmalator) -> f64 {
+    a.positions_vec()
+        .iter()
+        .zip(b.positions_vec())
+        .map(|(x, y)| (x - y).abs())
+        .fold(0.0, f64::max)
+}
+
+#[test]
+fn agents_without_edges_are_left_alone() {
+    let mut system = Swarmalator::random(4, 15, 1.0, 0.5, 0.0).unwrap();
+    let (positions, phases) = (system.positions_vec(), system.phases_vec());
+    system.set_topology_edges(vec![0, 1, 1, 0, 2, 2]).unwrap();
+
+    system.step_many(10, 0.05).unwrap();
+
+    // Agents 2 and 3 have no edges, and without natural frequencies don't move
+    let (moved, turned) = (system.positions_vec(), system.phases_vec());
+    assert_eq!(&moved[4..], &positions[4..]);
+    assert_eq!(&turned[2..], &phases[2..]);
+    assert_ne!(&moved[..4], &positions[..4]);
+    assert_ne!(&turned[..2], &phases[..2]);
+}
+
+#[test]
+fn complete_graphs_match_all_to_all_coupling() {
+    let agents = 12;
+    let mut all_to_all = Swarmalator::random(agents, 16, 1.0, 0.5, 0.2).unwrap();
+    let mut edges = Swarmalator::random(agents, 16, 1.0, 0.5, 0.2).unwrap();
+    let complete = (0..agents as u32)
+        .flat_map(|a| (a + 1..agents as u32).flat_map(move |b| [a, b]))
+        .collect();
+    edges.set_topology_edges(complete).unwrap();
+    let mut knn = Swarmalator::random(agents, 16, 1.0, 0.5, 0.2).unwrap();
+    knn.set_knn(agents - 1, 5).unwrap();
+
+    for system in [&mut all_to_all, &mut edges, &mut knn] {
+        system.step_many(50, 0.05).unwrap();
+    }
+
+    assert!(max_position_difference(&all_to_all, &edges) < 1e-9);
+    assert!(max_position_difference(&all_to_all, &knn) < 1e-9);
+}
+
+#[test]
+fn nearest_neighbours_follow_the_agents() {
+    let mut fixed = Swarmalator::random(30, 17, 1.0, 0.5, 0.2).unwrap();
+    fixed.set_knn(3, 1000).unwrap();
+    let mut refreshed = Swarmalator::random(30, 17, 1.0, 0.5, 0.2).unwrap();
+    refreshed.set_knn(3, 1).unwrap();
+
+    // The graph is built on the first step, so both start out the same
+    fixed.update(0.05).unwrap();
+    refreshed.update(0.05).unwrap();
+    assert_eq!(fixed.positions_vec(), refreshed.positions_vec());
+
+    fixed.step_many(100, 0.05).unwrap();
+    refreshed.step_many(100, 0.05).unwrap();
+    assert!(max_position_difference(&fixed, &refreshed) > 1e-3);
+}
+
+#[test]
+fn malformed_topologies_are_rejected() {
+    let mut system = Swarmalator::random(4, 18, 1.0, 0.5, 0.0).unwrap();
+    assert!(system.set_topology_edges(vec![0, 1, 2]).is_err());
+    assert!(system.set_topology_edges(vec![0, 4]).is_err());
+    assert!(system.set_knn(0, 1).is_err());
+    assert!(system.set_knn(2, 0).is_err());
+}